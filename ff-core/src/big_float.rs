@@ -0,0 +1,416 @@
+//! Arbitrary-precision binary floating-point.
+//!
+//! `BigRational` is exact, but its denominators grow without bound as the iteration proceeds.
+//! `BigFloat` instead rounds every operation to nearest-even at a fixed number of significant
+//! bits, chosen per request (e.g. `BigFloat<256>`), giving a ground truth at any zoom depth.
+
+use std::cmp::Ordering;
+use std::ops::{Add, Div, Mul, Neg, Sub};
+
+use num::{BigInt, BigRational, BigUint, Integer, One, Signed, ToPrimitive, Zero};
+
+use crate::numeric::{binary_parts, ldexp, FromRational};
+
+/// Precision for a `BigFloat`.
+///
+/// Parsed from a format name like `BigFloat<256>`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct BigFloatFormat {
+    /// Significant bits.
+    pub precision: u64,
+}
+
+impl BigFloatFormat {
+    /// Largest supported precision, in bits.
+    pub const MAX_PRECISION: u64 = 1 << 16;
+
+    pub fn new(precision: u64) -> Result<Self, String> {
+        if !(2..=Self::MAX_PRECISION).contains(&precision) {
+            return Err(format!(
+                "BigFloat precision must be between 2 and {} bits, got {}",
+                Self::MAX_PRECISION,
+                precision
+            ));
+        }
+        Ok(BigFloatFormat { precision })
+    }
+}
+
+impl std::str::FromStr for BigFloatFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let arg = s
+            .trim()
+            .strip_prefix("BigFloat<")
+            .and_then(|rest| rest.strip_suffix('>'))
+            .ok_or_else(|| format!("'{}' is not of the form BigFloat<P>", s))?;
+        let precision = arg
+            .trim()
+            .parse::<u64>()
+            .map_err(|err| format!("invalid precision '{}' in '{}': {}", arg, s, err))?;
+        BigFloatFormat::new(precision)
+    }
+}
+
+impl std::fmt::Display for BigFloatFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "BigFloat<{}>", self.precision)
+    }
+}
+
+/// A binary floating-point number with arbitrary precision and (practically) unbounded exponent.
+///
+/// As with `DynMaskedFloat`, each value carries its format. Values without a format-
+/// the constants produced by `FractalNumber::from_i32`- are exact, and take on the precision of
+/// whatever they're combined with.
+#[derive(Clone, Debug)]
+pub struct BigFloat {
+    /// The value is `mantissa * 2^exponent`. The mantissa is odd, or zero.
+    mantissa: BigInt,
+    exponent: i64,
+    format: Option<BigFloatFormat>,
+}
+
+/// Precision for dividing two unformatted values, which may not have an exact quotient.
+const UNFORMATTED_DIVISION: BigFloatFormat = BigFloatFormat { precision: 128 };
+
+impl BigFloat {
+    /// Creates an unformatted (exact) value.
+    pub fn from_i64(i: i64) -> Self {
+        Self::exact(i.into(), 0)
+    }
+
+    pub fn zero() -> Self {
+        Self::exact(BigInt::zero(), 0)
+    }
+
+    /// Rounds a rational to the given format.
+    pub fn from_rational(r: &BigRational, format: BigFloatFormat) -> Self {
+        match binary_parts(r, format.precision + 2) {
+            None => Self::zero().with_format(format),
+            Some(parts) => {
+                let mantissa: BigInt = parts.significand.into();
+                let mantissa = if parts.negative { -mantissa } else { mantissa };
+                Self::rounded(mantissa, parts.exponent, Some(format), parts.sticky)
+            }
+        }
+    }
+
+    /// The exact rational value.
+    pub fn to_rational(&self) -> BigRational {
+        if self.exponent >= 0 {
+            BigRational::from_integer(&self.mantissa << self.exponent as u64)
+        } else {
+            BigRational::new(
+                self.mantissa.clone(),
+                BigInt::one() << (-self.exponent) as u64,
+            )
+        }
+    }
+
+    /// Rounds this value to the given format.
+    pub fn with_format(self, format: BigFloatFormat) -> Self {
+        Self::rounded(self.mantissa, self.exponent, Some(format), false)
+    }
+
+    pub fn format(&self) -> Option<BigFloatFormat> {
+        self.format
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.mantissa.is_zero()
+    }
+
+    pub fn to_f64(&self) -> f64 {
+        // Keep 64 bits, with any below folded into the last as a sticky bit;
+        // converting that rounds correctly (up to double-rounding in the subnormal range).
+        let magnitude = self.mantissa.magnitude();
+        let shift = magnitude.bits().saturating_sub(64);
+        let mut top = (magnitude >> shift).to_u64().unwrap_or(u64::MAX);
+        if shift > 0 {
+            top |= 1;
+        }
+        let top = if self.mantissa.is_negative() {
+            -(top as f64)
+        } else {
+            top as f64
+        };
+        ldexp(top, self.exponent + shift as i64)
+    }
+
+    fn exact(mantissa: BigInt, exponent: i64) -> Self {
+        Self::rounded(mantissa, exponent, None, false)
+    }
+
+    /// Creates a value from `mantissa * 2^exponent`, rounded to the format (if any).
+    /// `sticky` indicates that the true value is slightly larger in magnitude.
+    fn rounded(
+        mut mantissa: BigInt,
+        mut exponent: i64,
+        format: Option<BigFloatFormat>,
+        sticky: bool,
+    ) -> Self {
+        if let Some(BigFloatFormat { precision }) = format {
+            let bits = mantissa.bits();
+            if bits > precision {
+                let shift = bits - precision;
+                let magnitude = mantissa.magnitude();
+                let mut kept: BigUint = magnitude >> shift;
+                let guard = magnitude.bit(shift - 1);
+                let lower = sticky || magnitude.trailing_zeros().unwrap_or(0) < shift - 1;
+                if guard && (lower || kept.bit(0)) {
+                    kept += 1u32;
+                }
+                let kept: BigInt = kept.into();
+                mantissa = if mantissa.is_negative() { -kept } else { kept };
+                exponent += shift as i64;
+            }
+        }
+        // Normalize, so that equal values have equal representations:
+        match mantissa.trailing_zeros() {
+            None => exponent = 0,
+            Some(zeros) if zeros > 0 => {
+                mantissa >>= zeros;
+                exponent += zeros as i64;
+            }
+            _ => (),
+        }
+        BigFloat {
+            mantissa,
+            exponent,
+            format,
+        }
+    }
+
+    /// Exponent just above the leading bit: |self| < 2^top.
+    fn top(&self) -> i64 {
+        self.exponent + self.mantissa.bits() as i64
+    }
+
+    fn common_format(&self, other: &Self) -> Option<BigFloatFormat> {
+        match (self.format, other.format) {
+            (Some(a), Some(b)) if b.precision > a.precision => Some(b),
+            (a, b) => a.or(b),
+        }
+    }
+
+    /// Both mantissas, shifted to the smaller of the two exponents.
+    fn aligned(&self, other: &Self) -> (BigInt, BigInt, i64) {
+        let exponent = std::cmp::min(self.exponent, other.exponent);
+        (
+            &self.mantissa << (self.exponent - exponent) as u64,
+            &other.mantissa << (other.exponent - exponent) as u64,
+            exponent,
+        )
+    }
+}
+
+impl From<BigFloat> for f64 {
+    fn from(v: BigFloat) -> Self {
+        v.to_f64()
+    }
+}
+
+impl Neg for BigFloat {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        BigFloat {
+            mantissa: -self.mantissa,
+            ..self
+        }
+    }
+}
+
+impl Add for BigFloat {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        let format = self.common_format(&other);
+        if let Some(BigFloatFormat { precision }) = format {
+            // If one operand is far below the last place of the other, it can't affect the
+            // rounded result; skip the (potentially very wide) aligned sum.
+            let (big, small) = if self.top() >= other.top() {
+                (&self, &other)
+            } else {
+                (&other, &self)
+            };
+            if !big.is_zero()
+                && big.mantissa.bits() <= precision
+                && small.top() < big.top() - precision as i64 - 2
+            {
+                return Self::rounded(big.mantissa.clone(), big.exponent, format, false);
+            }
+        }
+        let (a, b, exponent) = self.aligned(&other);
+        Self::rounded(a + b, exponent, format, false)
+    }
+}
+
+impl Sub for BigFloat {
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self {
+        self + -other
+    }
+}
+
+impl Mul for BigFloat {
+    type Output = Self;
+
+    fn mul(self, other: Self) -> Self {
+        let format = self.common_format(&other);
+        Self::rounded(
+            self.mantissa * other.mantissa,
+            self.exponent + other.exponent,
+            format,
+            false,
+        )
+    }
+}
+
+impl Div for BigFloat {
+    type Output = Self;
+
+    /// Division by zero panics, as for BigRational.
+    fn div(self, other: Self) -> Self {
+        let format = self.common_format(&other).unwrap_or(UNFORMATTED_DIVISION);
+        if other.is_zero() {
+            panic!("BigFloat division by zero");
+        }
+        // Shift the dividend so the quotient has at least two more bits than needed:
+        let shift = std::cmp::max(
+            format.precision as i64 + 2 + other.mantissa.bits() as i64
+                - self.mantissa.bits() as i64,
+            0,
+        );
+        let dividend = &self.mantissa << shift as u64;
+        let (quotient, remainder) = dividend.div_rem(&other.mantissa);
+        Self::rounded(
+            quotient,
+            self.exponent - other.exponent - shift,
+            Some(format),
+            !remainder.is_zero(),
+        )
+    }
+}
+
+impl PartialEq for BigFloat {
+    fn eq(&self, other: &Self) -> bool {
+        // Representations are normalized:
+        self.mantissa == other.mantissa && self.exponent == other.exponent
+    }
+}
+
+impl PartialOrd for BigFloat {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        let sign = |v: &Self| v.mantissa.sign();
+        if sign(self) != sign(other) || self.is_zero() {
+            return sign(self).partial_cmp(&sign(other));
+        }
+        // Same sign, nonzero: compare magnitudes by leading bit first.
+        let magnitude = match self.top().cmp(&other.top()) {
+            Ordering::Equal => {
+                let (a, b, _) = self.aligned(other);
+                a.abs().cmp(&b.abs())
+            }
+            ord => ord,
+        };
+        Some(if self.mantissa.is_negative() {
+            magnitude.reverse()
+        } else {
+            magnitude
+        })
+    }
+}
+
+/// Converts dyadic rationals exactly; others need a precision, via `BigFloat::from_rational`.
+impl FromRational for BigFloat {
+    fn from_bigrational(r: &BigRational) -> Result<Self, String> {
+        let denom = r.denom();
+        let zeros = denom.trailing_zeros().unwrap_or(0);
+        if !(denom >> zeros).is_one() {
+            return Err(format!(
+                "{} has no exact binary representation; specify a precision",
+                r
+            ));
+        }
+        Ok(Self::exact(r.numer().clone(), -(zeros as i64)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn format(precision: u64) -> BigFloatFormat {
+        BigFloatFormat::new(precision).unwrap()
+    }
+
+    #[test]
+    fn test_parse_format() {
+        assert_eq!("BigFloat<256>".parse::<BigFloatFormat>(), Ok(format(256)));
+        assert_eq!(format(1024).to_string(), "BigFloat<1024>");
+        assert!("BigFloat<1>".parse::<BigFloatFormat>().is_err());
+        assert!("BigFloat<>".parse::<BigFloatFormat>().is_err());
+        assert!("f64".parse::<BigFloatFormat>().is_err());
+    }
+
+    #[test]
+    fn test_matches_f64() {
+        // At 53 bits, every operation should round exactly as f64 does.
+        let values = [1.0, -3.5, 0.1, 1.0 / 3.0, 1e10, -2.5e-7, 65_504.0];
+        let f = format(53);
+        let big = |v: f64| BigFloat::from_rational(&BigRational::from_float(v).unwrap(), f);
+        for a in values {
+            for b in values {
+                assert_eq!((big(a) + big(b)).to_f64(), a + b, "{a} + {b}");
+                assert_eq!((big(a) - big(b)).to_f64(), a - b, "{a} - {b}");
+                assert_eq!((big(a) * big(b)).to_f64(), a * b, "{a} * {b}");
+                assert_eq!((big(a) / big(b)).to_f64(), a / b, "{a} / {b}");
+                assert_eq!(big(a).partial_cmp(&big(b)), a.partial_cmp(&b));
+            }
+        }
+    }
+
+    #[test]
+    fn test_precision() {
+        let f = format(256);
+        let third = BigFloat::from_rational(&BigRational::new(1.into(), 3.into()), f);
+        let one = BigFloat::from_i64(1);
+        let tiny = BigFloat::from_rational(&BigRational::new(1.into(), BigInt::one() << 250u32), f);
+        assert!(one.clone() + tiny.clone() > one);
+        assert_eq!((one.clone() + tiny.clone()) - one.clone(), tiny);
+        // But past the precision, the sum rounds back:
+        let tinier = tiny.clone() * tiny;
+        assert_eq!(one.clone() + tinier, one);
+
+        // 1/3 * 3 is within half an ulp of 1:
+        let product = (third * BigFloat::from_i64(3)).to_rational();
+        let error = (product - BigRational::one()).abs();
+        assert!(error <= BigRational::new(1.into(), BigInt::one() << 256u32));
+    }
+
+    #[test]
+    fn test_round_to_nearest_even() {
+        let f = format(3);
+        // 9 = 0b1001 is a tie between 8 and 10; 11 between 10 and 12.
+        assert_eq!(BigFloat::from_i64(9).with_format(f).to_f64(), 8.0);
+        assert_eq!(BigFloat::from_i64(11).with_format(f).to_f64(), 12.0);
+        assert_eq!(BigFloat::from_i64(-11).with_format(f).to_f64(), -12.0);
+        // Sticky bits break the tie:
+        let r = BigRational::new(73.into(), 8.into()); // 9.125
+        assert_eq!(BigFloat::from_rational(&r, f).to_f64(), 10.0);
+    }
+
+    #[test]
+    fn test_from_rational() {
+        let r = BigRational::new((-5).into(), 16.into());
+        assert_eq!(BigFloat::from_bigrational(&r).unwrap().to_rational(), r);
+        assert!(BigFloat::from_bigrational(&BigRational::new(1.into(), 3.into())).is_err());
+        let zero = BigFloat::from_bigrational(&BigRational::zero()).unwrap();
+        assert!(zero.is_zero());
+        assert_eq!(zero, BigFloat::zero());
+    }
+}
@@ -0,0 +1,355 @@
+//! Conformance of every format's `FromRational` to correct rounding: each rational converts to the
+//! nearest value of the format, with ties to even, rounded once from the exact value.
+//!
+//! The narrow formats are checked exhaustively, from their bit patterns. Every format in the
+//! registry is also checked in windows around a few points and near the ends of its range, over
+//! the values found by `introspect::representable`.
+//!
+//! Between each pair of adjacent values, a rational just below the midpoint must round down and
+//! one just above it must round up. Adjacent values alternate between even and odd, so with ties
+//! to even, each value wins the ties on both sides of it or neither.
+
+use std::ops::Range;
+
+use fixed::types::{I11F5, I13F3, I15F1, I8F8};
+use num::{BigInt, BigRational, One, Signed, Zero};
+
+use crate::introspect;
+use crate::number::{FractalNumber, NumberVisitor};
+use crate::posit::Posit;
+use crate::registry::{self, Evaluation, Family, Format};
+use crate::small_float::{BFloat16, Binary16, Fp8E4M3, Fp8E5M2};
+use crate::takum::{Linear, Takum};
+
+/// Values enumerated in each window.
+const LIMIT: usize = 64;
+
+/// Converts a rational into a format, and back.
+type Round = fn(&BigRational) -> Option<BigRational>;
+
+fn round<N: FractalNumber>(r: &BigRational) -> Option<BigRational> {
+    N::from_bigrational(r).ok()?.to_rational()
+}
+
+struct Rounder;
+
+impl NumberVisitor for Rounder {
+    type Output = Round;
+
+    fn visit<N: FractalNumber + Send + Sync>(self) -> Self::Output {
+        round::<N>
+    }
+}
+
+/// How a format breaks ties.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Ties {
+    Even,
+    /// Slash formats round to the nearest fraction; ties go either way.
+    Either,
+}
+
+/// Why a format isn't checked, if it isn't.
+fn exclusion(format: &Format) -> Option<&'static str> {
+    match format.family {
+        _ if format.evaluation == Evaluation::Block => Some("block formats share a scale"),
+        Family::Lns => Some("values are irrational; conversion rounds the logarithm"),
+        Family::Takum if !format.name.starts_with("Linear") => {
+            Some("values are irrational; conversion rounds the logarithm")
+        }
+        Family::MultiDouble => Some("no fixed set of values; each component is rounded once"),
+        Family::Interval => Some("converts to an enclosing interval; endpoints are checked"),
+        _ => None,
+    }
+}
+
+/// Whether the format saturates at its smallest value, rather than rounding to zero.
+fn tapered(format: &Format) -> bool {
+    matches!(format.family, Family::Posit | Family::Takum)
+}
+
+fn ties(format: &Format) -> Ties {
+    match format.family {
+        Family::Slash => Ties::Either,
+        _ => Ties::Even,
+    }
+}
+
+/// 2^k.
+fn power(k: i64) -> BigRational {
+    let magnitude = BigInt::one() << k.unsigned_abs();
+    if k >= 0 {
+        BigRational::from_integer(magnitude)
+    } else {
+        BigRational::new(BigInt::one(), magnitude)
+    }
+}
+
+/// Checks that `round` takes every rational to the nearest of `values`- adjacent values of the
+/// format, in increasing order.
+fn check_nearest(format: &Format, round: Round, values: &[BigRational]) {
+    let name = format.name;
+    let two = BigRational::from_integer(2.into());
+    for v in values {
+        assert_eq!(round(v).as_ref(), Some(v), "{}: {} isn't a value", name, v);
+    }
+    // Whether each tie rounds up, to the larger value:
+    let mut up = Vec::new();
+    for pair in values.windows(2) {
+        let (a, b) = (&pair[0], &pair[1]);
+        let mid = (a + b) / &two;
+        let nudge = (b - a) / BigRational::from_integer(1024.into());
+        let (below, above) = match (a.is_zero(), b.is_zero()) {
+            // Tapered formats never round to zero.
+            (true, _) if tapered(format) => (b, b),
+            (_, true) if tapered(format) => (a, a),
+            _ => (a, b),
+        };
+        let rounded = round(&(&mid - &nudge));
+        assert_eq!(
+            rounded.as_ref(),
+            Some(below),
+            "{}: below between {} and {}",
+            name,
+            a,
+            b
+        );
+        let rounded = round(&(&mid + &nudge));
+        assert_eq!(
+            rounded.as_ref(),
+            Some(above),
+            "{}: above between {} and {}",
+            name,
+            a,
+            b
+        );
+        let tie = round(&mid);
+        assert!(
+            tie.as_ref() == Some(below) || tie.as_ref() == Some(above),
+            "{}: tie between {} and {} rounds to {:?}",
+            name,
+            a,
+            b,
+            tie
+        );
+        up.push(tie.as_ref() == Some(b));
+    }
+    if ties(format) == Ties::Even {
+        for i in 1..up.len() {
+            if values[i - 1..=i + 1].iter().any(Zero::is_zero) {
+                continue;
+            }
+            assert_eq!(
+                up[i - 1],
+                !up[i],
+                "{}: ties on either side of {} don't alternate",
+                name,
+                values[i]
+            );
+        }
+    }
+}
+
+/// Checks the named format over the values of the bit patterns. If there's a `range`, checks the
+/// values whose magnitude is in it, of each sign.
+fn check_exhaustive<N: FractalNumber>(
+    name: &str,
+    patterns: impl Iterator<Item = N>,
+    range: Option<Range<BigRational>>,
+) {
+    let mut values: Vec<BigRational> = patterns.filter_map(|v| v.to_rational()).collect();
+    values.sort();
+    values.dedup();
+    let format = registry::find(name).expect("registered");
+    let round = registry::visit(name, Rounder).expect("registered");
+    match range {
+        None => check_nearest(format, round, &values),
+        Some(range) => {
+            let (negative, positive): (Vec<_>, Vec<_>) = values
+                .into_iter()
+                .filter(|v| range.contains(&v.abs()))
+                .partition(|v| v.is_negative());
+            check_nearest(format, round, &negative);
+            check_nearest(format, round, &positive);
+        }
+    }
+}
+
+/// Magnitudes at which posit<n, es> has all its exponent bits: elsewhere, rounding on the bit
+/// string (as the standard specifies) isn't rounding to the nearest value.
+fn posit_range(n: i64, es: u32) -> Range<BigRational> {
+    let scale = (n - 2 - es as i64) << es;
+    power(-scale)..power(scale)
+}
+
+#[test]
+fn test_exhaustive_small_floats() {
+    check_exhaustive("FP8-E5M2", (0..1 << 8).map(Fp8E5M2::from_bits), None);
+    check_exhaustive("FP8-E4M3", (0..1 << 8).map(Fp8E4M3::from_bits), None);
+    // Conversions round the magnitude, so the 16-bit formats' positive values stand in for all.
+    check_exhaustive("binary16", (0..1 << 15).map(Binary16::from_bits), None);
+    check_exhaustive("bfloat16", (0..1 << 15).map(BFloat16::from_bits), None);
+}
+
+#[test]
+fn test_exhaustive_posits() {
+    check_exhaustive("P8", (0..=u8::MAX).map(softposit::P8::from_bits), None);
+    // As for the floats, the 16-bit posits' positive values stand in for all.
+    check_exhaustive(
+        "P16",
+        (0..1 << 15).map(softposit::P16::from_bits),
+        Some(posit_range(16, 1)),
+    );
+    type P8Es2 = Posit<8, 2>;
+    check_exhaustive(
+        "Posit<8,2>",
+        (0..1 << 8).map(P8Es2::from_bits),
+        Some(posit_range(8, 2)),
+    );
+    type P12Es1 = Posit<12, 1>;
+    check_exhaustive(
+        "Posit<12,1>",
+        (0..1 << 12).map(P12Es1::from_bits),
+        Some(posit_range(12, 1)),
+    );
+    type P16Es2 = Posit<16, 2>;
+    check_exhaustive(
+        "Posit<16,2>",
+        (0..1 << 15).map(P16Es2::from_bits),
+        Some(posit_range(16, 2)),
+    );
+}
+
+#[test]
+fn test_exhaustive_takums() {
+    // Past 2^±14, an 8-bit takum doesn't have room for all of its characteristic bits.
+    type Linear8 = Takum<8, Linear>;
+    let range = Some(power(-14)..power(14));
+    check_exhaustive("LinearTakum<8>", (0..1 << 8).map(Linear8::from_bits), range);
+    type Linear16 = Takum<16, Linear>;
+    check_exhaustive(
+        "LinearTakum<16>",
+        (0..1 << 15).map(Linear16::from_bits),
+        None,
+    );
+}
+
+#[test]
+fn test_exhaustive_fixed_point() {
+    // The values are evenly spaced, so check around zero and at the ends of the range.
+    fn check<F: FractalNumber>(name: &str, from_bits: fn(i16) -> F) {
+        check_exhaustive(name, (i16::MIN..i16::MIN + 1024).map(from_bits), None);
+        check_exhaustive(name, (-1024..1024).map(from_bits), None);
+        check_exhaustive(name, (i16::MAX - 1024..=i16::MAX).map(from_bits), None);
+    }
+    check("I8F8", I8F8::from_bits);
+    check("I11F5", I11F5::from_bits);
+    check("I13F3", I13F3::from_bits);
+    check("I15F1", I15F1::from_bits);
+}
+
+/// The values of the format in a window, `range(width)`: widened until there are a few of them,
+/// or narrowed until there are few enough to list.
+fn window(
+    name: &str,
+    mut width: BigRational,
+    range: impl Fn(&BigRational) -> Range<BigRational>,
+) -> Vec<BigRational> {
+    let four = BigRational::from_integer(4.into());
+    // The values in the widest window that had too few:
+    let mut few: Option<Vec<BigRational>> = None;
+    for _ in 0..32 {
+        match introspect::representable(name, &range(&width), LIMIT) {
+            Ok(values) if values.len() >= 3 => return values,
+            Ok(values) => {
+                few = Some(values);
+                width *= &four;
+            }
+            Err(err) if err.starts_with("more than") && few.is_none() => width /= &four,
+            // Too many after too few, or out of range after widening:
+            Err(_) if few.is_some() => break,
+            Err(err) => panic!("{}: {}", name, err),
+        }
+    }
+    few.unwrap_or_default()
+}
+
+/// Checks the registry's formats in the families in windows around a few points, and near the
+/// ends of their range.
+fn check_windows(families: &[Family]) {
+    let rational = |n: i64, d: i64| BigRational::new(n.into(), d.into());
+    let centers = [
+        rational(1, 1),
+        rational(-1, 3),
+        rational(22, 7),
+        rational(-5, 64),
+        rational(100_003, 7),
+    ];
+    let four = BigRational::from_integer(4.into());
+    let formats = registry::FORMATS
+        .iter()
+        .filter(|format| families.contains(&format.family) && exclusion(format).is_none());
+    for format in formats {
+        let name = format.name;
+        let properties = introspect::properties(name).unwrap();
+        let epsilon = properties.epsilon.unwrap();
+        let (min, max) = (properties.min_positive, properties.max_positive);
+        // About how far apart the values are, near x:
+        let spacing = |x: &BigRational| match format.family {
+            Family::FixedPoint => min.clone().unwrap(),
+            // Away from the simplest fractions, slash formats' values are about epsilon^2 apart.
+            Family::Slash => &epsilon * &epsilon * x.abs(),
+            _ => &epsilon * x.abs(),
+        };
+        let round = registry::visit(name, Rounder).expect("registered");
+
+        for center in &centers {
+            let two = BigRational::from_integer(2.into());
+            if max.as_ref().is_some_and(|max| center.abs() * &two > *max) {
+                continue;
+            }
+            let values = window(name, spacing(center) * &four, |w| center - w..center + w);
+            check_nearest(format, round, &values);
+        }
+
+        // Tapered formats and masked floats don't round to nearest at the ends of their range.
+        // (Nor is it quick to probe the 128-bit formats', with values of hundreds of bits or more.)
+        let ends = matches!(
+            format.family,
+            Family::BinaryFloat | Family::DecimalFloat | Family::FixedPoint
+        );
+        if ends && format.bits <= 64 {
+            let min = min.clone().unwrap();
+            let values = window(name, &min * &four, |w| -w.clone()..w.clone());
+            check_nearest(format, round, &values);
+            let max = max.unwrap();
+            let values = window(name, spacing(&max) * &four, |w| &max - w..max.clone());
+            check_nearest(format, round, &values);
+        }
+    }
+}
+
+#[test]
+fn test_windows_floats() {
+    check_windows(&[Family::BinaryFloat, Family::MaskedFloat]);
+}
+
+#[test]
+fn test_windows_decimals() {
+    check_windows(&[Family::DecimalFloat]);
+}
+
+#[test]
+fn test_windows_tapered() {
+    check_windows(&[Family::Posit, Family::Takum]);
+}
+
+#[test]
+fn test_windows_fixed_point() {
+    check_windows(&[Family::FixedPoint]);
+}
+
+#[test]
+fn test_windows_slash() {
+    check_windows(&[Family::Slash]);
+}
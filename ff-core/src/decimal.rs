@@ -0,0 +1,596 @@
+//! IEEE 754 decimal floating-point: decimal32, decimal64 and decimal128.
+//!
+//! | Format     | Digits | emax |
+//! |------------|--------|------|
+//! | decimal32  | 7      | 96   |
+//! | decimal64  | 16     | 384  |
+//! | decimal128 | 34     | 6144 |
+//!
+//! Values are `coefficient * 10^exponent`, with at most `DIGITS` digits in the coefficient.
+//! Every operation is rounded once, to nearest with ties to even, with gradual underflow and
+//! overflow to infinity.
+//!
+//! This models the values, not an encoding (BID or DPD): values are kept with trailing zeros
+//! stripped from the coefficient, rather than with the IEEE "preferred exponent", so members of
+//! a cohort (e.g. 1.0 and 1.00) are not distinguished.
+
+use std::cmp::Ordering;
+use std::f64::consts::LOG10_2;
+use std::fmt::{Debug, Display};
+use std::marker::PhantomData;
+use std::ops::{Add, Div, Mul, Neg, Sub};
+
+use num::{BigInt, BigRational, BigUint, Integer, Signed, ToPrimitive, Zero};
+
+use crate::numeric::FromRational;
+
+/// Parameters of a decimal floating-point format.
+pub trait DecimalFormat {
+    /// Precision, in decimal digits.
+    const DIGITS: u32;
+    /// The largest exponent of the leading digit.
+    const EMAX: i64;
+}
+
+/// A value in a decimal floating-point format.
+pub struct Decimal<F> {
+    negative: bool,
+    kind: Kind,
+    coefficient: u128,
+    exponent: i64,
+    format: PhantomData<F>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Kind {
+    Finite,
+    Infinite,
+    NaN,
+}
+
+macro_rules! decimal_format {
+    ($(#[$doc:meta])* $format:ident, $alias:ident, $digits:expr, $emax:expr) => {
+        $(#[$doc])*
+        #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+        pub struct $format;
+
+        impl DecimalFormat for $format {
+            const DIGITS: u32 = $digits;
+            const EMAX: i64 = $emax;
+        }
+
+        $(#[$doc])*
+        pub type $alias = Decimal<$format>;
+    };
+}
+
+decimal_format!(
+    /// IEEE 754 decimal32.
+    Decimal32Format, Decimal32, 7, 96
+);
+decimal_format!(
+    /// IEEE 754 decimal64.
+    Decimal64Format, Decimal64, 16, 384
+);
+decimal_format!(
+    /// IEEE 754 decimal128.
+    Decimal128Format, Decimal128, 34, 6144
+);
+
+/// Powers of ten that fit in a u128.
+const POW10: [u128; 39] = {
+    let mut table = [1u128; 39];
+    let mut i = 1;
+    while i < table.len() {
+        table[i] = table[i - 1] * 10;
+        i += 1;
+    }
+    table
+};
+
+/// The number of decimal digits in `c`; zero has none.
+fn digits(c: u128) -> i64 {
+    POW10.partition_point(|&p| p <= c) as i64
+}
+
+impl<F: DecimalFormat> Decimal<F> {
+    /// The exponent of the last digit of the smallest subnormal.
+    const QMIN: i64 = 2 - F::EMAX - F::DIGITS as i64;
+
+    fn special(negative: bool, kind: Kind) -> Self {
+        Decimal {
+            negative,
+            kind,
+            coefficient: 0,
+            exponent: 0,
+            format: PhantomData,
+        }
+    }
+
+    pub fn zero(negative: bool) -> Self {
+        Self::special(negative, Kind::Finite)
+    }
+
+    pub fn infinity(negative: bool) -> Self {
+        Self::special(negative, Kind::Infinite)
+    }
+
+    pub fn nan() -> Self {
+        Self::special(false, Kind::NaN)
+    }
+
+    pub fn is_nan(&self) -> bool {
+        self.kind == Kind::NaN
+    }
+
+    pub fn is_infinite(&self) -> bool {
+        self.kind == Kind::Infinite
+    }
+
+    fn is_zero(&self) -> bool {
+        self.kind == Kind::Finite && self.coefficient == 0
+    }
+
+    /// The coefficient and exponent of a finite value, with trailing zeros removed.
+    pub fn parts(&self) -> Option<(bool, u128, i64)> {
+        (self.kind == Kind::Finite).then_some((self.negative, self.coefficient, self.exponent))
+    }
+
+    /// Rounds `coefficient * 10^exponent` (plus something less than one unit of its last digit,
+    /// if sticky) to the format. If sticky, the coefficient must have at least DIGITS + 2 digits,
+    /// so that `sticky` only ever breaks ties.
+    fn round(negative: bool, coefficient: u128, exponent: i64, sticky: bool) -> Self {
+        // Drop digits past the precision, or past the last digit of the subnormals:
+        let drop = (digits(coefficient) - F::DIGITS as i64)
+            .max(Self::QMIN - exponent)
+            .max(0);
+        let (mut c, mut e) = if drop == 0 {
+            (coefficient, exponent)
+        } else if drop >= POW10.len() as i64 {
+            // Less than half a unit is left.
+            (0, exponent + drop)
+        } else {
+            let unit = POW10[drop as usize];
+            let (q, r) = (coefficient / unit, coefficient % unit);
+            let half = unit / 2;
+            let up = r > half || (r == half && (sticky || q % 2 == 1));
+            (q + up as u128, exponent + drop)
+        };
+        if c == POW10[F::DIGITS as usize] {
+            c /= 10;
+            e += 1;
+        }
+        if c == 0 {
+            return Self::zero(negative);
+        }
+        if e + digits(c) - 1 > F::EMAX {
+            return Self::infinity(negative);
+        }
+        while c % 10 == 0 {
+            c /= 10;
+            e += 1;
+        }
+        Decimal {
+            negative,
+            kind: Kind::Finite,
+            coefficient: c,
+            exponent: e,
+            format: PhantomData,
+        }
+    }
+
+    /// Rounds a coefficient of any size.
+    fn round_big(negative: bool, coefficient: BigUint, exponent: i64, sticky: bool) -> Self {
+        // Keep 36 or 37 digits: enough to round decimal128.
+        let excess = ((coefficient.bits() as f64 * LOG10_2).ceil() as i64 - 37).max(0);
+        let (q, r) = coefficient.div_rem(&BigUint::from(10u32).pow(excess as u32));
+        let q = q.to_u128().expect("coefficient reduced to 37 digits");
+        Self::round(negative, q, exponent + excess, sticky || !r.is_zero())
+    }
+
+    pub fn from_i32(i: i32) -> Self {
+        Self::round(i < 0, i.unsigned_abs() as u128, 0, false)
+    }
+
+    pub fn from_f64(f: f64) -> Self {
+        if f.is_nan() {
+            Self::nan()
+        } else if f.is_infinite() {
+            Self::infinity(f < 0.0)
+        } else if f == 0.0 {
+            Self::zero(f.is_sign_negative())
+        } else {
+            let r = BigRational::from_float(f).expect("finite");
+            Self::from_bigrational(&r).expect("nonzero")
+        }
+    }
+
+    /// The exact value, if finite.
+    pub fn to_rational(&self) -> Option<BigRational> {
+        let (negative, c, e) = self.parts()?;
+        let c = BigInt::from(c);
+        let ten = BigInt::from(10);
+        let r = if e >= 0 {
+            BigRational::from_integer(c * ten.pow(e as u32))
+        } else {
+            BigRational::new(c, ten.pow((-e) as u32))
+        };
+        Some(if negative { -r } else { r })
+    }
+
+    pub fn to_f64(&self) -> f64 {
+        match self.kind {
+            Kind::NaN => f64::NAN,
+            Kind::Infinite if self.negative => f64::NEG_INFINITY,
+            Kind::Infinite => f64::INFINITY,
+            // Parsing is correctly rounded.
+            Kind::Finite => self.to_string().parse().unwrap_or(f64::NAN),
+        }
+    }
+
+    /// Compares magnitudes of two finite or infinite values.
+    fn cmp_magnitude(&self, other: &Self) -> Ordering {
+        match (self.kind, other.kind) {
+            (Kind::Infinite, Kind::Infinite) => return Ordering::Equal,
+            (Kind::Infinite, _) => return Ordering::Greater,
+            (_, Kind::Infinite) => return Ordering::Less,
+            _ => (),
+        }
+        match (self.is_zero(), other.is_zero()) {
+            (true, true) => return Ordering::Equal,
+            (true, false) => return Ordering::Less,
+            (false, true) => return Ordering::Greater,
+            _ => (),
+        }
+        let (da, db) = (digits(self.coefficient), digits(other.coefficient));
+        (self.exponent + da)
+            .cmp(&(other.exponent + db))
+            .then_with(|| {
+                // Same leading digit position; align the coefficients.
+                if da < db {
+                    (self.coefficient * POW10[(db - da) as usize]).cmp(&other.coefficient)
+                } else {
+                    self.coefficient
+                        .cmp(&(other.coefficient * POW10[(da - db) as usize]))
+                }
+            })
+    }
+
+    /// Adds two finite, nonzero values.
+    fn sum(a: Self, b: Self) -> Self {
+        let (a, b) = if a.cmp_magnitude(&b) == Ordering::Less {
+            (b, a)
+        } else {
+            (a, b)
+        };
+        // Pad the larger to DIGITS + 3 digits, so the sum keeps at least DIGITS + 2.
+        let pad = F::DIGITS as i64 + 3 - digits(a.coefficient);
+        let x = a.coefficient * POW10[pad as usize];
+        let exponent = a.exponent - pad;
+        let shift = b.exponent - exponent;
+        let (y, sticky) = if shift >= 0 {
+            // b is no larger than a, so this fits.
+            (b.coefficient * POW10[shift as usize], false)
+        } else if -shift >= POW10.len() as i64 {
+            (0, true)
+        } else {
+            let unit = POW10[(-shift) as usize];
+            (b.coefficient / unit, b.coefficient % unit != 0)
+        };
+        let c = if a.negative == b.negative {
+            x + y
+        } else if sticky {
+            // The true value of y is a little larger than the shifted value.
+            x - y - 1
+        } else {
+            x - y
+        };
+        if c == 0 && !sticky {
+            // Exact cancellation is positive zero.
+            return Self::zero(false);
+        }
+        Self::round(a.negative, c, exponent, sticky)
+    }
+}
+
+impl<F> Clone for Decimal<F> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<F> Copy for Decimal<F> {}
+
+impl<F: DecimalFormat> Display for Decimal<F> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let sign = if self.negative { "-" } else { "" };
+        match self.kind {
+            Kind::NaN => write!(f, "NaN"),
+            Kind::Infinite => write!(f, "{}Infinity", sign),
+            Kind::Finite => write!(f, "{}{}E{}", sign, self.coefficient, self.exponent),
+        }
+    }
+}
+
+impl<F: DecimalFormat> Debug for Decimal<F> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Decimal({})", self)
+    }
+}
+
+impl<F: DecimalFormat> From<Decimal<F>> for f64 {
+    fn from(v: Decimal<F>) -> Self {
+        v.to_f64()
+    }
+}
+
+impl<F: DecimalFormat> PartialEq for Decimal<F> {
+    fn eq(&self, other: &Self) -> bool {
+        self.partial_cmp(other) == Some(Ordering::Equal)
+    }
+}
+
+impl<F: DecimalFormat> PartialOrd for Decimal<F> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        if self.is_nan() || other.is_nan() {
+            return None;
+        }
+        let sign = |v: &Self| match v {
+            _ if v.is_zero() => 0,
+            _ if v.negative => -1,
+            _ => 1,
+        };
+        Some(match sign(self).cmp(&sign(other)) {
+            Ordering::Equal if sign(self) < 0 => other.cmp_magnitude(self),
+            Ordering::Equal => self.cmp_magnitude(other),
+            ord => ord,
+        })
+    }
+}
+
+impl<F: DecimalFormat> Neg for Decimal<F> {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        Decimal {
+            negative: !self.negative,
+            ..self
+        }
+    }
+}
+
+impl<F: DecimalFormat> Add for Decimal<F> {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        match (self.kind, other.kind) {
+            (Kind::NaN, _) | (_, Kind::NaN) => Self::nan(),
+            (Kind::Infinite, Kind::Infinite) if self.negative != other.negative => Self::nan(),
+            (Kind::Infinite, _) => self,
+            (_, Kind::Infinite) => other,
+            _ if self.is_zero() && other.is_zero() => Self::zero(self.negative && other.negative),
+            _ if self.is_zero() => other,
+            _ if other.is_zero() => self,
+            _ => Self::sum(self, other),
+        }
+    }
+}
+
+impl<F: DecimalFormat> Sub for Decimal<F> {
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self {
+        self + -other
+    }
+}
+
+impl<F: DecimalFormat> Mul for Decimal<F> {
+    type Output = Self;
+
+    fn mul(self, other: Self) -> Self {
+        let negative = self.negative != other.negative;
+        match (self.kind, other.kind) {
+            (Kind::NaN, _) | (_, Kind::NaN) => Self::nan(),
+            (Kind::Infinite, _) | (_, Kind::Infinite) => {
+                if self.is_zero() || other.is_zero() {
+                    Self::nan()
+                } else {
+                    Self::infinity(negative)
+                }
+            }
+            _ => {
+                let exponent = self.exponent + other.exponent;
+                match self.coefficient.checked_mul(other.coefficient) {
+                    Some(c) => Self::round(negative, c, exponent, false),
+                    None => Self::round_big(
+                        negative,
+                        BigUint::from(self.coefficient) * other.coefficient,
+                        exponent,
+                        false,
+                    ),
+                }
+            }
+        }
+    }
+}
+
+impl<F: DecimalFormat> Div for Decimal<F> {
+    type Output = Self;
+
+    fn div(self, other: Self) -> Self {
+        let negative = self.negative != other.negative;
+        match (self.kind, other.kind) {
+            (Kind::NaN, _) | (_, Kind::NaN) => Self::nan(),
+            (Kind::Infinite, Kind::Infinite) => Self::nan(),
+            (Kind::Infinite, _) => Self::infinity(negative),
+            (_, Kind::Infinite) => Self::zero(negative),
+            _ if other.is_zero() && self.is_zero() => Self::nan(),
+            _ if other.is_zero() => Self::infinity(negative),
+            _ if self.is_zero() => Self::zero(negative),
+            _ => {
+                // Scale the dividend so the quotient has at least DIGITS + 2 digits.
+                let (da, db) = (digits(self.coefficient), digits(other.coefficient));
+                let scale = (F::DIGITS as i64 + 2 + db - da).max(0);
+                let exponent = self.exponent - other.exponent - scale;
+                if da + scale < POW10.len() as i64 {
+                    let dividend = self.coefficient * POW10[scale as usize];
+                    let (q, r) = (dividend / other.coefficient, dividend % other.coefficient);
+                    Self::round(negative, q, exponent, r != 0)
+                } else {
+                    let dividend =
+                        BigUint::from(self.coefficient) * BigUint::from(10u32).pow(scale as u32);
+                    let (q, r) = dividend.div_rem(&BigUint::from(other.coefficient));
+                    Self::round_big(negative, q, exponent, !r.is_zero())
+                }
+            }
+        }
+    }
+}
+
+impl<F: DecimalFormat> FromRational for Decimal<F> {
+    fn from_bigrational(r: &BigRational) -> Result<Self, String> {
+        if r.is_zero() {
+            return Ok(Self::zero(false));
+        }
+        let negative = r.is_negative();
+        let n = r.numer().magnitude();
+        let d = r.denom().magnitude();
+        // The position of the leading digit, within one:
+        let estimate = ((n.bits() as f64 - d.bits() as f64) * LOG10_2).floor() as i64;
+        if estimate > F::EMAX + 1 {
+            return Ok(Self::infinity(negative));
+        }
+        if estimate < Self::QMIN - 2 {
+            return Ok(Self::zero(negative));
+        }
+        // Divide out DIGITS + 2 or more digits:
+        let scale = F::DIGITS as i64 + 2 - estimate;
+        let ten = BigUint::from(10u32);
+        let (q, rem) = if scale >= 0 {
+            (n * ten.pow(scale as u32)).div_rem(d)
+        } else {
+            n.div_rem(&(d * ten.pow((-scale) as u32)))
+        };
+        Ok(Self::round_big(negative, q, -scale, !rem.is_zero()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rational(n: i64, d: i64) -> BigRational {
+        BigRational::new(n.into(), d.into())
+    }
+
+    #[test]
+    fn test_rounding() {
+        let third = Decimal32::from_bigrational(&rational(1, 3)).unwrap();
+        assert_eq!(third.parts(), Some((false, 3333333, -7)));
+        let two_thirds = Decimal32::from_bigrational(&rational(-2, 3)).unwrap();
+        assert_eq!(two_thirds.parts(), Some((true, 6666667, -7)));
+        // Ties to even:
+        let tie = Decimal32::from_bigrational(&rational(12345675, 10)).unwrap();
+        assert_eq!(tie.parts(), Some((false, 1234568, 0)));
+        let tie = Decimal32::from_bigrational(&rational(12345685, 10)).unwrap();
+        assert_eq!(tie.parts(), Some((false, 1234568, 0)));
+        // Trailing zeros are stripped:
+        assert_eq!(Decimal32::from_i32(1200).parts(), Some((false, 12, 2)));
+        assert_eq!(
+            Decimal32::from_i32(123456789).parts(),
+            Some((false, 1234568, 2))
+        );
+    }
+
+    #[test]
+    fn test_radix() {
+        // The classic: exact in decimal, not in binary.
+        let tenth = Decimal64::from_bigrational(&rational(1, 10)).unwrap();
+        let fifth = Decimal64::from_bigrational(&rational(2, 10)).unwrap();
+        let three_tenths = Decimal64::from_bigrational(&rational(3, 10)).unwrap();
+        assert_eq!(tenth + fifth, three_tenths);
+        assert_ne!(0.1 + 0.2, 0.3);
+        // ...and the other way around.
+        let third = Decimal32::from_i32(1) / Decimal32::from_i32(3);
+        assert_ne!(third * Decimal32::from_i32(3), Decimal32::from_i32(1));
+        assert_eq!(
+            (third * Decimal32::from_i32(3)).parts(),
+            Some((false, 9999999, -7))
+        );
+    }
+
+    #[test]
+    fn test_correctly_rounded() {
+        fn check<F: DecimalFormat>() {
+            let values = [
+                rational(1, 3),
+                rational(-2, 7),
+                rational(314159265, 100000),
+                rational(1, 1000),
+                rational(-999999999999, 7),
+                rational(5, 1),
+                rational(-1, 65536),
+            ];
+            for a in &values {
+                for b in &values {
+                    let (x, y) = (
+                        Decimal::<F>::from_bigrational(a).unwrap(),
+                        Decimal::<F>::from_bigrational(b).unwrap(),
+                    );
+                    let (ex, ey) = (x.to_rational().unwrap(), y.to_rational().unwrap());
+                    let expect = |r: BigRational| Decimal::<F>::from_bigrational(&r).unwrap();
+                    assert_eq!(x + y, expect(&ex + &ey), "{x} + {y}");
+                    assert_eq!(x - y, expect(&ex - &ey), "{x} - {y}");
+                    assert_eq!(x * y, expect(&ex * &ey), "{x} * {y}");
+                    assert_eq!(x / y, expect(&ex / &ey), "{x} / {y}");
+                }
+            }
+        }
+        check::<Decimal32Format>();
+        check::<Decimal64Format>();
+        check::<Decimal128Format>();
+    }
+
+    #[test]
+    fn test_range() {
+        let max = Decimal32::from_bigrational(&rational(9999999, 1)).unwrap()
+            * Decimal32::from_bigrational(&BigRational::from_integer(BigInt::from(10).pow(90)))
+                .unwrap();
+        assert_eq!(max.parts(), Some((false, 9999999, 90)));
+        assert!((max * Decimal32::from_i32(10)).is_infinite());
+        // The smallest subnormal is 1E-101; half of it ties to zero, 3/2 of it rounds to 2E-101.
+        let tiny =
+            Decimal32::from_bigrational(&BigRational::new(1.into(), BigInt::from(10).pow(101)))
+                .unwrap();
+        assert_eq!(tiny.parts(), Some((false, 1, -101)));
+        let two = Decimal32::from_i32(2);
+        assert_eq!(tiny / two, Decimal32::zero(false));
+        assert_eq!(
+            (tiny * Decimal32::from_i32(3) / two).parts(),
+            Some((false, 2, -101))
+        );
+        // Subnormals have fewer digits:
+        let small = tiny * Decimal32::from_i32(1234567);
+        assert_eq!(
+            (small / Decimal32::from_i32(1000)).parts(),
+            Some((false, 1235, -101))
+        );
+    }
+
+    #[test]
+    fn test_specials() {
+        let one = Decimal64::from_i32(1);
+        let zero = Decimal64::zero(false);
+        assert!((one / zero).is_infinite());
+        assert!((zero / zero).is_nan());
+        assert!((Decimal64::infinity(false) - Decimal64::infinity(false)).is_nan());
+        assert_eq!(zero, Decimal64::zero(true));
+        assert_eq!(one - one, zero);
+        assert!(Decimal64::nan() != Decimal64::nan());
+        assert!(-one < zero && zero < one && one < Decimal64::infinity(false));
+        assert_eq!(Decimal64::from_f64(0.1).to_f64(), 0.1);
+        // The nearest f64 to 0.1 rounds back to 0.1 at 16 digits, but not at 34.
+        assert_eq!(Decimal64::from_f64(0.1).parts(), Some((false, 1, -1)));
+        assert_ne!(Decimal128::from_f64(0.1).parts(), Some((false, 1, -1)));
+    }
+}
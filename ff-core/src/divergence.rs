@@ -0,0 +1,181 @@
+//! Per-pixel comparison of a render in a test format against the same render in a reference
+//! format: "finding farlands" by evaluating two types in lockstep.
+//!
+//! The reference is usually a wider format: `f64`, or `BigFloat<P>` at a precision that's exact
+//! for the window.
+
+use std::str::FromStr;
+
+use crate::{mandelbrot, newton, CancelContext, CommonParams, Escape, FractalParams, Zero};
+
+/// How a point's result in the test format compares with the reference format's.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Divergence {
+    /// Whether the point escaped (Mandelbrot) or reached a zero (Newton) in the test format.
+    /// A point whose escape couldn't be decided counts as escaped.
+    pub test: bool,
+    /// Likewise, in the reference format.
+    pub reference: bool,
+    /// The test format's iteration count less the reference's, if both escaped (or reached
+    /// a zero).
+    pub count_difference: Option<i64>,
+    /// Whether both reached the same zero, if both reached one. None for Mandelbrot.
+    pub same_root: Option<bool>,
+}
+
+impl Divergence {
+    /// Whether the formats agree on whether the point escaped (or reached a zero).
+    pub fn agrees(&self) -> bool {
+        self.test == self.reference
+    }
+
+    /// Whether the formats' results differ, as far as the measure is concerned.
+    pub fn differs(&self, measure: Measure) -> bool {
+        !self.agrees()
+            || match measure {
+                Measure::Classification => false,
+                Measure::Count => self.count_difference.is_some_and(|d| d != 0),
+                Measure::Root => self.same_root == Some(false),
+            }
+    }
+}
+
+/// Shorthand for "the divergences for this region"
+pub type DivergenceVector = Vec<Divergence>;
+
+/// Which part of a `Divergence` to render.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Measure {
+    /// Whether the escape (or convergence) agrees.
+    #[default]
+    Classification,
+    /// The difference in iteration count.
+    Count,
+    /// Whether the same zero was reached.
+    Root,
+}
+
+impl FromStr for Measure {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "classification" => Ok(Measure::Classification),
+            "count" => Ok(Measure::Count),
+            "root" => Ok(Measure::Root),
+            _ => Err(format!(
+                "unknown divergence measure {}: want classification, count, or root",
+                s
+            )),
+        }
+    }
+}
+
+/// Evaluates the fractal in the params' format and in the `reference` format, and compares them.
+pub fn compare(
+    ctx: &dyn CancelContext,
+    params: &CommonParams,
+    fractal: &FractalParams,
+    reference: &str,
+) -> Result<DivergenceVector, String> {
+    let reference_params = CommonParams {
+        numeric: reference.to_string(),
+        ..params.clone()
+    };
+    match *fractal {
+        FractalParams::Mandelbrot { iters, method } => {
+            let test = mandelbrot::compute_with(ctx, params, iters, method)?;
+            let reference = mandelbrot::compute_with(ctx, &reference_params, iters, method)?;
+            Ok(compare_escapes(&test, &reference))
+        }
+        FractalParams::Newton { iters } => {
+            let test = newton::compute(ctx, params, iters)?;
+            let reference = newton::compute(ctx, &reference_params, iters)?;
+            Ok(compare_zeros(&test, &reference))
+        }
+    }
+}
+
+fn compare_escapes(test: &[Option<Escape>], reference: &[Option<Escape>]) -> DivergenceVector {
+    test.iter()
+        .zip(reference)
+        .map(|(test, reference)| Divergence {
+            test: test.is_some(),
+            reference: reference.is_some(),
+            count_difference: test
+                .zip(*reference)
+                .map(|(test, reference)| test.count as i64 - reference.count as i64),
+            same_root: None,
+        })
+        .collect()
+}
+
+fn compare_zeros(test: &[Option<Zero>], reference: &[Option<Zero>]) -> DivergenceVector {
+    // Zeros are numbered separately in each render, so match them by position:
+    // a test root is the same as the reference root if that's the nearest reference root.
+    let mut roots: Vec<(usize, (f64, f64))> = Vec::new();
+    for zero in reference.iter().flatten() {
+        if !roots.iter().any(|(index, _)| *index == zero.zero) {
+            roots.push((zero.zero, zero.root));
+        }
+    }
+    let distance = |a: (f64, f64), b: (f64, f64)| (a.0 - b.0).powi(2) + (a.1 - b.1).powi(2);
+    let nearest = |root: (f64, f64)| {
+        roots
+            .iter()
+            .min_by(|a, b| distance(a.1, root).total_cmp(&distance(b.1, root)))
+            .map(|(index, _)| *index)
+    };
+
+    test.iter()
+        .zip(reference)
+        .map(|(test, reference)| {
+            let both = test.zip(*reference);
+            Divergence {
+                test: test.is_some(),
+                reference: reference.is_some(),
+                count_difference: both
+                    .map(|(test, reference)| test.count as i64 - reference.count as i64),
+                same_root: both.map(|(test, reference)| nearest(test.root) == Some(reference.zero)),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{test_params, NeverCancel};
+
+    #[test]
+    fn test_self() {
+        let mandelbrot = FractalParams::Mandelbrot {
+            iters: 32,
+            method: mandelbrot::Method::Direct,
+        };
+        for fractal in [mandelbrot, FractalParams::Newton { iters: 32 }] {
+            let divergence =
+                compare(&NeverCancel(), &test_params("f64", 16), &fractal, "f64").unwrap();
+            assert_eq!(divergence.len(), 256);
+            for d in divergence {
+                assert!(d.agrees());
+                assert!(d.count_difference.unwrap_or(0) == 0);
+                assert!(d.same_root.unwrap_or(true));
+            }
+        }
+    }
+
+    #[test]
+    fn test_coarse() {
+        let newton = FractalParams::Newton { iters: 32 };
+        let divergence =
+            compare(&NeverCancel(), &test_params("FP8-E4M3", 16), &newton, "f64").unwrap();
+        assert!(divergence
+            .iter()
+            .any(|d| !d.agrees() || d.count_difference != Some(0)));
+        // Most points still reach the same root.
+        let same = divergence.iter().filter(|d| d.same_root == Some(true)).count();
+        let different = divergence.iter().filter(|d| d.same_root == Some(false)).count();
+        assert!(same > different, "{} vs {}", same, different);
+    }
+}
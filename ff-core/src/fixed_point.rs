@@ -0,0 +1,345 @@
+//! Fixed-point formats, with a selectable overflow policy.
+//!
+//! The `fixed` crate's operators panic on overflow in debug builds and wrap in release builds.
+//! `Fixed<F, P>` makes the policy explicit, and the same in every build profile:
+//!
+//! - `Wrapping<I16F16>` wraps around, as release builds of the plain operators do.
+//!   This is also what the bare name, `I16F16`, selects.
+//! - `Saturating<I16F16>` clamps to the largest or smallest value.
+//! - `Checked<I16F16>` fails the render with an error.
+//!
+//! Division by zero panics under every policy.
+//!
+//! The widths are the registry's fixed-point rows; each takes any of the policies.
+
+use std::any::Any;
+use std::fmt::Debug;
+use std::marker::PhantomData;
+use std::ops::{Add, Div, Mul, Sub};
+use std::sync::Mutex;
+
+use fixed::traits::FixedSigned;
+use num::{BigInt, BigRational};
+
+use crate::number::{FractalNumber, NumberVisitor};
+use crate::numeric::FromRational;
+use crate::registry;
+
+/// Panic payload for an overflow under the `Checked` policy.
+///
+/// Evaluation catches this and reports it as an error, rather than dropping the row.
+#[derive(Debug)]
+pub struct Overflow {
+    pub operation: &'static str,
+}
+
+impl std::fmt::Display for Overflow {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "fixed-point overflow in {}", self.operation)
+    }
+}
+
+/// Collects `Overflow`s caught during a parallel evaluation.
+#[derive(Default)]
+pub(crate) struct OverflowSlot(Mutex<Option<Overflow>>);
+
+impl OverflowSlot {
+    /// Handles a caught panic: if it's an `Overflow`, keeps it to be reported by `into_result`.
+    /// Returns false for any other panic.
+    pub fn catch(&self, panic: Box<dyn Any + Send>) -> bool {
+        match panic.downcast::<Overflow>() {
+            Ok(overflow) => {
+                self.0.lock().unwrap().get_or_insert(*overflow);
+                true
+            }
+            Err(_) => false,
+        }
+    }
+
+    pub fn into_result(self) -> Result<(), String> {
+        match self.0.into_inner().unwrap() {
+            Some(overflow) => Err(overflow.to_string()),
+            None => Ok(()),
+        }
+    }
+}
+
+/// How a `Fixed` handles results that are out of range.
+pub trait OverflowPolicy: Copy + Debug + Send + Sync + 'static {
+    const NAME: &'static str;
+
+    fn from_i32<F: FixedSigned>(i: i32) -> F;
+    fn add<F: FixedSigned>(a: F, b: F) -> F;
+    fn sub<F: FixedSigned>(a: F, b: F) -> F;
+    fn mul<F: FixedSigned>(a: F, b: F) -> F;
+    fn div<F: FixedSigned>(a: F, b: F) -> F;
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Wrapping;
+
+impl OverflowPolicy for Wrapping {
+    const NAME: &'static str = "Wrapping";
+
+    fn from_i32<F: FixedSigned>(i: i32) -> F {
+        F::wrapping_from_num(i)
+    }
+    fn add<F: FixedSigned>(a: F, b: F) -> F {
+        a.wrapping_add(b)
+    }
+    fn sub<F: FixedSigned>(a: F, b: F) -> F {
+        a.wrapping_sub(b)
+    }
+    fn mul<F: FixedSigned>(a: F, b: F) -> F {
+        a.wrapping_mul(b)
+    }
+    fn div<F: FixedSigned>(a: F, b: F) -> F {
+        a.wrapping_div(b)
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Saturating;
+
+impl OverflowPolicy for Saturating {
+    const NAME: &'static str = "Saturating";
+
+    fn from_i32<F: FixedSigned>(i: i32) -> F {
+        F::saturating_from_num(i)
+    }
+    fn add<F: FixedSigned>(a: F, b: F) -> F {
+        a.saturating_add(b)
+    }
+    fn sub<F: FixedSigned>(a: F, b: F) -> F {
+        a.saturating_sub(b)
+    }
+    fn mul<F: FixedSigned>(a: F, b: F) -> F {
+        a.saturating_mul(b)
+    }
+    fn div<F: FixedSigned>(a: F, b: F) -> F {
+        a.saturating_div(b)
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Checked;
+
+impl Checked {
+    fn check<F>(result: Option<F>, operation: &'static str) -> F {
+        result.unwrap_or_else(|| std::panic::panic_any(Overflow { operation }))
+    }
+}
+
+impl OverflowPolicy for Checked {
+    const NAME: &'static str = "Checked";
+
+    fn from_i32<F: FixedSigned>(i: i32) -> F {
+        Self::check(F::checked_from_num(i), "conversion")
+    }
+    fn add<F: FixedSigned>(a: F, b: F) -> F {
+        Self::check(a.checked_add(b), "addition")
+    }
+    fn sub<F: FixedSigned>(a: F, b: F) -> F {
+        Self::check(a.checked_sub(b), "subtraction")
+    }
+    fn mul<F: FixedSigned>(a: F, b: F) -> F {
+        Self::check(a.checked_mul(b), "multiplication")
+    }
+    fn div<F: FixedSigned>(a: F, b: F) -> F {
+        if b.is_zero() {
+            panic!("fixed-point division by zero");
+        }
+        Self::check(a.checked_div(b), "division")
+    }
+}
+
+/// A fixed-point number F, with overflow handled according to P.
+#[derive(Copy, Clone, Debug)]
+pub struct Fixed<F, P> {
+    val: F,
+    policy: PhantomData<P>,
+}
+
+impl<F: PartialEq, P> PartialEq for Fixed<F, P> {
+    fn eq(&self, other: &Self) -> bool {
+        self.val == other.val
+    }
+}
+
+impl<F: PartialOrd, P> PartialOrd for Fixed<F, P> {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        self.val.partial_cmp(&other.val)
+    }
+}
+
+impl<F: FixedSigned, P: OverflowPolicy> Fixed<F, P> {
+    pub fn new(val: F) -> Self {
+        Fixed {
+            val,
+            policy: PhantomData,
+        }
+    }
+
+    pub fn get(self) -> F {
+        self.val
+    }
+}
+
+impl<F: FixedSigned, P: OverflowPolicy> Add for Fixed<F, P> {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Self::new(P::add(self.val, other.val))
+    }
+}
+
+impl<F: FixedSigned, P: OverflowPolicy> Sub for Fixed<F, P> {
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self {
+        Self::new(P::sub(self.val, other.val))
+    }
+}
+
+impl<F: FixedSigned, P: OverflowPolicy> Mul for Fixed<F, P> {
+    type Output = Self;
+
+    fn mul(self, other: Self) -> Self {
+        Self::new(P::mul(self.val, other.val))
+    }
+}
+
+impl<F: FixedSigned, P: OverflowPolicy> Div for Fixed<F, P> {
+    type Output = Self;
+
+    fn div(self, other: Self) -> Self {
+        Self::new(P::div(self.val, other.val))
+    }
+}
+
+impl<F: FixedSigned + FromRational, P: OverflowPolicy> FromRational for Fixed<F, P> {
+    fn from_bigrational(r: &BigRational) -> Result<Self, String> {
+        F::from_bigrational(r).map(Self::new)
+    }
+}
+
+impl<F: FixedSigned + FromRational, P: OverflowPolicy> FractalNumber for Fixed<F, P> {
+    fn from_i32(i: i32) -> Self {
+        Self::new(P::from_i32(i))
+    }
+
+    fn to_f64(self) -> f64 {
+        self.val.to_num()
+    }
+
+    fn to_rational(&self) -> Option<BigRational> {
+        let bits: i128 = self.val.to_bits().try_into().ok()?;
+        Some(BigRational::new(bits.into(), BigInt::from(1) << F::FRAC_NBITS))
+    }
+}
+
+/// Applies the visitor to `Fixed<F, P>`, for the policy P named by `policy`.
+pub(crate) fn visit_policy<F, V>(policy: &str, visitor: V) -> Option<V::Output>
+where
+    F: FixedSigned + FromRational + Send + Sync + 'static,
+    V: NumberVisitor,
+{
+    match policy {
+        Wrapping::NAME => Some(visitor.visit::<Fixed<F, Wrapping>>()),
+        Saturating::NAME => Some(visitor.visit::<Fixed<F, Saturating>>()),
+        Checked::NAME => Some(visitor.visit::<Fixed<F, Checked>>()),
+        _ => None,
+    }
+}
+
+/// If `name` is a fixed-point format with a policy, e.g. `Saturating<I16F16>`, applies the
+/// visitor to its type. A bare width is the registry's, which wraps.
+pub(crate) fn visit<V: NumberVisitor>(name: &str, visitor: V) -> Option<V::Output> {
+    let (policy, rest) = name.trim().split_once('<')?;
+    let width = rest.strip_suffix('>')?;
+    registry::visit_fixed(width.trim(), policy, visitor)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fixed::types::I8F8;
+
+    type W = Fixed<I8F8, Wrapping>;
+    type S = Fixed<I8F8, Saturating>;
+    type C = Fixed<I8F8, Checked>;
+
+    #[test]
+    fn test_policies() {
+        // I8F8 covers [-128, 128).
+        let big = 100;
+        assert_eq!((W::from_i32(big) + W::from_i32(big)).to_f64(), -56.0);
+        assert_eq!(
+            (S::from_i32(big) + S::from_i32(big)).to_f64(),
+            I8F8::MAX.to_num::<f64>()
+        );
+        assert_eq!((S::from_i32(-big) * S::from_i32(big)).to_f64(), -128.0);
+        assert_eq!((C::from_i32(big) - C::from_i32(big)).to_f64(), 0.0);
+
+        let overflow = std::panic::catch_unwind(|| C::from_i32(big) * C::from_i32(2)).unwrap_err();
+        let overflow = overflow.downcast::<Overflow>().unwrap();
+        assert_eq!(overflow.operation, "multiplication");
+    }
+
+    struct Name;
+
+    impl NumberVisitor for Name {
+        type Output = &'static str;
+
+        fn visit<N: FractalNumber + Send + Sync>(self) -> &'static str {
+            std::any::type_name::<N>()
+        }
+    }
+
+    #[test]
+    fn test_visit() {
+        let name = |format: &str| visit(format, Name);
+        assert_eq!(registry::visit("I16F16", Name), name("Wrapping<I16F16>"));
+        assert!(name("Saturating<I64F64>").unwrap().contains("Saturating"));
+        assert!(name("Checked<I8F8>").unwrap().contains("Checked"));
+        assert_eq!(name("Checked<I16F17>"), None);
+        assert_eq!(name("Clamped<I16F16>"), None);
+        assert_eq!(name("I16F16"), None);
+        assert_eq!(name("Interval<f64>"), None);
+        let widths = registry::FORMATS.iter().filter(|f| f.family == registry::Family::FixedPoint);
+        for format in widths {
+            for policy in [Wrapping::NAME, Saturating::NAME, Checked::NAME] {
+                let named = format!("{}<{}>", policy, format.name);
+                assert!(name(&named).is_some(), "{} is not registered", named);
+            }
+        }
+    }
+
+    #[test]
+    fn test_render() {
+        use crate::{mandelbrot, test_params, CommonParams, NeverCancel};
+
+        let escapes = |params: &CommonParams| {
+            mandelbrot::compute(&NeverCancel(), params, 32).map(|escapes| {
+                let count = |e: Option<crate::Escape>| e.map(|e| (e.count, e.z_magnitude_squared));
+                escapes.into_iter().map(count).collect::<Vec<_>>()
+            })
+        };
+        assert_eq!(
+            escapes(&test_params("Wrapping<I16F16>", 12)),
+            escapes(&test_params("I16F16", 12))
+        );
+        // Far enough out that the first square is past I8F8's range of [-128, 128):
+        let far = |numeric| {
+            let r = |n: i64| BigRational::from_integer(n.into());
+            CommonParams {
+                x: r(100)..r(120),
+                ..test_params(numeric, 4)
+            }
+        };
+        assert!(escapes(&far("Saturating<I8F8>")).is_ok());
+        assert!(escapes(&far("I8F8")).is_ok());
+        let overflow = escapes(&far("Checked<I8F8>")).unwrap_err();
+        assert!(overflow.starts_with("fixed-point overflow"), "{}", overflow);
+    }
+}
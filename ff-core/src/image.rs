@@ -1,4 +1,6 @@
-use crate::{Escape, EscapeVector, Size, Zero, ZeroVector};
+use crate::divergence::{Divergence, DivergenceVector, Measure};
+use crate::instrument::Event;
+use crate::{Escape, EscapeVector, EventVector, SensitivityVector, Size, Zero, ZeroVector};
 use hsv;
 
 /// Settings for rendering a fractal into an image.
@@ -10,21 +12,13 @@ impl Renderer {
     ///
     /// The `data` vector must be `size.x * size.y` entries long.
     /// Each point (pixel) is rendered as black if None, or corresponding to its value if Some.
+    /// Points whose escape couldn't be decided are rendered in gray.
     pub fn render(&self, size: Size, data: EscapeVector) -> Result<image::DynamicImage, String> {
-        if data.len() != (size.width * size.height) {
-            return Err(format!(
-                "error: data size != width * height: {} != {} * {}",
-                data.len(),
-                size.width,
-                size.height
-            ));
-        }
-
         // Find min/max iterations, so we can compute hue in that scale
         let (min, max) = data
             .iter()
             .fold((usize::MAX, usize::MIN), |(min, max), v| match v {
-                None => (min, max),
+                None | Some(Escape { undecided: true, .. }) => (min, max),
                 Some(Escape { count, .. }) => {
                     (std::cmp::min(*count, min), std::cmp::max(*count, max))
                 }
@@ -32,24 +26,49 @@ impl Renderer {
 
         let pixel_values = data.into_iter().map(|v| match v {
             None => image::Rgb([0, 0, 0]),
+            Some(Escape {
+                undecided: true, ..
+            }) => UNDECIDED,
             Some(Escape {
                 count,
                 z_magnitude_squared,
+                ..
             }) => mandelbrot_to_rgb(min, max, count, z_magnitude_squared),
         });
 
-        let mut img =
-            image::ImageBuffer::<image::Rgb<u8>, _>::new(size.width as u32, size.height as u32);
-        img.pixels_mut()
-            .zip(pixel_values)
-            .for_each(|(pixel, value)| {
-                *pixel = value;
-            });
+        render_pixels(size, pixel_values)
+    }
+}
 
-        Ok(img.into())
+/// Fills an image with `pixels`, which must be `size.x * size.y` entries long.
+fn render_pixels(
+    size: Size,
+    pixels: impl ExactSizeIterator<Item = image::Rgb<u8>>,
+) -> Result<image::DynamicImage, String> {
+    if pixels.len() != (size.width * size.height) {
+        return Err(format!(
+            "error: data size != width * height: {} != {} * {}",
+            pixels.len(),
+            size.width,
+            size.height
+        ));
     }
+
+    let mut img =
+        image::ImageBuffer::<image::Rgb<u8>, _>::new(size.width as u32, size.height as u32);
+    img.pixels_mut()
+        .zip(pixels)
+        .for_each(|(pixel, value)| {
+            *pixel = value;
+        });
+
+    Ok(img.into())
 }
 
+/// Color for points whose escape couldn't be decided.
+/// Escape colors are fully saturated, so this can't be mistaken for one.
+const UNDECIDED: image::Rgb<u8> = image::Rgb([128, 128, 128]);
+
 /// Convert a value within a range to an RGB value.
 fn mandelbrot_to_rgb(min: usize, max: usize, value: usize, escape: f64) -> image::Rgb<u8> {
     // hue is in range [0, 1]
@@ -76,18 +95,9 @@ impl NewtonRenderer {
     /// The `data` vector must be `size.x * size.y` entries long.
     /// Each point (pixel) is rendered as black if None, or corresponding to its value if Some.
     pub fn render(&self, size: Size, data: ZeroVector) -> Result<image::DynamicImage, String> {
-        if data.len() != (size.width * size.height) {
-            return Err(format!(
-                "error: data size != width * height: {} != {} * {}",
-                data.len(),
-                size.width,
-                size.height
-            ));
-        }
-
         let max_zero = data.iter().fold(usize::MIN, |max, v| match v {
             None => max,
-            Some(Zero { count: _, zero }) => std::cmp::max(max, *zero),
+            Some(Zero { zero, .. }) => std::cmp::max(max, *zero),
         });
 
         // The number of iteration is very long-tailed, use the 90th percentile
@@ -95,10 +105,7 @@ impl NewtonRenderer {
         let mut sort_data: Vec<usize> = data
             .iter()
             .cloned()
-            .filter_map(|a| match a {
-                None => None,
-                Some(Zero { count, .. }) => Some(count),
-            })
+            .filter_map(|a| a.map(|Zero { count, .. }| count))
             .collect();
         let len = sort_data.len();
         let high_iters = if len > 3 {
@@ -110,18 +117,10 @@ impl NewtonRenderer {
 
         let pixel_values = data.into_iter().map(|v| match v {
             None => image::Rgb([0, 0, 0]),
-            Some(Zero { count, zero }) => newton_to_rgb(max_zero + 1, zero, high_iters, count),
+            Some(Zero { count, zero, .. }) => newton_to_rgb(max_zero + 1, zero, high_iters, count),
         });
 
-        let mut img =
-            image::ImageBuffer::<image::Rgb<u8>, _>::new(size.width as u32, size.height as u32);
-        img.pixels_mut()
-            .zip(pixel_values)
-            .for_each(|(pixel, value)| {
-                *pixel = value;
-            });
-
-        Ok(img.into())
+        render_pixels(size, pixel_values)
     }
 }
 
@@ -138,3 +137,132 @@ fn newton_to_rgb(
     );
     image::Rgb([r, g, b])
 }
+
+#[derive(Default)]
+pub struct SensitivityRenderer {}
+
+impl SensitivityRenderer {
+    /// Render a sensitivity (variance) map into an image.
+    ///
+    /// The `data` vector must be `size.x * size.y` entries long.
+    /// Each point (pixel) is rendered as black if None; otherwise, from blue (no variance) to
+    /// red (the most variance in the image), on a logarithmic scale.
+    pub fn render(&self, size: Size, data: SensitivityVector) -> Result<image::DynamicImage, String> {
+        // Variances span orders of magnitude; scale by log(1 + variance).
+        let max = data
+            .iter()
+            .flatten()
+            .map(|v| v.ln_1p())
+            .fold(0.0, f64::max);
+
+        let pixel_values = data.into_iter().map(|v| match v {
+            None => image::Rgb([0, 0, 0]),
+            Some(variance) => sensitivity_to_rgb(variance.ln_1p(), max),
+        });
+
+        render_pixels(size, pixel_values)
+    }
+}
+
+/// Settings for rendering event counts; see `instrument::Instrumented`.
+#[derive(Default)]
+pub struct EventRenderer {
+    /// The event to map; all exceptional events if None.
+    pub event: Option<Event>,
+}
+
+impl EventRenderer {
+    /// Render a heatmap of event counts into an image.
+    ///
+    /// The `data` vector must be `size.x * size.y` entries long.
+    /// Points without events are rendered as black; otherwise, from blue (one event) to red
+    /// (the most events in the image), on a logarithmic scale.
+    pub fn render(&self, size: Size, data: EventVector) -> Result<image::DynamicImage, String> {
+        let counts = data.iter().map(|events| match self.event {
+            Some(event) => events.get(event),
+            None => events.exceptional(),
+        });
+        let max = counts.clone().max().unwrap_or(0);
+
+        let pixel_values = counts.map(|count| match count {
+            0 => image::Rgb([0, 0, 0]),
+            count => sensitivity_to_rgb((count as f64).ln(), (max as f64).ln()),
+        });
+
+        render_pixels(size, pixel_values)
+    }
+}
+
+/// Color for points where both formats escaped, at the same count if that's what's rendered.
+const AGREES: image::Rgb<u8> = image::Rgb([128, 128, 128]);
+
+/// Settings for rendering a comparison between formats; see `divergence::compare`.
+#[derive(Default)]
+pub struct DivergenceRenderer {
+    pub measure: Measure,
+}
+
+impl DivergenceRenderer {
+    /// Render a comparison into an image.
+    ///
+    /// The `data` vector must be `size.x * size.y` entries long.
+    /// - `Measure::Classification`: points where the formats agree are black (neither escaped)
+    ///   or gray (both escaped); red where only the test format escaped, blue where only the
+    ///   reference did.
+    /// - `Measure::Count`: points where either didn't escape are black, and the same count is
+    ///   gray; red where the test format took more iterations, blue where it took fewer, brighter
+    ///   for larger differences.
+    /// - `Measure::Root`: points where either didn't reach a zero are black; green where both
+    ///   reached the same zero, red where they didn't.
+    pub fn render(&self, size: Size, data: DivergenceVector) -> Result<image::DynamicImage, String> {
+        let max = data
+            .iter()
+            .filter_map(|d| d.count_difference)
+            .map(i64::unsigned_abs)
+            .max()
+            .unwrap_or(0);
+        let pixel_values = data.into_iter().map(|d| match self.measure {
+            Measure::Classification => match d {
+                Divergence {
+                    test: false,
+                    reference: false,
+                    ..
+                } => image::Rgb([0, 0, 0]),
+                Divergence {
+                    test: true,
+                    reference: true,
+                    ..
+                } => AGREES,
+                Divergence { test: true, .. } => image::Rgb([255, 0, 0]),
+                Divergence { .. } => image::Rgb([0, 0, 255]),
+            },
+            Measure::Count => match d.count_difference {
+                None => image::Rgb([0, 0, 0]),
+                Some(0) => AGREES,
+                Some(difference) => {
+                    // At least half brightness, so small differences stand out from black.
+                    let value = 128 + (127 * difference.unsigned_abs() / max.max(1)) as u8;
+                    if difference > 0 {
+                        image::Rgb([value, 0, 0])
+                    } else {
+                        image::Rgb([0, 0, value])
+                    }
+                }
+            },
+            Measure::Root => match d.same_root {
+                None => image::Rgb([0, 0, 0]),
+                Some(true) => image::Rgb([0, 255, 0]),
+                Some(false) => image::Rgb([255, 0, 0]),
+            },
+        });
+
+        render_pixels(size, pixel_values)
+    }
+}
+
+fn sensitivity_to_rgb(value: f64, max: f64) -> image::Rgb<u8> {
+    let fraction = if max > 0.0 { value / max } else { 0.0 };
+    // Hue runs from 240 (blue) down to 0 (red).
+    let (r, g, b) = hsv::hsv_to_rgb(240.0 * (1.0 - fraction.clamp(0.0, 1.0)), 1.0, 1.0);
+    image::Rgb([r, g, b])
+}
@@ -0,0 +1,339 @@
+//! Instrumented arithmetic: a wrapper that counts operations and exceptional events.
+//!
+//! `Instrumented<N>` computes each operation in N, then compares the result against the same
+//! operation in f64 to classify what happened: a result that's zero, or off by more than a factor
+//! of two, or of the wrong sign, or NaN or infinite where f64 isn't, hit one of the format's
+//! limits. The counts are kept per thread; `count` collects them for one pixel's evaluation
+//! (see `mandelbrot::events` and `newton::events`).
+//!
+//! Since the reference is f64, events are approximate for formats wider than f64.
+
+use std::cell::Cell;
+use std::cmp::Ordering;
+use std::fmt::Display;
+use std::ops::{Add, Div, Mul, Sub};
+use std::str::FromStr;
+
+use num::BigRational;
+
+use crate::number::FractalNumber;
+use crate::numeric::FromRational;
+
+/// An exceptional event in an arithmetic operation.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Event {
+    /// The result is less than half the exact result: clamped to the largest exponent, or
+    /// saturated.
+    Overflow,
+    /// The result is zero, or more than twice the exact result (e.g. rounded up to a posit's
+    /// smallest value).
+    Underflow,
+    /// The result has the wrong sign: a fixed-point value wrapped around.
+    Wrap,
+    /// A NaN (or posit NaR) from operands that weren't.
+    NaN,
+    /// An infinity from finite operands, other than by division by zero.
+    Infinity,
+    DivisionByZero,
+}
+
+impl Event {
+    pub const ALL: [Event; 6] = [
+        Event::Overflow,
+        Event::Underflow,
+        Event::Wrap,
+        Event::NaN,
+        Event::Infinity,
+        Event::DivisionByZero,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Event::Overflow => "overflow",
+            Event::Underflow => "underflow",
+            Event::Wrap => "wrap",
+            Event::NaN => "nan",
+            Event::Infinity => "infinity",
+            Event::DivisionByZero => "division-by-zero",
+        }
+    }
+}
+
+impl FromStr for Event {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Event::ALL
+            .into_iter()
+            .find(|event| event.name() == s)
+            .ok_or_else(|| format!("unknown event {}", s))
+    }
+}
+
+impl Display for Event {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Counts of operations and events, e.g. for one pixel.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Events {
+    pub operations: u32,
+    counts: [u32; Event::ALL.len()],
+}
+
+impl Events {
+    pub fn get(&self, event: Event) -> u32 {
+        self.counts[event as usize]
+    }
+
+    /// The total of all exceptional events.
+    pub fn exceptional(&self) -> u32 {
+        self.counts.iter().sum()
+    }
+}
+
+thread_local! {
+    static EVENTS: Cell<Events> = Cell::new(Events::default());
+}
+
+fn record(event: Option<Event>) {
+    EVENTS.with(|events| {
+        let mut counts = events.get();
+        counts.operations = counts.operations.saturating_add(1);
+        if let Some(event) = event {
+            let count = &mut counts.counts[event as usize];
+            *count = count.saturating_add(1);
+        }
+        events.set(counts);
+    })
+}
+
+/// Runs `f`, and returns the events it counted on this thread.
+pub(crate) fn count<T>(f: impl FnOnce() -> T) -> (T, Events) {
+    EVENTS.with(|events| events.set(Events::default()));
+    let result = f();
+    (result, EVENTS.with(|events| events.take()))
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) enum Operation {
+    Add,
+    Sub,
+    Mul,
+    Div,
+}
+
+/// Classifies the result of `a op b`.
+fn classify(operation: Operation, a: f64, b: f64, result: f64) -> Option<Event> {
+    if a.is_nan() || b.is_nan() {
+        // Propagated, not new.
+        return None;
+    }
+    if operation == Operation::Div && b == 0.0 {
+        return Some(Event::DivisionByZero);
+    }
+    if result.is_nan() {
+        return Some(Event::NaN);
+    }
+    if a.is_infinite() || b.is_infinite() {
+        return None;
+    }
+    if result.is_infinite() {
+        return Some(Event::Infinity);
+    }
+    let exact = match operation {
+        Operation::Add => a + b,
+        Operation::Sub => a - b,
+        Operation::Mul => a * b,
+        Operation::Div => a / b,
+    };
+    if exact == 0.0 || !exact.is_finite() {
+        return None;
+    }
+    // Sums that cancel can't overflow, and f64 can't tell their exact result.
+    if matches!(operation, Operation::Add | Operation::Sub)
+        && exact.abs() < a.abs().max(b.abs()) / 2.0
+    {
+        return None;
+    }
+    if result == 0.0 || result.abs() > exact.abs() * 2.0 {
+        Some(Event::Underflow)
+    } else if result.signum() != exact.signum() {
+        Some(Event::Wrap)
+    } else if result.abs() < exact.abs() / 2.0 {
+        Some(Event::Overflow)
+    } else {
+        None
+    }
+}
+
+/// A value whose operations are counted.
+#[derive(Clone, Debug)]
+pub struct Instrumented<N>(N);
+
+impl<N: FractalNumber> Instrumented<N> {
+    pub fn new(value: N) -> Self {
+        Instrumented(value)
+    }
+
+    pub fn value(&self) -> &N {
+        &self.0
+    }
+
+    /// Applies the operation, and records what happened.
+    fn combine(self, other: Self, operation: Operation, op: impl FnOnce(N, N) -> N) -> Self {
+        let (a, b) = (self.0.clone().to_f64(), other.0.clone().to_f64());
+        let result = op(self.0, other.0);
+        record(classify(operation, a, b, result.clone().to_f64()));
+        Instrumented(result)
+    }
+
+    /// Applies a fused operation (see `FractalNumber::fused_add_products`), and records it as
+    /// one `operation` on the two products; None if N has no fused accumulation.
+    pub(crate) fn fuse(
+        values: [&Self; 4],
+        operation: Operation,
+        op: impl FnOnce(&N, &N, &N, &N) -> Option<N>,
+    ) -> Option<Self> {
+        let [a, b, c, d] = values.map(|v| &v.0);
+        let result = op(a, b, c, d)?;
+        let product = |x: &N, y: &N| x.clone().to_f64() * y.clone().to_f64();
+        record(classify(operation, product(a, b), product(c, d), result.clone().to_f64()));
+        Some(Instrumented(result))
+    }
+}
+
+impl<N: FractalNumber> From<Instrumented<N>> for f64 {
+    fn from(v: Instrumented<N>) -> Self {
+        v.0.to_f64()
+    }
+}
+
+impl<N: FractalNumber> FromRational for Instrumented<N> {
+    fn from_bigrational(r: &BigRational) -> Result<Self, String> {
+        Ok(Instrumented(N::from_bigrational(r)?))
+    }
+}
+
+impl<N: FractalNumber> PartialEq for Instrumented<N> {
+    fn eq(&self, other: &Self) -> bool {
+        self.0 == other.0
+    }
+}
+
+impl<N: FractalNumber> PartialOrd for Instrumented<N> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        self.0.partial_cmp(&other.0)
+    }
+}
+
+impl<N: FractalNumber> Add for Instrumented<N> {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        self.combine(other, Operation::Add, |a, b| a + b)
+    }
+}
+
+impl<N: FractalNumber> Sub for Instrumented<N> {
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self {
+        self.combine(other, Operation::Sub, |a, b| a - b)
+    }
+}
+
+impl<N: FractalNumber> Mul for Instrumented<N> {
+    type Output = Self;
+
+    fn mul(self, other: Self) -> Self {
+        self.combine(other, Operation::Mul, |a, b| a * b)
+    }
+}
+
+impl<N: FractalNumber> Div for Instrumented<N> {
+    type Output = Self;
+
+    fn div(self, other: Self) -> Self {
+        self.combine(other, Operation::Div, |a, b| a / b)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixed_point::{Fixed, Wrapping};
+    use crate::masked_float::MaskedFloat;
+    use crate::{mandelbrot, test_params, CommonParams, NeverCancel};
+    use fixed::types::I11F5;
+
+    fn counted<N: FractalNumber>(a: N, b: N, op: impl FnOnce(N, N) -> N) -> Events {
+        count(|| op(a, b)).1
+    }
+
+    #[test]
+    fn test_float_events() {
+        let v = |f: f32| Instrumented::new(f);
+        let events = counted(v(1.0), v(0.0), |a, b| a / b);
+        assert_eq!(events.operations, 1);
+        assert_eq!(events.get(Event::DivisionByZero), 1);
+        assert_eq!(counted(v(1e30), v(1e30), |a, b| a * b).get(Event::Infinity), 1);
+        assert_eq!(counted(v(1e-30), v(1e-30), |a, b| a * b).get(Event::Underflow), 1);
+        assert_eq!(counted(v(0.0), v(0.0), |a, b| a / b).get(Event::DivisionByZero), 1);
+        assert_eq!(counted(v(1.5), v(2.5), |a, b| a * b).exceptional(), 0);
+    }
+
+    #[test]
+    fn test_limits() {
+        type M = MaskedFloat<3, 50>;
+        let v = |f: f64| Instrumented::new(M::new(f));
+        assert_eq!(counted(v(200.0), v(200.0), |a, b| a * b).get(Event::Overflow), 1);
+
+        type F = Fixed<I11F5, Wrapping>;
+        let v = |f: f64| Instrumented::new(F::new(I11F5::from_num(f)));
+        assert_eq!(counted(v(1000.0), v(1000.0), |a, b| a + b).get(Event::Wrap), 1);
+
+        let nar = counted(
+            Instrumented::new(softposit::P16::from_f64(1.0)),
+            Instrumented::new(softposit::P16::from_f64(0.0)),
+            |a, b| a / b,
+        );
+        assert_eq!(nar.get(Event::DivisionByZero), 1);
+    }
+
+    #[test]
+    fn test_fused() {
+        let v = |f: f64| Instrumented::new(f);
+        let (a, b) = (v(1.2e154), v(1.2e154));
+        let (sum, events) = count(|| Instrumented::fused_add_products(&a, &b, &a, &b));
+        assert_eq!(sum.unwrap().value(), &f64::INFINITY);
+        assert_eq!(events.operations, 1);
+        assert_eq!(events.get(Event::Infinity), 1);
+
+        // Fused products are one operation, rather than three:
+        let events = |fused: bool| {
+            let params = CommonParams {
+                fused,
+                ..test_params("f64", 8)
+            };
+            let (_, events) =
+                mandelbrot::events(&NeverCancel(), &params, 16, mandelbrot::Method::Direct)
+                    .unwrap();
+            events
+        };
+        let (fused, unfused) = (events(true), events(false));
+        assert_ne!(fused, unfused);
+        let operations = |events: Vec<Events>| events.iter().map(|e| e.operations).sum::<u32>();
+        assert!(operations(fused) < operations(unfused));
+    }
+
+    #[test]
+    fn test_event_names() {
+        for event in Event::ALL {
+            assert_eq!(event.name().parse::<Event>(), Ok(event));
+        }
+        assert!("all".parse::<Event>().is_err());
+    }
+}
@@ -0,0 +1,443 @@
+//! Interval arithmetic, with outward rounding.
+//!
+//! An `Interval<N>` holds bounds `[lo, hi]` in some endpoint format N, and every operation rounds
+//! the lower bound down and the upper bound up: the exact result of evaluating the same
+//! expression on real numbers is always within the interval. Where an interval straddles the
+//! escape bound, the format can't tell whether the point escaped; see `FractalNumber::decide_ge`.
+//!
+//! Endpoints round outward by stepping one place out from the round-to-nearest result.
+//! For f64, the error-free transformations give the sign of the rounding error, so only
+//! inexact results are widened, and only in the direction of the error.
+
+use std::cmp::Ordering;
+use std::ops::{Add, Div, Mul, Sub};
+
+use num::BigRational;
+
+use crate::multi_double::{two_prod, two_sum};
+use crate::number::FractalNumber;
+use crate::numeric::FromRational;
+
+/// A format that can be an interval endpoint: one that can step to the adjacent value.
+pub trait Endpoint: FractalNumber + Copy {
+    /// The next value toward negative infinity.
+    fn next_down(self) -> Self;
+    /// The next value toward positive infinity.
+    fn next_up(self) -> Self;
+    /// A value beyond every finite value; NaN (an unknown bound), if the format has no infinity.
+    fn infinity() -> Self;
+
+    /// Bounds on a + b: (rounded down, rounded up).
+    fn add_outward(a: Self, b: Self) -> (Self, Self) {
+        widen(a + b)
+    }
+    fn sub_outward(a: Self, b: Self) -> (Self, Self) {
+        widen(a - b)
+    }
+    fn mul_outward(a: Self, b: Self) -> (Self, Self) {
+        widen(a * b)
+    }
+    fn div_outward(a: Self, b: Self) -> (Self, Self) {
+        widen(a / b)
+    }
+}
+
+/// Bounds on a round-to-nearest result: it's within one place of the exact result.
+fn widen<N: Endpoint>(v: N) -> (N, N) {
+    (v.next_down(), v.next_up())
+}
+
+/// Bounds on `val + error`, using only the sign of the error.
+fn bracket(val: f64, error: f64) -> (f64, f64) {
+    if error > 0.0 {
+        (val, val.next_up())
+    } else if error < 0.0 {
+        (val.next_down(), val)
+    } else {
+        (val, val)
+    }
+}
+
+/// Whether `two_prod` of these factors is exact: no overflow in splitting, no underflow in
+/// the product's error term.
+fn product_is_exact(a: f64, b: f64, p: f64) -> bool {
+    const LARGEST: f64 = 1e299;
+    const SMALLEST: f64 = 1e-290;
+    a.abs() < LARGEST && b.abs() < LARGEST && (p == 0.0 || p.abs() > SMALLEST)
+}
+
+impl Endpoint for f64 {
+    fn next_down(self) -> Self {
+        f64::next_down(self)
+    }
+    fn next_up(self) -> Self {
+        f64::next_up(self)
+    }
+    fn infinity() -> Self {
+        f64::INFINITY
+    }
+
+    fn add_outward(a: Self, b: Self) -> (Self, Self) {
+        let (s, e) = two_sum(a, b);
+        if s.is_finite() {
+            bracket(s, e)
+        } else {
+            widen(s)
+        }
+    }
+
+    fn sub_outward(a: Self, b: Self) -> (Self, Self) {
+        Self::add_outward(a, -b)
+    }
+
+    fn mul_outward(a: Self, b: Self) -> (Self, Self) {
+        let (p, e) = two_prod(a, b);
+        if p == 0.0 && a != 0.0 && b != 0.0 {
+            // Underflowed to zero.
+            widen(p)
+        } else if p.is_finite() && product_is_exact(a, b, p) {
+            bracket(p, e)
+        } else {
+            widen(p)
+        }
+    }
+
+    fn div_outward(a: Self, b: Self) -> (Self, Self) {
+        let q = a / b;
+        if !q.is_finite() || q == 0.0 || !product_is_exact(q, b, a) {
+            return widen(q);
+        }
+        // The residual a - q*b is exact, and has the sign of the quotient's error (times b's).
+        let (p, e) = two_prod(q, b);
+        let residual = (a - p) - e;
+        bracket(q, residual * b.signum())
+    }
+}
+
+impl Endpoint for f32 {
+    fn next_down(self) -> Self {
+        f32::next_down(self)
+    }
+    fn next_up(self) -> Self {
+        f32::next_up(self)
+    }
+    fn infinity() -> Self {
+        f32::INFINITY
+    }
+}
+
+/// An interval `[lo, hi]` of values in format N.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Interval<N> {
+    lo: N,
+    hi: N,
+}
+
+/// The smaller of the values; NaN (an unknown bound) if either is NaN.
+fn lower<N: PartialOrd>(a: N, b: N) -> N {
+    match a.partial_cmp(&b) {
+        Some(Ordering::Greater) => b,
+        Some(_) => a,
+        None if a.partial_cmp(&a).is_none() => a,
+        None => b,
+    }
+}
+
+/// The larger of the values; NaN (an unknown bound) if either is NaN.
+fn upper<N: PartialOrd>(a: N, b: N) -> N {
+    match a.partial_cmp(&b) {
+        Some(Ordering::Less) => b,
+        Some(_) => a,
+        None if a.partial_cmp(&a).is_none() => a,
+        None => b,
+    }
+}
+
+impl<N: Endpoint> Interval<N> {
+    /// Creates the interval `[lo, hi]`.
+    pub fn new(lo: N, hi: N) -> Self {
+        Interval { lo, hi }
+    }
+
+    pub fn lo(&self) -> N {
+        self.lo
+    }
+
+    pub fn hi(&self) -> N {
+        self.hi
+    }
+
+    /// Creates an interval containing `v`, which is exactly `exact`.
+    fn containing(v: N, exact: &BigRational) -> Self {
+        match BigRational::from_float(v.to_f64()) {
+            Some(r) if r == *exact => Interval { lo: v, hi: v },
+            Some(r) if r > *exact => Interval {
+                lo: v.next_down(),
+                hi: v,
+            },
+            Some(_) => Interval {
+                lo: v,
+                hi: v.next_up(),
+            },
+            None => Interval {
+                lo: v.next_down(),
+                hi: v.next_up(),
+            },
+        }
+    }
+
+    /// The interval of all values.
+    fn everything() -> Self {
+        let infinity = N::infinity();
+        Interval {
+            lo: N::from_i32(0) - infinity,
+            hi: infinity,
+        }
+    }
+
+    /// Bounds on the results of `op` applied to each pair of endpoints.
+    fn corners(a: Self, b: Self, op: fn(N, N) -> (N, N)) -> Self {
+        let products = [
+            op(a.lo, b.lo),
+            op(a.lo, b.hi),
+            op(a.hi, b.lo),
+            op(a.hi, b.hi),
+        ];
+        let (mut lo, mut hi) = products[0];
+        for (down, up) in &products[1..] {
+            lo = lower(lo, *down);
+            hi = upper(hi, *up);
+        }
+        Interval { lo, hi }
+    }
+
+    /// Bounds on `a*b + c*d`, or `a*b - c*d` if `subtract`, using the endpoint format's fused
+    /// accumulation; None if it has none.
+    ///
+    /// The second product is bounded first, so each fused operation rounds `a*b ± q` once,
+    /// with q exact: that's within one place. The bounds of `a*b` are at the corners.
+    fn fused(a: Self, b: Self, c: Self, d: Self, subtract: bool) -> Option<Self> {
+        let one = N::from_i32(1);
+        let q = c * d;
+        // The sum grows with q; the difference shrinks.
+        let (q_down, q_up) = if subtract { (q.hi, q.lo) } else { (q.lo, q.hi) };
+        let op = if subtract {
+            N::fused_sub_products
+        } else {
+            N::fused_add_products
+        };
+        let mut bounds: Option<Self> = None;
+        for x in [a.lo, a.hi] {
+            for y in [b.lo, b.hi] {
+                let lo = op(&x, &y, &q_down, &one)?.next_down();
+                let hi = op(&x, &y, &q_up, &one)?.next_up();
+                bounds = Some(match bounds {
+                    None => Interval { lo, hi },
+                    Some(bounds) => Interval {
+                        lo: lower(bounds.lo, lo),
+                        hi: upper(bounds.hi, hi),
+                    },
+                });
+            }
+        }
+        bounds
+    }
+}
+
+impl<N: Endpoint> Add for Interval<N> {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Interval {
+            lo: N::add_outward(self.lo, other.lo).0,
+            hi: N::add_outward(self.hi, other.hi).1,
+        }
+    }
+}
+
+impl<N: Endpoint> Sub for Interval<N> {
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self {
+        Interval {
+            lo: N::sub_outward(self.lo, other.hi).0,
+            hi: N::sub_outward(self.hi, other.lo).1,
+        }
+    }
+}
+
+impl<N: Endpoint> Mul for Interval<N> {
+    type Output = Self;
+
+    fn mul(self, other: Self) -> Self {
+        Self::corners(self, other, N::mul_outward)
+    }
+}
+
+impl<N: Endpoint> Div for Interval<N> {
+    type Output = Self;
+
+    fn div(self, other: Self) -> Self {
+        let zero = N::from_i32(0);
+        // Dividing by an interval that contains zero (or whose bounds are unknown) gives no bound.
+        if !(other.lo > zero || other.hi < zero) {
+            return Self::everything();
+        }
+        Self::corners(self, other, N::div_outward)
+    }
+}
+
+/// Intervals are ordered only if they don't overlap (or are the same point).
+impl<N: Endpoint> PartialOrd for Interval<N> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        if self.hi < other.lo {
+            Some(Ordering::Less)
+        } else if self.lo > other.hi {
+            Some(Ordering::Greater)
+        } else if self.lo == self.hi && self.lo == other.lo && self.hi == other.hi {
+            Some(Ordering::Equal)
+        } else {
+            None
+        }
+    }
+}
+
+impl<N: Endpoint> FromRational for Interval<N> {
+    fn from_bigrational(r: &BigRational) -> Result<Self, String> {
+        Ok(Self::containing(N::from_bigrational(r)?, r))
+    }
+}
+
+impl<N: Endpoint> FractalNumber for Interval<N> {
+    fn from_i32(i: i32) -> Self {
+        Self::containing(N::from_i32(i), &BigRational::from_integer(i.into()))
+    }
+
+    /// The midpoint.
+    fn to_f64(self) -> f64 {
+        self.lo.to_f64() / 2.0 + self.hi.to_f64() / 2.0
+    }
+
+    /// The value, if the interval is a single point.
+    fn to_rational(&self) -> Option<BigRational> {
+        if self.lo == self.hi {
+            self.lo.to_rational()
+        } else {
+            None
+        }
+    }
+
+    fn decide_ge(&self, other: &Self) -> Option<bool> {
+        if self.lo >= other.hi {
+            Some(true)
+        } else if self.hi < other.lo {
+            Some(false)
+        } else {
+            None
+        }
+    }
+
+    fn fused_add_products(a: &Self, b: &Self, c: &Self, d: &Self) -> Option<Self> {
+        Self::fused(*a, *b, *c, *d, false)
+    }
+
+    fn fused_sub_products(a: &Self, b: &Self, c: &Self, d: &Self) -> Option<Self> {
+        Self::fused(*a, *b, *c, *d, true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type I = Interval<f64>;
+
+    fn contains(i: I, exact: &BigRational) -> bool {
+        let lo = BigRational::from_float(i.lo()).unwrap();
+        let hi = BigRational::from_float(i.hi()).unwrap();
+        lo <= *exact && *exact <= hi
+    }
+
+    #[test]
+    fn test_encloses_exact_results() {
+        let tenth = BigRational::new(1.into(), 10.into());
+        let third = BigRational::new(1.into(), 3.into());
+        let (a, b) = (
+            I::from_bigrational(&tenth).unwrap(),
+            I::from_bigrational(&third).unwrap(),
+        );
+        assert!(contains(a, &tenth));
+        assert!(contains(a + b, &(&tenth + &third)));
+        assert!(contains(a - b, &(&tenth - &third)));
+        assert!(contains(a * b, &(&tenth * &third)));
+        assert!(contains(a / b, &(&tenth / &third)));
+        assert!(contains(
+            b * b * b - a,
+            &(&third * &third * &third - &tenth)
+        ));
+    }
+
+    #[test]
+    fn test_fused_encloses_exact_results() {
+        let tenth = BigRational::new(1.into(), 10.into());
+        let third = BigRational::new(1.into(), 3.into());
+        let (a, b) = (
+            I::from_bigrational(&tenth).unwrap(),
+            I::from_bigrational(&third).unwrap(),
+        );
+        let sum = I::fused_add_products(&a, &b, &b, &a).unwrap();
+        assert!(contains(sum, &(&tenth * &third * BigRational::from_integer(2.into()))));
+        // Cancellation leaves only the rounding errors; the bounds still hold.
+        let difference = I::fused_sub_products(&a, &b, &b, &a).unwrap();
+        assert!(contains(difference, &BigRational::from_integer(0.into())));
+        let difference = I::fused_sub_products(&b, &b, &a, &a).unwrap();
+        assert!(contains(difference, &(&third * &third - &tenth * &tenth)));
+        // Endpoint formats without fused accumulation have none for intervals either.
+        let h = Interval::<crate::small_float::Binary16>::from_i32(1);
+        assert!(Interval::fused_add_products(&h, &h, &h, &h).is_none());
+    }
+
+    #[test]
+    fn test_exact_operations_stay_points() {
+        let two = I::from_i32(2);
+        let three = I::from_i32(3);
+        assert_eq!(two * three, I::from_i32(6));
+        assert_eq!(three - two, I::from_i32(1));
+        assert_eq!(three / two, I::new(1.5, 1.5));
+        // 1/3 isn't exact, so the interval is one place wide.
+        let third = I::from_i32(1) / three;
+        assert_eq!(third.hi(), third.lo().next_up());
+    }
+
+    #[test]
+    fn test_decide() {
+        let four = I::from_i32(4);
+        assert_eq!(I::new(4.0, 5.0).decide_ge(&four), Some(true));
+        assert_eq!(I::new(1.0, 3.5).decide_ge(&four), Some(false));
+        assert_eq!(I::new(3.5, 4.5).decide_ge(&four), None);
+        assert_eq!(I::new(3.5, 4.5).partial_cmp(&four), None);
+        // Ordinary formats always decide:
+        assert_eq!(3.0f64.decide_ge(&4.0), Some(false));
+    }
+
+    #[test]
+    fn test_division_by_zero() {
+        let around_zero = I::new(-1.0, 1.0);
+        let quotient = I::from_i32(1) / around_zero;
+        assert_eq!(quotient.lo(), f64::NEG_INFINITY);
+        assert_eq!(quotient.hi(), f64::INFINITY);
+    }
+
+    #[test]
+    fn test_small_float_endpoints() {
+        use crate::small_float::{Binary16, Fp8E4M3};
+        let tenth = BigRational::new(1.into(), 10.into());
+        let i = Interval::<Binary16>::from_bigrational(&tenth).unwrap();
+        assert!(i.lo().to_f64() < 0.1 && 0.1 < i.hi().to_f64());
+        // E4M3 saturates, so results at the top of the range have an unknown upper bound.
+        let big = Interval::<Fp8E4M3>::from_i32(256);
+        let product = big * big;
+        assert!(product.hi().is_nan());
+        assert_eq!(product.decide_ge(&Interval::from_i32(4)), Some(true));
+    }
+}
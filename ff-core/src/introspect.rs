@@ -0,0 +1,289 @@
+//! Properties of the named numeric formats: precision near 1, range, and representable values.
+//!
+//! Properties are found by probing: converting chosen rationals into the format and reading back
+//! the values they round to. That only relies on the format rounding monotonically, so it works
+//! the same for every format in the registry- and shows what the format actually does, which is
+//! what a render sees.
+
+use std::ops::Range;
+
+use num::{BigInt, BigRational, One, Signed, ToPrimitive};
+
+use crate::number::{FractalNumber, NumberVisitor};
+use crate::registry::{self, Evaluation, Format};
+
+/// Largest power of two probed, in either direction.
+const MAX_EXPONENT: u32 = 1 << 16;
+
+/// How far past a candidate extreme to probe, in powers of two.
+///
+/// Tapered formats (posits, takums) don't represent every power of two, so neighboring powers can
+/// round to the same value well before the extremes.
+const WINDOW: u32 = 32;
+
+/// Halvings spent looking for a value between two others before concluding there isn't one.
+const MAX_DEPTH: u32 = 64;
+
+/// Halvings spent narrowing in on the largest finite value.
+const MAX_PRECISION: u32 = 512;
+
+/// Machine-readable properties of a numeric format.
+#[derive(Clone, Debug)]
+pub struct Properties {
+    pub format: &'static Format,
+    /// The distance from 1 to the next larger value.
+    ///
+    /// For multi-double formats, whose components can be far apart, this is much smaller than
+    /// their precision elsewhere.
+    pub epsilon: Option<BigRational>,
+    /// The smallest positive value; None if it's beyond 2^-MAX_EXPONENT.
+    pub min_positive: Option<BigRational>,
+    /// The largest finite value; None if it's beyond 2^MAX_EXPONENT.
+    pub max_positive: Option<BigRational>,
+    /// log10(max_positive / min_positive).
+    pub decades: Option<f64>,
+}
+
+/// Finds the properties of the named format.
+pub fn properties(name: &str) -> Result<Properties, String> {
+    let format = point_format(name)?;
+    let (epsilon, min_positive, max_positive) = registry::visit(name, Probe).expect("registered");
+    let decades = match (&min_positive, &max_positive) {
+        (Some(min), Some(max)) => Some(log10(max) - log10(min)),
+        _ => None,
+    };
+    Ok(Properties {
+        format,
+        epsilon,
+        min_positive,
+        max_positive,
+        decades,
+    })
+}
+
+/// Lists the values of the named format in `range`, in increasing order.
+///
+/// Returns an error if there are more than `limit` of them.
+pub fn representable(
+    name: &str,
+    range: &Range<BigRational>,
+    limit: usize,
+) -> Result<Vec<BigRational>, String> {
+    point_format(name)?;
+    registry::visit(name, Enumerate { range, limit }).expect("registered")
+}
+
+/// Looks up a format whose values stand alone.
+fn point_format(name: &str) -> Result<&'static Format, String> {
+    let format = registry::find(name).ok_or_else(|| format!("unknown numeric format {}", name))?;
+    match format.evaluation {
+        Evaluation::Number => Ok(format),
+        Evaluation::Block => Err(format!(
+            "{} is a block format; its values depend on the block's shared state",
+            name
+        )),
+    }
+}
+
+/// The value of N nearest `r`, if it's finite.
+fn value<N: FractalNumber>(r: &BigRational) -> Option<BigRational> {
+    N::from_bigrational(r).ok()?.to_rational()
+}
+
+/// 2^k.
+fn power(k: i64) -> BigRational {
+    let magnitude = BigInt::one() << k.unsigned_abs();
+    if k >= 0 {
+        BigRational::from_integer(magnitude)
+    } else {
+        BigRational::new(BigInt::one(), magnitude)
+    }
+}
+
+/// The largest k in 0..MAX_EXPONENT for which `holds`, given that it holds at 0 and
+/// (mostly) holds up to some point and not after.
+fn last(holds: impl Fn(u32) -> bool) -> Option<u32> {
+    let (mut lo, mut hi) = (0, 1);
+    while holds(hi) {
+        lo = hi;
+        hi *= 2;
+        if hi > MAX_EXPONENT {
+            return None;
+        }
+    }
+    while hi - lo > 1 {
+        let mid = lo + (hi - lo) / 2;
+        if holds(mid) {
+            lo = mid;
+        } else {
+            hi = mid;
+        }
+    }
+    Some(lo)
+}
+
+/// The smallest of the positive values.
+fn min_positive(values: impl IntoIterator<Item = Option<BigRational>>) -> Option<BigRational> {
+    values
+        .into_iter()
+        .flatten()
+        .filter(|v| v.is_positive())
+        .min()
+}
+
+fn epsilon<N: FractalNumber>() -> Option<BigRational> {
+    let one = BigRational::one();
+    let above = |k: u32| value::<N>(&(&one + power(-(k as i64))));
+    let k = last(|k| above(k).is_some_and(|v| v > one))?;
+    above(k).map(|v| v - &one)
+}
+
+fn smallest<N: FractalNumber>() -> Option<BigRational> {
+    let at = |k: u32| value::<N>(&power(-(k as i64)));
+    // Past the smallest value, powers of two round to zero, or all to the smallest value.
+    let k = last(|k| {
+        let v = at(k);
+        v.as_ref().is_some_and(|v| v.is_positive()) && at(k + WINDOW) != v
+    })?;
+    min_positive((k..=k + WINDOW).map(at))
+}
+
+fn largest<N: FractalNumber>() -> Option<BigRational> {
+    let at = |k: u32| value::<N>(&power(k as i64));
+    // Past the largest value, powers of two overflow, or all round to the largest value.
+    let k = last(|k| {
+        let v = at(k);
+        v.is_some() && at(k + WINDOW) != v
+    })?;
+    let probes: Vec<_> = (k..=k + WINDOW).map(at).collect();
+    let mut candidates: Vec<_> = probes.iter().flatten().cloned().collect();
+    if let Some(overflow) = probes.iter().position(Option::is_none) {
+        // Narrow in on the overflow threshold.
+        let overflow = (k as usize + overflow) as i64;
+        let (mut lo, mut hi) = (power(overflow - 1), power(overflow));
+        for _ in 0..MAX_PRECISION {
+            let mid = (&lo + &hi) / BigRational::from_integer(2.into());
+            if value::<N>(&mid).is_some() {
+                lo = mid;
+            } else {
+                hi = mid;
+            }
+        }
+        candidates.extend(value::<N>(&lo));
+    }
+    candidates.into_iter().filter(|v| v.is_positive()).max()
+}
+
+/// log10(r), for positive r too large or small for an f64.
+fn log10(r: &BigRational) -> f64 {
+    fn log2(n: &BigInt) -> f64 {
+        let shift = n.bits().saturating_sub(64);
+        (n >> shift).to_f64().unwrap_or(f64::NAN).log2() + shift as f64
+    }
+    (log2(r.numer()) - log2(r.denom())) * std::f64::consts::LOG10_2
+}
+
+struct Probe;
+
+impl NumberVisitor for Probe {
+    type Output = (Option<BigRational>, Option<BigRational>, Option<BigRational>);
+
+    fn visit<N: FractalNumber + Send + Sync>(self) -> Self::Output {
+        (epsilon::<N>(), smallest::<N>(), largest::<N>())
+    }
+}
+
+struct Enumerate<'a> {
+    range: &'a Range<BigRational>,
+    limit: usize,
+}
+
+impl NumberVisitor for Enumerate<'_> {
+    type Output = Result<Vec<BigRational>, String>;
+
+    fn visit<N: FractalNumber + Send + Sync>(self) -> Self::Output {
+        let Range { start, end } = self.range;
+        let first = value::<N>(start).ok_or_else(|| format!("{} is out of range", start))?;
+        let last = value::<N>(end).ok_or_else(|| format!("{} is out of range", end))?;
+        // By monotonicity, every value in the range is between these.
+        let mut values = vec![first.clone()];
+        between::<N>(&first, &last, &mut values, self.limit)?;
+        values.push(last);
+        values.dedup();
+        values.retain(|v| self.range.contains(v));
+        Ok(values)
+    }
+}
+
+/// Appends the values of N strictly between the values x and y, in order.
+fn between<N: FractalNumber>(
+    x: &BigRational,
+    y: &BigRational,
+    values: &mut Vec<BigRational>,
+    limit: usize,
+) -> Result<(), String> {
+    // If a probe rounds to x, nothing is between x and the probe (and likewise for y);
+    // keep halving what's left until a probe finds a new value.
+    let (mut lo, mut hi) = (x.clone(), y.clone());
+    for _ in 0..MAX_DEPTH {
+        let mid = (&lo + &hi) / BigRational::from_integer(2.into());
+        let v = value::<N>(&mid).ok_or_else(|| format!("{} has no single value", mid))?;
+        if v > *x && v < *y {
+            between::<N>(x, &v, values, limit)?;
+            if values.len() >= limit {
+                return Err(format!("more than {} values in range", limit));
+            }
+            values.push(v.clone());
+            return between::<N>(&v, y, values, limit);
+        } else if v <= *x {
+            lo = mid;
+        } else {
+            hi = mid;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rational(n: i64, d: i64) -> BigRational {
+        BigRational::new(n.into(), d.into())
+    }
+
+    #[test]
+    fn test_f32() {
+        let p = properties("f32").unwrap();
+        assert_eq!(p.epsilon, Some(power(-23)));
+        assert_eq!(p.min_positive, Some(power(-149)));
+        assert_eq!(p.max_positive, BigRational::from_float(f32::MAX));
+        let decades = p.decades.unwrap();
+        assert!((83.0..84.0).contains(&decades), "{}", decades);
+    }
+
+    #[test]
+    fn test_fixed() {
+        let p = properties("I11F5").unwrap();
+        assert_eq!(p.epsilon, Some(rational(1, 32)));
+        assert_eq!(p.min_positive, Some(rational(1, 32)));
+        assert_eq!(p.max_positive, Some(rational(1024 * 32 - 1, 32)));
+
+        let values = representable("I11F5", &(rational(0, 1)..rational(1, 4)), 100).unwrap();
+        let expected: Vec<_> = (0..8).map(|i| rational(i, 32)).collect();
+        assert_eq!(values, expected);
+    }
+
+    #[test]
+    fn test_saturating() {
+        // Posits round to their extremes rather than to zero or infinity.
+        let p = properties("Posit<8,2>").unwrap();
+        assert_eq!(p.min_positive, Some(power(-24)));
+        assert_eq!(p.max_positive, Some(power(24)));
+
+        let values = representable("FP8-E4M3", &(rational(1, 1)..rational(2, 1)), 100).unwrap();
+        assert_eq!(values.len(), 8);
+        assert!(representable("FP8-E4M3", &(rational(1, 1)..rational(2, 1)), 4).is_err());
+        assert!(properties("MXFP4").is_err());
+    }
+}
@@ -4,11 +4,33 @@ use std::ops::Range;
 
 use num::BigRational;
 
+pub mod big_float;
+#[cfg(test)]
+mod conformance;
+pub mod decimal;
+pub mod divergence;
+pub mod fixed_point;
+pub mod instrument;
+pub mod interval;
+pub mod introspect;
+pub mod lns;
 pub mod mandelbrot;
 pub mod masked_float;
+pub mod mca;
+pub mod mixed;
+pub mod multi_double;
+pub mod mx;
 pub mod newton;
 mod number;
 mod numeric;
+pub mod posit;
+mod random;
+pub mod registry;
+pub mod search;
+pub mod slash;
+pub mod small_float;
+pub mod takum;
+pub mod trace;
 
 pub use numeric::FromRational;
 
@@ -26,6 +48,23 @@ impl CancelContext for NeverCancel {
     }
 }
 
+/// Parameters for a `size`-pixel square render of the window from -2 to 2, for tests.
+#[cfg(test)]
+pub(crate) fn test_params(numeric: &str, size: usize) -> CommonParams {
+    let r = |n: i64| BigRational::from_integer(n.into());
+    CommonParams {
+        size: Size {
+            width: size,
+            height: size,
+        },
+        x: r(-2)..r(2),
+        y: r(-2)..r(2),
+        numeric: numeric.to_string(),
+        seed: None,
+        fused: false,
+    }
+}
+
 /// Rendering-request parameters, common across renderables.
 #[derive(Debug, Clone)]
 pub struct CommonParams {
@@ -40,13 +79,26 @@ pub struct CommonParams {
     /// Numeric type to use for the computations.
     /// This is assumed to be "mappable" by the rendering engine.
     pub numeric: String,
+
+    /// Seed for formats with random behavior (e.g. stochastic rounding, `MCA<...>`).
+    /// If set, each pixel's random stream is derived from it, so the render is reproducible.
+    pub seed: Option<u64>,
+
+    /// Whether complex arithmetic accumulates its products with fused accumulation (a posit
+    /// quire, or FMA), in formats that support it; see `FractalNumber::fused_add_products`.
+    /// Other formats round after every operation either way.
+    pub fused: bool,
 }
 
 /// Fractal-specific rendering parameters.
 #[non_exhaustive]
 #[derive(Debug, Clone)]
 pub enum FractalParams {
-    Mandelbrot { iters: usize },
+    Mandelbrot {
+        iters: usize,
+        /// How each point's orbit is computed; see `mandelbrot::Method`.
+        method: mandelbrot::Method,
+    },
     Newton { iters: usize },
 }
 
@@ -59,11 +111,31 @@ impl FractalParams {
     }
 }
 
+/// What to compute at each pixel.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum RenderMode {
+    /// The fractal itself.
+    #[default]
+    Fractal,
+    /// The variance of the result across this many seeded trials: a map of where the result
+    /// is sensitive to the format's random behavior (e.g. `MCA<f64,24>`).
+    Sensitivity { trials: usize },
+    /// A heatmap of how often each point's evaluation hit the format's limits: the count of
+    /// this event, or of all exceptional events if None. See `instrument::Instrumented`.
+    Events { event: Option<instrument::Event> },
+    /// A comparison with the same render in the `reference` format; see `divergence::compare`.
+    Divergence {
+        reference: String,
+        measure: divergence::Measure,
+    },
+}
+
 /// Request for rendering a fractal.
 #[derive(Debug, Clone)]
 pub struct RenderRequest {
     pub common: CommonParams,
     pub fractal: FractalParams,
+    pub mode: RenderMode,
 }
 
 /// A pair of integer (x, y) dimensions.
@@ -80,17 +152,28 @@ pub mod image;
 pub struct Escape {
     pub count: usize,
     pub z_magnitude_squared: f64,
+    /// The format couldn't certify whether the point escaped on this iteration
+    /// (e.g. an interval straddling the bound), so iteration stopped there.
+    pub undecided: bool,
 }
 
 /// Shorthand for "the escapes for this region"
 pub type EscapeVector = Vec<Option<Escape>>;
 
-/// Zero term: Which zero was reached, and how many iterations it took
+/// Zero term: Which zero was reached, where, and how many iterations it took
 #[derive(Copy, Clone, Debug)]
 pub struct Zero {
     pub count: usize,
     pub zero: usize,
+    /// Where the iteration converged, as (re, im).
+    pub root: (f64, f64),
 }
 
 /// Shorthand for "the zeros for this region"
 pub type ZeroVector = Vec<Option<Zero>>;
+
+/// Variance across trials, for each point; None if no trial produced a value.
+pub type SensitivityVector = Vec<Option<f64>>;
+
+/// Operation and event counts, for each point.
+pub type EventVector = Vec<instrument::Events>;
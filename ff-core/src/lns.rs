@@ -0,0 +1,298 @@
+//! Logarithmic number system.
+//!
+//! An `Lns<I, F>` stores a sign and the base-2 logarithm of the magnitude, as a signed
+//! fixed-point number with I integer bits (including its sign) and F fraction bits:
+//! 1 + I + F bits in all. As in hardware LNS, the most negative logarithm is reserved for zero.
+//!
+//! Multiplication and division are exact (up to overflow): they add and subtract logarithms.
+//! Addition and subtraction go through the Gaussian logarithms,
+//!
+//!   log2(2^a + 2^b) = a + sb(b - a),  sb(d) = log2(1 + 2^d)
+//!   log2(2^a - 2^b) = a + db(b - a),  db(d) = log2(1 - 2^d)
+//!
+//! which hardware reads from a table. Here they're evaluated in f64 and rounded to the nearest
+//! fraction bit, which is what an exact table holds.
+//!
+//! Results too large in magnitude saturate; results too small flush to zero.
+
+use std::cmp::Ordering;
+use std::f64::consts::LN_2;
+use std::ops::{Add, Div, Mul, Neg, Sub};
+
+use num::{BigRational, ToPrimitive};
+
+use crate::numeric::{binary_parts, ldexp, FromRational};
+
+/// A logarithmic number with I integer bits and F fraction bits in its logarithm.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Lns<const I: u32, const F: u32> {
+    negative: bool,
+    /// log2 of the magnitude, scaled by 2^F; None for zero.
+    log: Option<i64>,
+}
+
+/// sb(d) = log2(1 + 2^d)
+fn sb(d: f64) -> f64 {
+    d.exp2().ln_1p() / LN_2
+}
+
+/// db(d) = log2(1 - 2^d), for d < 0
+fn db(d: f64) -> f64 {
+    (-(d * LN_2).exp_m1()).log2()
+}
+
+impl<const I: u32, const F: u32> Lns<I, F> {
+    // Keep the scaled logarithm exact in an f64.
+    const VALID: () = assert!(I >= 1 && F <= 40 && I + F <= 48, "unsupported LNS size");
+    const SCALE: f64 = (1u64 << F) as f64;
+    const MAX_LOG: i64 = (1 << (I + F - 1)) - 1;
+
+    pub const ZERO: Self = Lns {
+        negative: false,
+        log: None,
+    };
+
+    /// Creates a value from a scaled logarithm, saturating or flushing to zero if out of range.
+    fn from_log(negative: bool, log: i64) -> Self {
+        #[allow(clippy::let_unit_value)]
+        let _ = Self::VALID;
+        if log < -Self::MAX_LOG {
+            Self::ZERO
+        } else {
+            Lns {
+                negative,
+                log: Some(log.min(Self::MAX_LOG)),
+            }
+        }
+    }
+
+    /// Creates a value with logarithm `exponent + log`, rounding `log` to nearest.
+    fn from_parts(negative: bool, exponent: i64, log: f64) -> Self {
+        let scaled = exponent
+            .saturating_mul(1 << F)
+            .saturating_add((log * Self::SCALE).round() as i64);
+        Self::from_log(negative, scaled)
+    }
+
+    pub fn from_f64(f: f64) -> Self {
+        if f == 0.0 || f.is_nan() {
+            Self::ZERO
+        } else if f.is_infinite() {
+            Self::from_log(f < 0.0, Self::MAX_LOG)
+        } else {
+            Self::from_parts(f < 0.0, 0, f.abs().log2())
+        }
+    }
+
+    pub fn from_i32(i: i32) -> Self {
+        Self::from_f64(i.into())
+    }
+
+    pub fn to_f64(self) -> f64 {
+        let Some(log) = self.log else {
+            return 0.0;
+        };
+        let whole = log >> F;
+        let fraction = (log - (whole << F)) as f64 / Self::SCALE;
+        let magnitude = ldexp(fraction.exp2(), whole);
+        if self.negative {
+            -magnitude
+        } else {
+            magnitude
+        }
+    }
+
+    fn sign(&self) -> i8 {
+        match self.log {
+            None => 0,
+            Some(_) if self.negative => -1,
+            Some(_) => 1,
+        }
+    }
+}
+
+impl<const I: u32, const F: u32> From<Lns<I, F>> for f64 {
+    fn from(v: Lns<I, F>) -> Self {
+        v.to_f64()
+    }
+}
+
+impl<const I: u32, const F: u32> PartialOrd for Lns<I, F> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(match self.sign().cmp(&other.sign()) {
+            Ordering::Equal => match (self.log, other.log) {
+                (Some(a), Some(b)) if self.negative => b.cmp(&a),
+                (Some(a), Some(b)) => a.cmp(&b),
+                _ => Ordering::Equal,
+            },
+            ord => ord,
+        })
+    }
+}
+
+impl<const I: u32, const F: u32> Neg for Lns<I, F> {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        match self.log {
+            None => self,
+            Some(_) => Lns {
+                negative: !self.negative,
+                ..self
+            },
+        }
+    }
+}
+
+impl<const I: u32, const F: u32> Add for Lns<I, F> {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        let (a, b) = match (self.log, other.log) {
+            (None, _) => return other,
+            (_, None) => return self,
+            (Some(a), Some(b)) => (a, b),
+        };
+        let (big, big_log, small_log) = if a >= b { (self, a, b) } else { (other, b, a) };
+        let d = (small_log - big_log) as f64 / Self::SCALE;
+        let delta = if self.negative == other.negative {
+            sb(d)
+        } else if d == 0.0 {
+            return Self::ZERO;
+        } else {
+            db(d)
+        };
+        Self::from_log(
+            big.negative,
+            big_log.saturating_add((delta * Self::SCALE).round() as i64),
+        )
+    }
+}
+
+impl<const I: u32, const F: u32> Sub for Lns<I, F> {
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self {
+        self + -other
+    }
+}
+
+impl<const I: u32, const F: u32> Mul for Lns<I, F> {
+    type Output = Self;
+
+    fn mul(self, other: Self) -> Self {
+        match (self.log, other.log) {
+            (Some(a), Some(b)) => Self::from_log(self.negative != other.negative, a + b),
+            _ => Self::ZERO,
+        }
+    }
+}
+
+impl<const I: u32, const F: u32> Div for Lns<I, F> {
+    type Output = Self;
+
+    /// Division by zero saturates, as an overflow would.
+    fn div(self, other: Self) -> Self {
+        let negative = self.negative != other.negative;
+        match (self.log, other.log) {
+            (None, _) => Self::ZERO,
+            (Some(_), None) => Self::from_log(negative, Self::MAX_LOG),
+            (Some(a), Some(b)) => Self::from_log(negative, a - b),
+        }
+    }
+}
+
+impl<const I: u32, const F: u32> FromRational for Lns<I, F> {
+    fn from_bigrational(r: &BigRational) -> Result<Self, String> {
+        let Some(parts) = binary_parts(r, 64) else {
+            return Ok(Self::ZERO);
+        };
+        let sig = parts
+            .significand
+            .to_f64()
+            .ok_or_else(|| format!("significand of {} out of range", r))?;
+        Ok(Self::from_parts(parts.negative, parts.exponent, sig.log2()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type L16 = Lns<5, 10>;
+    type L32 = Lns<8, 23>;
+
+    #[test]
+    fn test_multiplication_is_exact() {
+        let product = L16::from_i32(3) * L16::from_i32(5) / L16::from_i32(3);
+        assert_eq!(product, L16::from_i32(5));
+        assert_eq!((L16::from_i32(4) * L16::from_i32(-8)).to_f64(), -32.0);
+        assert_eq!((L16::from_i32(1) / L16::from_i32(16)).to_f64(), 1.0 / 16.0);
+    }
+
+    #[test]
+    fn test_addition() {
+        let values = [1.0, -1.0, 0.1, 1.0 / 3.0, -2.5, 1000.0, 1e-5];
+        for a in values {
+            for b in values {
+                let sum = (L32::from_f64(a) + L32::from_f64(b)).to_f64();
+                let difference = (L32::from_f64(a) - L32::from_f64(b)).to_f64();
+                // Relative error of the log is 2^-24; the values are rounded three times.
+                let tolerance = |v: f64| v.abs() * 2f64.powi(-20) + 1e-15;
+                assert!(
+                    (sum - (a + b)).abs() <= tolerance(a + b),
+                    "{a} + {b} = {sum}"
+                );
+                assert!(
+                    (difference - (a - b)).abs() <= tolerance(a - b),
+                    "{a} - {b} = {difference}"
+                );
+            }
+        }
+        let x = L16::from_f64(0.3);
+        assert_eq!(x - x, L16::ZERO);
+        assert_eq!(x + L16::ZERO, x);
+        assert_eq!(L16::ZERO - x, -x);
+    }
+
+    #[test]
+    fn test_range() {
+        // I=5 bits of log: magnitudes from 2^-16 to 2^16.
+        let big = L16::from_i32(1 << 12);
+        assert!((big * big).to_f64() < 65536.0);
+        assert!((big * big).to_f64() > 65000.0);
+        let small = L16::from_i32(1) / big;
+        assert_eq!(small * small, L16::ZERO);
+        assert_eq!(
+            (L16::from_i32(-1) / L16::ZERO).to_f64(),
+            -(big * big).to_f64()
+        );
+    }
+
+    #[test]
+    fn test_ordering() {
+        let values = [-100.0, -1.5, -0.001, 0.0, 0.001, 1.5, 100.0];
+        for a in values {
+            for b in values {
+                assert_eq!(
+                    L16::from_f64(a).partial_cmp(&L16::from_f64(b)),
+                    a.partial_cmp(&b),
+                    "{a} <=> {b}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_from_rational() {
+        let r = BigRational::new((-3).into(), 4.into());
+        assert_eq!(L32::from_bigrational(&r).unwrap(), L32::from_f64(-0.75));
+        // Past the range of f64:
+        let huge = BigRational::from_integer(num::BigInt::from(3) << 2000u32);
+        let huge = Lns::<13, 10>::from_bigrational(&huge).unwrap();
+        assert_eq!(
+            huge.log,
+            Some(((2001.0 + 1.5f64.log2()) * 1024.0).round() as i64)
+        );
+    }
+}
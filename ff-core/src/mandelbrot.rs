@@ -3,42 +3,42 @@ use std::{ops::Range, panic::AssertUnwindSafe};
 
 /// Implementation of the Mandelbrot fractal,
 /// parameterized on a numeric type.
-use crate::{masked_float::MaskedFloat, numeric::Complex, CancelContext, CommonParams};
+use crate::{
+    big_float::{BigFloat, BigFloatFormat},
+    fixed_point::OverflowSlot,
+    instrument::{self, Instrumented},
+    mca::{self, Mca, McaFormat},
+    mixed::{self, MixedFormat},
+    mx::{Element, MxBlock, MxFormat},
+    numeric::{make_range, Complex, FromRational},
+    random,
+    registry::{self, Fractal},
+    CancelContext, CommonParams,
+};
 
 pub use crate::number::FractalNumber;
-use crate::{Escape, EscapeVector};
+use crate::number::{ConvertVisitor, NumberVisitor};
+use crate::{Escape, EscapeVector, EventVector, SensitivityVector};
 use num::BigRational;
 
-/// Function pointer for evaluating escape counts
-type EscapeFn = fn( &dyn CancelContext, &CommonParams, usize) -> Result<EscapeVector, String>;
-
-/// Pointers, by numeric format name:
-const FUNCTIONS: &[(&'static str, EscapeFn)] = &[
-    ("f32", evaluate_parallel_numeric::<f32>),
-    ("f64", evaluate_parallel_numeric::<f64>),
-    ("P32", evaluate_parallel_numeric::<softposit::P32>),
-    ("P16", evaluate_parallel_numeric::<softposit::P16>),
-    ("P8", evaluate_parallel_numeric::<softposit::P8>),
-    (
-        "MaskedFloat<3,50>",
-        evaluate_parallel_numeric::<MaskedFloat<3, 50>>,
-    ),
-    (
-        "MaskedFloat<4,50>",
-        evaluate_parallel_numeric::<MaskedFloat<4, 50>>,
-    ),
-    (
-        "MaskedFloat<6,3>",
-        evaluate_parallel_numeric::<MaskedFloat<6, 3>>,
-    ),
-    ("I11F5", evaluate_parallel_numeric::<fixed::types::I11F5>),
-    // ("I13F3", evaluate_parallel_numeric::<fixed::types::I13F3>),
-    // ("I15F1", evaluate_parallel_numeric::<fixed::types::I15F1>),
-];
+/// How each point's orbit is computed.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Method {
+    /// Iterate each point directly in the numeric format.
+    #[default]
+    Direct,
+    /// Iterate one reference orbit (the window's center) at high precision, and each point's
+    /// offset from it in the numeric format. The offsets stay representable at zoom depths where
+    /// the points themselves aren't.
+    Perturbation,
+}
 
 /// List the numeric formats that are valid for rendering.
+///
+/// `compute` also accepts the formats the registry excludes (see `registry::Format::exclusion`),
+/// and the parameterized families (see `registry`).
 pub fn formats() -> impl Iterator<Item = &'static str> {
-    FUNCTIONS.iter().map(|(name, _)| *name)
+    registry::listed(Fractal::Mandelbrot).map(|format| format.name)
 }
 
 /// Computes the escape values in the given window.
@@ -46,102 +46,677 @@ pub fn formats() -> impl Iterator<Item = &'static str> {
 /// Under the hood, this uses Rayon's par_iter, so it's recommended to launch it from a Rayon
 /// thread-pool.
 pub fn compute(ctx: &dyn CancelContext, params: &CommonParams, iterations: usize) -> Result<EscapeVector, String> {
+    compute_with(ctx, params, iterations, Method::Direct)
+}
+
+/// Computes the escape values in the given window, using the given method.
+pub fn compute_with(
+    ctx: &dyn CancelContext,
+    params: &CommonParams,
+    iterations: usize,
+    method: Method,
+) -> Result<EscapeVector, String> {
     let fmt = params.numeric.as_str();
-    // Linear scan, we don't have that many options:
-    for (candidate, computer) in FUNCTIONS.iter() {
-        if *candidate == fmt {
-            return computer(ctx, params, iterations);
+    let evaluate = Evaluate {
+        ctx,
+        params,
+        iterations,
+        method,
+    };
+    if let Some(result) = registry::visit_any(fmt, evaluate) {
+        return result?;
+    }
+
+    // MX formats share a scale across a block of pixels, so they're evaluated a block at a time:
+    if fmt.starts_with("MX") {
+        let format: MxFormat = fmt.parse()?;
+        if method == Method::Perturbation {
+            return Err(format!("perturbation is not supported for MX format {}", format));
         }
+        if params.fused {
+            return Err(format!("fused arithmetic is not supported for MX format {}", format));
+        }
+        return evaluate_mx(ctx, params, iterations, format);
+    }
+
+    if fmt.starts_with("MCA<") {
+        let format: McaFormat = fmt.parse()?;
+        let evaluate = EvaluateMca {
+            ctx,
+            params,
+            iterations,
+            method,
+            precision: format.precision,
+        };
+        if let Some(result) = mca::visit(&format.inner, evaluate) {
+            return result;
+        }
+    }
+
+    if fmt.starts_with("Mixed<") {
+        let format: MixedFormat = fmt.parse()?;
+        if method == Method::Perturbation {
+            return Err(format!("perturbation is not supported for mixed format {}", format));
+        }
+        let evaluate = EvaluateMixed {
+            ctx,
+            params,
+            iterations,
+            coordinate: mixed::round(&format.coordinate)?,
+            test: mixed::test(&format.test)?,
+        };
+        return mixed::visit(&format.state, evaluate)?;
     }
 
     Err(format!("unknown numeric format {}", fmt))
 }
 
-fn evaluate_parallel_numeric<N>(
+/// Computes the escape values in the given window, with each point's operation and event counts;
+/// see `instrument::Instrumented`.
+///
+/// Supports the formats `compute_with` does, other than MX, MCA, and mixed formats.
+pub fn events(
     ctx: &dyn CancelContext,
     params: &CommonParams,
     iterations: usize,
+    method: Method,
+) -> Result<(EscapeVector, EventVector), String> {
+    let fmt = params.numeric.as_str();
+    let evaluate = EvaluateInstrumented {
+        ctx,
+        params,
+        iterations,
+        method,
+    };
+    if let Some(result) = registry::visit_any(fmt, evaluate) {
+        return result?;
+    }
+
+    Err(format!("can't count events in numeric format {}", fmt))
+}
+
+/// Computes the variance of each point's escape count across `trials` seeded renders.
+///
+/// Only formats with random behavior (e.g. `MCA<f64,24>`) vary between trials.
+/// Points that don't escape count as escaping at `iterations`.
+pub fn sensitivity(
+    ctx: &dyn CancelContext,
+    params: &CommonParams,
+    iterations: usize,
+    method: Method,
+    trials: usize,
+) -> Result<SensitivityVector, String> {
+    mca::sensitivity(params, trials, |params| {
+        let escapes = compute_with(ctx, params, iterations, method)?;
+        Ok(escapes
+            .into_iter()
+            .map(|escape| Some([escape.map_or(iterations, |escape| escape.count) as f64])))
+    })
+}
+
+/// Evaluates a format whose type is chosen at runtime.
+struct Evaluate<'a> {
+    ctx: &'a dyn CancelContext,
+    params: &'a CommonParams,
+    iterations: usize,
+    method: Method,
+}
+
+impl ConvertVisitor for Evaluate<'_> {
+    type Output = Result<EscapeVector, String>;
+
+    fn visit<N: FractalNumber + Send + Sync + 'static>(
+        self,
+        convert: impl Fn(&BigRational) -> Result<N, String> + Send + Sync + 'static,
+    ) -> Self::Output {
+        evaluate_parallel(self.ctx, self.params, self.iterations, self.method, convert)
+    }
+}
+
+/// Evaluates a format whose type is chosen at runtime, counting events.
+struct EvaluateInstrumented<'a> {
+    ctx: &'a dyn CancelContext,
+    params: &'a CommonParams,
+    iterations: usize,
+    method: Method,
+}
+
+impl ConvertVisitor for EvaluateInstrumented<'_> {
+    type Output = Result<(EscapeVector, EventVector), String>;
+
+    fn visit<N: FractalNumber + Send + Sync + 'static>(
+        self,
+        convert: impl Fn(&BigRational) -> Result<N, String> + Send + Sync + 'static,
+    ) -> Self::Output {
+        evaluate_instrumented(self.ctx, self.params, self.iterations, self.method, convert)
+    }
+}
+
+/// Evaluates a format wrapped in Monte Carlo arithmetic.
+struct EvaluateMca<'a> {
+    ctx: &'a dyn CancelContext,
+    params: &'a CommonParams,
+    iterations: usize,
+    method: Method,
+    precision: u32,
+}
+
+impl NumberVisitor for EvaluateMca<'_> {
+    type Output = Result<EscapeVector, String>;
+
+    fn visit<N: FractalNumber + Send + Sync>(self) -> Self::Output {
+        evaluate_parallel(self.ctx, self.params, self.iterations, self.method, |r| {
+            Mca::<N>::from_bigrational(r)?.with_precision(self.precision)
+        })
+    }
+}
+
+/// Evaluates a mixed format, given its state's type.
+struct EvaluateMixed<'a> {
+    ctx: &'a dyn CancelContext,
+    params: &'a CommonParams,
+    iterations: usize,
+    coordinate: mixed::Round,
+    test: Box<dyn mixed::Test>,
+}
+
+impl ConvertVisitor for EvaluateMixed<'_> {
+    type Output = Result<EscapeVector, String>;
+
+    fn visit<N: FractalNumber + Send + Sync + 'static>(
+        self,
+        convert: impl Fn(&BigRational) -> Result<N, String> + Send + Sync + 'static,
+    ) -> Self::Output {
+        let (params, test) = (self.params, self.test.as_ref());
+        // The coordinates are rounded to their own format, then converted to the state's:
+        let convert = |r: &BigRational| convert(&(self.coordinate)(r)?);
+        let xs = make_range(&params.x, params.size.width, &convert)?;
+        let ys = make_range(&params.y, params.size.height, &convert)?;
+        evaluate_grid(self.ctx, params, xs, ys, |x, y| {
+            let escaped = |z: &Complex<N>| test.escaped(mixed::exact(z), params.fused);
+            escape_tested(x, y, self.iterations, params.fused, escaped, |_, _| ())
+        })
+    }
+}
+
+/// Evaluates the window, using `convert` to produce the input coordinates.
+fn evaluate_parallel<N>(
+    ctx: &dyn CancelContext,
+    params: &CommonParams,
+    iterations: usize,
+    method: Method,
+    convert: impl Fn(&BigRational) -> Result<N, String>,
 ) -> Result<EscapeVector, String>
 where
     N: FractalNumber + Send + Sync,
+{
+    evaluate_points(ctx, params, iterations, method, convert, |point| point())
+}
+
+/// Like `evaluate_parallel`, with each point's operation and event counts.
+fn evaluate_instrumented<N>(
+    ctx: &dyn CancelContext,
+    params: &CommonParams,
+    iterations: usize,
+    method: Method,
+    convert: impl Fn(&BigRational) -> Result<N, String>,
+) -> Result<(EscapeVector, EventVector), String>
+where
+    N: FractalNumber + Send + Sync,
+{
+    let convert = |r: &BigRational| convert(r).map(Instrumented::new);
+    let points = evaluate_points(ctx, params, iterations, method, convert, |point| {
+        instrument::count(point)
+    })?;
+    Ok(points.into_iter().unzip())
+}
+
+/// Evaluates the window, using `convert` to produce the input coordinates,
+/// and `observe` to evaluate each point.
+fn evaluate_points<N, T>(
+    ctx: &dyn CancelContext,
+    params: &CommonParams,
+    iterations: usize,
+    method: Method,
+    convert: impl Fn(&BigRational) -> Result<N, String>,
+    observe: impl Fn(&dyn Fn() -> Option<Escape>) -> T + Sync,
+) -> Result<Vec<T>, String>
+where
+    N: FractalNumber + Send + Sync,
+    T: Clone + Default + Send,
 {
     let size = params.size;
-    // Create the X and Y ranges up-front:
-    let make_range = |r: &Range<BigRational>, steps: usize| -> Result<Vec<N>, String> {
-        let step = (&r.end - &r.start) / BigRational::new(steps.into(), 1.into());
-        let mut results = Vec::with_capacity(steps);
-        let mut next = r.start.clone();
-        for _ in 0..steps {
-            let converted = N::from_bigrational(&next)?;
-            results.push(converted);
-            next += &step;
+    match method {
+        Method::Direct => {
+            // Create the X and Y ranges up-front:
+            let xs = make_range(&params.x, size.width, &convert)?;
+            let ys = make_range(&params.y, size.height, &convert)?;
+            evaluate_grid(ctx, params, xs, ys, |x, y| {
+                observe(&|| escape(x, y, iterations, params.fused))
+            })
         }
-        Ok(results)
-    };
-    let xs = make_range(&params.x, size.width)?;
-    let ys = make_range(&params.y, size.height)?;
-    let mut output: EscapeVector = Vec::new();
-    output.resize(size.width * size.height, None);
+        Method::Perturbation => {
+            let (center, reference) = reference_orbit(params, iterations);
+            let orbit = reference
+                .iter()
+                .map(|z| {
+                    Ok(Complex {
+                        re: convert(&z.re)?,
+                        im: convert(&z.im)?,
+                    })
+                })
+                .collect::<Result<Vec<_>, String>>()?;
+            // The points are offsets from the center:
+            let offset = |r: &Range<BigRational>, c: &BigRational| (&r.start - c)..(&r.end - c);
+            let dxs = make_range(&offset(&params.x, &center.re), size.width, &convert)?;
+            let dys = make_range(&offset(&params.y, &center.im), size.height, &convert)?;
+            evaluate_grid(ctx, params, dxs, dys, |dx, dy| {
+                observe(&|| escape_perturbed(dx, dy, &orbit, iterations, params.fused))
+            })
+        }
+    }
+}
+
+/// Evaluates `point` at each pair of coordinates, a row at a time.
+fn evaluate_grid<N, T>(
+    ctx: &dyn CancelContext,
+    params: &CommonParams,
+    xs: Vec<N>,
+    ys: Vec<N>,
+    point: impl Fn(&N, &N) -> T + Sync,
+) -> Result<Vec<T>, String>
+where
+    N: FractalNumber + Send + Sync,
+    T: Clone + Default + Send,
+{
+    let width = params.size.width;
+    evaluate_rows(ctx, params, ys, |row, y, row_out| {
+        xs.iter().zip(row_out).enumerate().for_each(|(col, (x, out))| {
+            if let Some(seed) = params.seed {
+                random::reseed(seed, (row * width + col) as u64);
+            }
+            *out = point(x, &y);
+        })
+    })
+}
+
+/// Evaluates each row of the output with `evaluate_row`, given its index and y coordinate.
+///
+/// A panic drops its row, except for a fixed-point `Overflow`, which fails the render.
+fn evaluate_rows<Y, T>(
+    ctx: &dyn CancelContext,
+    params: &CommonParams,
+    ys: Vec<Y>,
+    evaluate_row: impl Fn(usize, Y, &mut [T]) + Sync,
+) -> Result<Vec<T>, String>
+where
+    Y: Send,
+    T: Clone + Default + Send,
+{
+    let size = params.size;
+    let mut output: Vec<T> = Vec::new();
+    output.resize(size.width * size.height, T::default());
 
+    let overflow = OverflowSlot::default();
     let out_rows = output.chunks_mut(size.width);
     ys.into_iter()
         .zip(out_rows)
+        .enumerate()
         .par_bridge()
         .into_par_iter()
-        .for_each(|(y, row_out)| {
+        .for_each(|(row, (y, row_out))| {
             if ctx.is_canceled() {
                 return;
             }
             // Catch the unwind before it makes it out of the Rayon worker thread.
-            let result = std::panic::catch_unwind(AssertUnwindSafe(|| {
-                xs.iter().zip(row_out).for_each(|(x, out)| {
-                    *out = escape(x, &y, iterations);
-                })
-            }));
-            if result.is_err() {
-                tracing::error!("caught panic during mandelbrot evaluation");
+            let result =
+                std::panic::catch_unwind(AssertUnwindSafe(|| evaluate_row(row, y, row_out)));
+            if let Err(panic) = result {
+                if !overflow.catch(panic) {
+                    tracing::error!("caught panic during mandelbrot evaluation");
+                }
             }
         });
     if ctx.is_canceled() {
         Err("canceled".to_string())
     } else {
-
+        overflow.into_result()?;
         Ok(output)
     }
 
 }
 
+/// Evaluates the window in an MX format. Each run of `format.block` pixels in a row is one block.
+fn evaluate_mx(
+    ctx: &dyn CancelContext,
+    params: &CommonParams,
+    iterations: usize,
+    format: MxFormat,
+) -> Result<EscapeVector, String> {
+    let size = params.size;
+    let xs = make_range(&params.x, size.width, &f64::from_bigrational)?;
+    let ys = make_range(&params.y, size.height, &f64::from_bigrational)?;
+    evaluate_rows(ctx, params, ys, |_, y, row_out| {
+        for (xs, out) in xs.chunks(format.block).zip(row_out.chunks_mut(format.block)) {
+            out.copy_from_slice(&escape_block(format.element, xs, y, iterations));
+        }
+    })
+}
+
 #[inline]
-fn escape<N>(x: &N, y: &N, limit: usize) -> Option<Escape>
+fn escape<N>(x: &N, y: &N, limit: usize, fused: bool) -> Option<Escape>
 where
     N: FractalNumber,
+{
+    escape_observed(x, y, limit, fused, |_, _| ())
+}
+
+/// Like `escape`, calling `observe` with z and the result of the escape test on each iteration.
+#[inline]
+pub(crate) fn escape_observed<N>(
+    x: &N,
+    y: &N,
+    limit: usize,
+    fused: bool,
+    observe: impl FnMut(&Complex<N>, Option<bool>),
+) -> Option<Escape>
+where
+    N: FractalNumber,
+{
+    let four: N = N::from_i32(4);
+    let escaped = |z: &Complex<N>| {
+        let z_magnitude_squared = z.magnitude_squared(fused);
+
+        // The Mandelbrot "escape condition" is that the Cartesian distance from the zero point
+        // of the complex plane (0 + 0i) is at least two.
+        // Normally, that distance is sqrt(x^2+y^2) - but we can skip the square-root and avoid
+        // a trait requirement by comparing d^2 to 2^2 instead:
+        // Some formats (intervals) can't always tell; those points are reported as undecided.
+        (z_magnitude_squared.decide_ge(&four), z_magnitude_squared)
+    };
+    escape_tested(x, y, limit, fused, escaped, observe)
+}
+
+/// Like `escape_observed`, with the escape test given: whether z has escaped (None if that
+/// can't be decided), and |z|^2, in any format.
+#[inline]
+fn escape_tested<N, M>(
+    x: &N,
+    y: &N,
+    limit: usize,
+    fused: bool,
+    escaped: impl Fn(&Complex<N>) -> (Option<bool>, M),
+    mut observe: impl FnMut(&Complex<N>, Option<bool>),
+) -> Option<Escape>
+where
+    N: FractalNumber,
+    M: FractalNumber,
 {
     let mut z: Complex<N> = Complex {
         re: N::from_i32(0),
         im: N::from_i32(0),
     };
-    let four: N = N::from_i32(4);
     let coord = Complex {
         re: x.clone(),
         im: y.clone(),
     };
 
     for i in 0..limit {
-        let sq = z.square();
+        let sq = if fused { z.square_fused() } else { z.square() };
         z = sq + coord.clone();
 
-        let z_magnitude_squared = z.re.clone() * z.re.clone() + z.im.clone() * z.im.clone();
+        let (decided, z_magnitude_squared) = escaped(&z);
+        observe(&z, decided);
+        match decided {
+            Some(false) => (),
+            decided => {
+                return Some(Escape {
+                    count: i,
+                    z_magnitude_squared: z_magnitude_squared.to_f64(),
+                    undecided: decided.is_none(),
+                })
+            }
+        }
+    }
+    None
+}
 
-        // The Mandelbrot "escape condition" is that the Cartesian distance from the zero point
-        // of the complex plane (0 + 0i) is at least two.
-        // Normally, that distance is sqrt(x^2+y^2) - but we can skip the square-root and avoid
-        // a trait requirement by comparing d^2 to 2^2 instead:
-        if z_magnitude_squared >= four {
-            return Some(Escape {
-                count: i,
-                z_magnitude_squared: z_magnitude_squared.to_f64(),
-            });
+/// Computes the orbit of the window's center, at enough precision to resolve its pixels.
+///
+/// Returns the center and its orbit, starting from Z_0 = 0; the orbit ends early if it escapes.
+fn reference_orbit(params: &CommonParams, limit: usize) -> (Complex<BigRational>, Vec<Complex<BigRational>>) {
+    let two = BigRational::from_integer(2.into());
+    let center = Complex {
+        re: (&params.x.start + &params.x.end) / &two,
+        im: (&params.y.start + &params.y.end) / &two,
+    };
+
+    // Carry 64 bits past the pixel spacing, so the reference doesn't limit the deltas.
+    let depth = |r: &Range<BigRational>, steps: usize| {
+        let spacing = (&r.end - &r.start) / BigRational::from_integer(steps.max(1).into());
+        spacing.denom().bits().saturating_sub(spacing.numer().bits())
+    };
+    let depth = depth(&params.x, params.size.width).max(depth(&params.y, params.size.height));
+    let format = BigFloatFormat::new((64 + depth).min(BigFloatFormat::MAX_PRECISION))
+        .expect("precision is in range");
+
+    let coord = Complex {
+        re: BigFloat::from_rational(&center.re, format),
+        im: BigFloat::from_rational(&center.im, format),
+    };
+    let mut z = Complex {
+        re: BigFloat::zero().with_format(format),
+        im: BigFloat::zero().with_format(format),
+    };
+    let four = BigFloat::from_i64(4);
+    let mut orbit = vec![Complex {
+        re: z.re.to_rational(),
+        im: z.im.to_rational(),
+    }];
+    for _ in 0..limit {
+        z = z.square() + coord.clone();
+        orbit.push(Complex {
+            re: z.re.to_rational(),
+            im: z.im.to_rational(),
+        });
+        if z.re.clone() * z.re.clone() + z.im.clone() * z.im.clone() >= four {
+            break;
         }
     }
-    return None;
+    (center, orbit)
+}
+
+/// Like `escape`, for the point at offset (dx, dy) from the reference point with the given orbit.
+///
+/// Iterates the offset from the reference orbit: dz' = (2Z + dz)dz + dc.
+/// When the offset grows larger than the point itself, or the reference orbit runs out,
+/// the offset loses precision relative to the point (a "glitch"); so the iteration restarts
+/// from the start of the reference orbit, with the point's current value as the offset.
+fn escape_perturbed<N>(
+    dx: &N,
+    dy: &N,
+    orbit: &[Complex<N>],
+    limit: usize,
+    fused: bool,
+) -> Option<Escape>
+where
+    N: FractalNumber,
+{
+    let mut dz: Complex<N> = Complex {
+        re: N::from_i32(0),
+        im: N::from_i32(0),
+    };
+    let mut m = 0;
+    let four: N = N::from_i32(4);
+
+    for i in 0..limit {
+        let reference = &orbit[m];
+        let a = reference.re.clone() + reference.re.clone() + dz.re.clone();
+        let b = reference.im.clone() + reference.im.clone() + dz.im.clone();
+        let factor = Complex { re: a, im: b };
+        let product = if fused { factor.mul_fused(dz) } else { factor * dz };
+        dz = product
+            + Complex {
+                re: dx.clone(),
+                im: dy.clone(),
+            };
+        m += 1;
+
+        let z = Complex {
+            re: orbit[m].re.clone() + dz.re.clone(),
+            im: orbit[m].im.clone() + dz.im.clone(),
+        };
+        let z_magnitude_squared = z.magnitude_squared(fused);
+        match z_magnitude_squared.decide_ge(&four) {
+            Some(false) => (),
+            decided => {
+                return Some(Escape {
+                    count: i,
+                    z_magnitude_squared: z_magnitude_squared.to_f64(),
+                    undecided: decided.is_none(),
+                })
+            }
+        }
+
+        let dz_magnitude_squared = dz.magnitude_squared(fused);
+        if m + 1 == orbit.len() || z_magnitude_squared < dz_magnitude_squared {
+            dz = z;
+            m = 0;
+        }
+    }
+    None
+}
+
+/// Like `escape`, for a block of pixels that share an MX scale.
+///
+/// Pixels that have escaped are masked off, so they stop affecting the others' scale.
+fn escape_block(element: Element, xs: &[f64], y: f64, limit: usize) -> Vec<Option<Escape>> {
+    let lanes = xs.len();
+    let constant = |v: f64| MxBlock::splat(element, v, lanes);
+    let two = constant(2.0);
+    let coord = Complex {
+        re: MxBlock::new(element, xs),
+        im: constant(y),
+    };
+    let mut z = Complex {
+        re: constant(0.0),
+        im: constant(0.0),
+    };
+    let mut escapes = vec![None; lanes];
+
+    for i in 0..limit {
+        // As in Complex::square:
+        let re = z.re.clone() * z.re.clone() - z.im.clone() * z.im.clone();
+        let im = two.clone() * (z.re.clone() * z.im.clone());
+        z = Complex { re, im } + coord.clone();
+
+        let z_magnitude_squared = z.re.clone() * z.re.clone() + z.im.clone() * z.im.clone();
+        let mut active = z_magnitude_squared.active();
+        for (lane, escape) in escapes.iter_mut().enumerate() {
+            let magnitude = z_magnitude_squared.get(lane);
+            if active & (1 << lane) != 0 && magnitude >= 4.0 {
+                *escape = Some(Escape {
+                    count: i,
+                    z_magnitude_squared: magnitude,
+                    undecided: false,
+                });
+                active &= !(1 << lane);
+            }
+        }
+        if active == 0 {
+            break;
+        }
+        if active != z_magnitude_squared.active() {
+            z = Complex {
+                re: z.re.with_active(active),
+                im: z.im.with_active(active),
+            };
+        }
+    }
+    escapes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{test_params, NeverCancel};
+
+    fn counts(params: &CommonParams, iterations: usize, method: Method) -> Vec<Option<usize>> {
+        compute_with(&NeverCancel(), params, iterations, method)
+            .unwrap()
+            .into_iter()
+            .map(|escape| escape.map(|escape| escape.count))
+            .collect()
+    }
+
+    fn distinct(counts: &[Option<usize>]) -> usize {
+        counts.iter().collect::<std::collections::BTreeSet<_>>().len()
+    }
+
+    #[test]
+    fn test_perturbation_matches_direct() {
+        let params = test_params("f64", 16);
+        assert_eq!(
+            counts(&params, 64, Method::Perturbation),
+            counts(&params, 64, Method::Direct)
+        );
+    }
+
+    #[test]
+    fn test_rebase() {
+        // The reference, 1/2, escapes within a few iterations; points near 1/4 take longer,
+        // and points nearer the origin don't escape at all.
+        let r = |n: i64, d: i64| BigRational::new(n.into(), d.into());
+        let params = CommonParams {
+            x: r(0, 1)..r(1, 1),
+            y: r(-1, 2)..r(1, 2),
+            ..test_params("f64", 16)
+        };
+        let (_, orbit) = reference_orbit(&params, 64);
+        let direct = counts(&params, 64, Method::Direct);
+        assert!(direct.iter().any(|count| count.is_none_or(|count| count >= orbit.len())));
+        assert_eq!(counts(&params, 64, Method::Perturbation), direct);
+    }
+
+    #[test]
+    fn test_deep() {
+        // A window 2^-25 across in the seahorse valley: below f32's resolution there, but not
+        // f64's.
+        let decimal = |n: i64| BigRational::new(n.into(), 1_000_000_000_000_000i64.into());
+        let center = Complex {
+            re: decimal(-743643887037151),
+            im: decimal(131825904205330),
+        };
+        let half = BigRational::new(1.into(), (1i64 << 26).into());
+        let params = CommonParams {
+            x: &center.re - &half..&center.re + &half,
+            y: &center.im - &half..&center.im + &half,
+            ..test_params("f32", 16)
+        };
+        let reference = CommonParams {
+            numeric: "f64".to_string(),
+            ..params.clone()
+        };
+
+        let expected = counts(&reference, 1000, Method::Direct);
+        assert!(distinct(&expected) > 1);
+        // Directly, every f32 point is the same:
+        assert_eq!(distinct(&counts(&params, 1000, Method::Direct)), 1);
+        // As offsets from the reference orbit, they're distinct:
+        assert_eq!(counts(&params, 1000, Method::Perturbation), expected);
+    }
+
+    #[test]
+    fn test_mx() {
+        let params = test_params("MXFP8-E4M3", 8);
+        let counts = counts(&params, 32, Method::Direct);
+        assert!(distinct(&counts) > 1);
+        // MX blocks have no fused accumulation:
+        let fused = CommonParams {
+            fused: true,
+            ..params
+        };
+        let err = compute_with(&NeverCancel(), &fused, 32, Method::Direct).unwrap_err();
+        assert!(err.contains("fused"), "{}", err);
+    }
 }
@@ -1,3 +1,11 @@
+use std::cmp::Ordering;
+
+use num::{BigRational, ToPrimitive};
+
+use crate::multi_double::{two_prod, two_sum};
+use crate::numeric::{binary_parts, ldexp};
+use crate::random;
+
 // Sizes and masks to select the components of an IEEE f64.
 const EXPONENT: usize = 11;
 const FRACTION: usize = 52;
@@ -59,11 +67,48 @@ impl<const E: usize, const F: usize> MaskedFloat<E, F> {
         val.into()
     }
 
+    /// Rounds a rational to the nearest value, ties to even.
+    ///
+    /// Unlike the conversion from an f64, which truncates the fraction as arithmetic does,
+    /// this rounds once, from the exact value.
+    pub fn from_rational(r: &BigRational) -> Self {
+        let format = MaskedFormat {
+            exponent: E,
+            fraction: F,
+            rounding: Rounding::NearestEven,
+            specials: MaskedSpecials::Clamp,
+        };
+        let (val, error) = truncate(r);
+        Self {
+            val: format.apply(val, error),
+        }
+    }
+
     pub fn to_f64(self) -> f64 {
         self.val
     }
 }
 
+/// Truncates a rational to an f64, for rounding into a masked format: returns the f64 and
+/// the sign of the remainder, as the `error` for `round_fraction`.
+///
+/// The truncation is exact for rationals in the range of any masked format.
+fn truncate(r: &BigRational) -> (f64, f64) {
+    let Some(parts) = binary_parts(r, FRACTION as u64 + 1) else {
+        return (0.0, 0.0);
+    };
+    let magnitude = ldexp(
+        parts.significand.to_f64().unwrap_or(f64::NAN),
+        parts.exponent,
+    );
+    let val = if parts.negative {
+        -magnitude
+    } else {
+        magnitude
+    };
+    (val, if parts.sticky { val.signum() } else { 0.0 })
+}
+
 impl<const E: usize, const F: usize> From<MaskedFloat<E, F>> for f64 {
     fn from(masked: MaskedFloat<E, F>) -> Self {
         masked.val
@@ -72,38 +117,45 @@ impl<const E: usize, const F: usize> From<MaskedFloat<E, F>> for f64 {
 
 impl<const E: usize, const F: usize> From<f64> for MaskedFloat<E, F> {
     fn from(val: f64) -> Self {
-        let bits = val.to_bits();
-        let sign = bits & (SIGN_MASK | EXPONENT_SIGN_MASK);
-        let mut frac = bits & FRACTION_MASKS[F];
-        let exp = if bits & EXPONENT_SIGN_MASK != 0 {
-            if bits & EXPONENT_MASKS[E] != 0 {
-                frac = 0;
-                ((1 << E + FRACTION) - 1) & EXPONENT_MASK
-            } else {
-                bits & EXPONENT_MASK
-            }
-        } else {
-            // bits & EXPONENT_SIGN_MASK == 0
-            // Special case for zero:
-            if bits & !SIGN_MASK == 0 {
-                return Self {
-                    val: f64::from_bits(bits & SIGN_MASK),
-                };
-            }
-
-            if bits & EXPONENT_MASKS[E] != EXPONENT_MASKS[E] {
-                frac = FRACTION_MASK;
-                EXPONENT_MASKS[E]
-            } else {
-                bits & EXPONENT_MASK
-            }
-        };
         Self {
-            val: f64::from_bits(sign | exp | frac),
+            val: mask(val, E, F),
         }
     }
 }
 
+/// Applies the exponent and fraction masks for E exponent bits and F fraction bits to the value.
+///
+/// This is the masking behind both `MaskedFloat` and `DynMaskedFloat`; see `MaskedFloat` for
+/// how the exponent is clamped.
+fn mask(val: f64, e: usize, f: usize) -> f64 {
+    let bits = val.to_bits();
+    let sign = bits & (SIGN_MASK | EXPONENT_SIGN_MASK);
+    // FRACTION_MASKS has no entry for "all the fraction bits".
+    let mut frac = bits & FRACTION_MASKS.get(f).unwrap_or(&FRACTION_MASK);
+    let exp = if bits & EXPONENT_SIGN_MASK != 0 {
+        if bits & EXPONENT_MASKS[e] != 0 {
+            frac = 0;
+            ((1 << (e + FRACTION)) - 1) & EXPONENT_MASK
+        } else {
+            bits & EXPONENT_MASK
+        }
+    } else {
+        // bits & EXPONENT_SIGN_MASK == 0
+        // Special case for zero:
+        if bits & !SIGN_MASK == 0 {
+            return f64::from_bits(bits & SIGN_MASK);
+        }
+
+        if bits & EXPONENT_MASKS[e] != EXPONENT_MASKS[e] {
+            frac = FRACTION_MASK;
+            EXPONENT_MASKS[e]
+        } else {
+            bits & EXPONENT_MASK
+        }
+    };
+    f64::from_bits(sign | exp | frac)
+}
+
 impl<const E: usize, const F: usize> std::ops::Add for MaskedFloat<E, F> {
     type Output = Self;
 
@@ -138,9 +190,7 @@ impl<'a, const E: usize, const F: usize> std::ops::Mul<&'a MaskedFloat<E, F>>
     }
 }
 
-impl<'a, const E: usize, const F: usize> std::ops::Add<&'a MaskedFloat<E, F>>
-    for MaskedFloat<E, F>
-{
+impl<const E: usize, const F: usize> std::ops::Add<&MaskedFloat<E, F>> for MaskedFloat<E, F> {
     type Output = MaskedFloat<E, F>;
 
     fn add(self, other: &'_ MaskedFloat<E, F>) -> MaskedFloat<E, F> {
@@ -156,6 +206,513 @@ impl<const E: usize, const F: usize> std::ops::Div for MaskedFloat<E, F> {
     }
 }
 
+/// How a `DynMaskedFloat` discards fraction bits.
+///
+/// Selected by an optional third argument to the format name, e.g. `MaskedFloat<4,10,RNE>`.
+/// Apart from `Truncate`, the modes round the exact result of each operation,
+/// not the f64 result.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Rounding {
+    /// Clear the discarded bits of the f64 result, as `MaskedFloat` does. This is the default.
+    #[default]
+    Truncate,
+    /// `RZ`: round toward zero.
+    TowardZero,
+    /// `RNE`: round to nearest, ties to even.
+    NearestEven,
+    /// `RU`: round toward positive infinity.
+    Up,
+    /// `RD`: round toward negative infinity.
+    Down,
+    /// `SR`: round away from zero with probability proportional to the discarded fraction.
+    Stochastic,
+}
+
+impl Rounding {
+    const NAMES: [(Rounding, &str); 5] = [
+        (Rounding::TowardZero, "RZ"),
+        (Rounding::NearestEven, "RNE"),
+        (Rounding::Up, "RU"),
+        (Rounding::Down, "RD"),
+        (Rounding::Stochastic, "SR"),
+    ];
+
+    /// The name used in format strings; None for the default.
+    pub fn name(&self) -> Option<&'static str> {
+        Self::NAMES
+            .iter()
+            .find(|(mode, _)| mode == self)
+            .map(|(_, name)| *name)
+    }
+}
+
+impl std::str::FromStr for Rounding {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::NAMES
+            .iter()
+            .find(|(_, name)| *name == s.trim())
+            .map(|(mode, _)| *mode)
+            .ok_or_else(|| {
+                let names: Vec<_> = Self::NAMES.iter().map(|(_, name)| *name).collect();
+                format!(
+                    "unknown rounding mode '{}'; expected one of {}",
+                    s,
+                    names.join(", ")
+                )
+            })
+    }
+}
+
+/// How a `DynMaskedFloat` represents values outside its normal range.
+///
+/// Selected by an optional last argument to the format name, e.g. `MaskedFloat<4,9,RNE,IEEE>`.
+/// Apart from `Clamp`, the modes give `MaskedFloat<E,F>` the values of an IEEE 754 format with
+/// E+1 exponent bits (and, as `mask` keeps, F+1 fraction bits): `MaskedFloat<4,9,RNE,IEEE>`
+/// emulates binary16.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum MaskedSpecials {
+    /// Clamp the exponent, as `MaskedFloat` does: there are no subnormals, infinities or NaNs.
+    /// This is the default.
+    #[default]
+    Clamp,
+    /// `IEEE`: gradual underflow through subnormals, overflow to infinity, and NaN propagation.
+    /// As in IEEE 754, overflow rounding toward zero gives the largest finite value instead.
+    Ieee,
+    /// `FTZ`: as `IEEE`, but results that round to a subnormal are flushed to zero.
+    FlushToZero,
+    /// `DAZ`: as `IEEE`, but subnormal operands (including those compared) are read as zero.
+    DenormalsAreZero,
+}
+
+impl MaskedSpecials {
+    const NAMES: [(MaskedSpecials, &str); 3] = [
+        (MaskedSpecials::Ieee, "IEEE"),
+        (MaskedSpecials::FlushToZero, "FTZ"),
+        (MaskedSpecials::DenormalsAreZero, "DAZ"),
+    ];
+
+    /// The name used in format strings; None for the default.
+    pub fn name(&self) -> Option<&'static str> {
+        Self::NAMES
+            .iter()
+            .find(|(mode, _)| mode == self)
+            .map(|(_, name)| *name)
+    }
+}
+
+impl std::str::FromStr for MaskedSpecials {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::NAMES
+            .iter()
+            .find(|(_, name)| *name == s.trim())
+            .map(|(mode, _)| *mode)
+            .ok_or_else(|| {
+                let names: Vec<_> = Self::NAMES.iter().map(|(_, name)| *name).collect();
+                format!(
+                    "unknown special-value mode '{}'; expected one of {}",
+                    s,
+                    names.join(", ")
+                )
+            })
+    }
+}
+
+/// Rounds the value to the fraction bits kept by `mask` for F, leaving the exponent alone.
+///
+/// The exact value being rounded is `val + error`, where `error` is the rounding error of the
+/// f64 operation that produced `val` (zero if it was exact). Only its sign is used: it is much
+/// smaller than the last place of `val`, so it only matters where `val` itself is on a boundary.
+fn round_fraction(val: f64, error: f64, f: usize, rounding: Rounding) -> f64 {
+    if !val.is_finite() || val == 0.0 {
+        return val;
+    }
+    let bits = val.to_bits();
+    let negative = bits & SIGN_MASK != 0;
+    // The lowest bit `mask` keeps: FRACTION_MASKS[f] keeps F+1 bits.
+    let ulp = 1u64
+        << FRACTION_MASKS
+            .get(f)
+            .unwrap_or(&FRACTION_MASK)
+            .trailing_zeros();
+    // The magnitude of the exact value is `truncated + dropped + epsilon`,
+    // for some infinitesimal epsilon with sign `tail`.
+    let mut truncated = bits & !(ulp - 1);
+    let mut dropped = bits & (ulp - 1);
+    let tail = tail(error, negative);
+    if rounding == Rounding::Truncate {
+        return f64::from_bits(truncated);
+    }
+    if dropped == 0 && tail == Ordering::Less {
+        // Just below a representable value: truncation goes to the one below that.
+        // Decrementing the bit pattern steps the magnitude down, even across a binade.
+        truncated -= ulp;
+        dropped = ulp;
+    }
+    let away = rounds_away(
+        rounding,
+        negative,
+        (2 * dropped).cmp(&ulp).then(tail),
+        dropped != 0 || tail != Ordering::Equal,
+        truncated & ulp != 0,
+        || random::next_u64() & (ulp - 1) < dropped,
+    );
+    // Incrementing the bit pattern steps the magnitude up, carrying into the exponent.
+    f64::from_bits(if away { truncated + ulp } else { truncated })
+}
+
+/// Rounds the value to a multiple of `2^quantum`, as `round_fraction` rounds to the fraction
+/// bits: for the subnormals of an IEEE format, whose last place is fixed.
+///
+/// The value's magnitude must be less than `2^(quantum + 53)`.
+fn round_subnormal(val: f64, error: f64, quantum: i64, rounding: Rounding) -> f64 {
+    let negative = val.is_sign_negative();
+    // In units of the last place, the magnitude's integer and fractional parts are both exact.
+    let scaled = ldexp(val.abs(), -quantum);
+    let mut truncated = scaled.trunc();
+    let mut dropped = scaled - truncated;
+    let tail = tail(error, negative);
+    if rounding != Rounding::Truncate && dropped == 0.0 && tail == Ordering::Less {
+        truncated -= 1.0;
+        dropped = 1.0;
+    }
+    let away = rounds_away(
+        rounding,
+        negative,
+        (2.0 * dropped).total_cmp(&1.0).then(tail),
+        dropped != 0.0 || tail != Ordering::Equal,
+        truncated % 2.0 == 1.0,
+        || ldexp((random::next_u64() >> 11) as f64, -53) < dropped,
+    );
+    let magnitude = ldexp(if away { truncated + 1.0 } else { truncated }, quantum);
+    if negative {
+        -magnitude
+    } else {
+        magnitude
+    }
+}
+
+/// How the exact magnitude compares with the rounded f64 one, given the f64 operation's `error`.
+fn tail(error: f64, negative: bool) -> Ordering {
+    match (error.partial_cmp(&0.0), negative) {
+        (Some(Ordering::Greater), false) | (Some(Ordering::Less), true) => Ordering::Greater,
+        (Some(Ordering::Greater), true) | (Some(Ordering::Less), false) => Ordering::Less,
+        _ => Ordering::Equal,
+    }
+}
+
+/// Whether rounding takes a truncated magnitude up to the next representable one.
+///
+/// `half` compares the discarded part (including any infinitesimal tail) with half the step
+/// between them; `odd` is whether the truncated magnitude is odd in its last place.
+/// `stochastic` draws the decision for `Rounding::Stochastic`.
+fn rounds_away(
+    rounding: Rounding,
+    negative: bool,
+    half: Ordering,
+    inexact: bool,
+    odd: bool,
+    stochastic: impl FnOnce() -> bool,
+) -> bool {
+    match rounding {
+        Rounding::Truncate | Rounding::TowardZero => false,
+        Rounding::NearestEven => match half {
+            Ordering::Greater => true,
+            Ordering::Less => false,
+            Ordering::Equal => odd,
+        },
+        Rounding::Up => inexact && !negative,
+        Rounding::Down => inexact && negative,
+        Rounding::Stochastic => stochastic(),
+    }
+}
+
+/// Exponent and fraction widths for a `DynMaskedFloat`.
+///
+/// Parsed from the same name as the const-generic type, e.g. `MaskedFloat<4,50>`,
+/// optionally with a rounding mode, special-value mode, or both: `MaskedFloat<4,50,RNE>`,
+/// `MaskedFloat<4,50,IEEE>`, `MaskedFloat<4,50,RNE,FTZ>`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct MaskedFormat {
+    pub exponent: usize,
+    pub fraction: usize,
+    pub rounding: Rounding,
+    pub specials: MaskedSpecials,
+}
+
+impl MaskedFormat {
+    pub fn new(exponent: usize, fraction: usize) -> Result<Self, String> {
+        if exponent >= EXPONENT {
+            return Err(format!(
+                "MaskedFloat exponent must be at most {} bits, got {}",
+                EXPONENT - 1,
+                exponent
+            ));
+        }
+        if fraction > FRACTION {
+            return Err(format!(
+                "MaskedFloat fraction must be at most {} bits, got {}",
+                FRACTION, fraction
+            ));
+        }
+        Ok(MaskedFormat {
+            exponent,
+            fraction,
+            rounding: Rounding::default(),
+            specials: MaskedSpecials::default(),
+        })
+    }
+
+    pub fn with_rounding(self, rounding: Rounding) -> Self {
+        MaskedFormat { rounding, ..self }
+    }
+
+    pub fn with_specials(self, specials: MaskedSpecials) -> Self {
+        MaskedFormat { specials, ..self }
+    }
+
+    /// Rounds and masks a value into this format. `error` is as for `round_fraction`.
+    fn apply(&self, val: f64, error: f64) -> f64 {
+        if self.specials == MaskedSpecials::Clamp {
+            return mask(
+                round_fraction(val, error, self.fraction, self.rounding),
+                self.exponent,
+                self.fraction,
+            );
+        }
+        if !val.is_finite() || val == 0.0 {
+            return val;
+        }
+        // The IEEE format with one more exponent bit than `mask` clamps to:
+        let min_normal = ldexp(1.0, self.min_exponent());
+        let rounded = if val.abs() < min_normal {
+            let digits = (self.fraction as i64 + 1).min(FRACTION as i64);
+            round_subnormal(val, error, self.min_exponent() - digits, self.rounding)
+        } else {
+            round_fraction(val, error, self.fraction, self.rounding)
+        };
+        let max = self.max_finite();
+        if self.specials == MaskedSpecials::FlushToZero && rounded.abs() < min_normal {
+            0.0f64.copysign(val)
+        } else if rounded.abs() > max {
+            let toward_zero = match self.rounding {
+                Rounding::Truncate | Rounding::TowardZero => true,
+                Rounding::Up => val < 0.0,
+                Rounding::Down => val > 0.0,
+                Rounding::NearestEven | Rounding::Stochastic => false,
+            };
+            if toward_zero { max } else { f64::INFINITY }.copysign(val)
+        } else {
+            rounded
+        }
+    }
+
+    /// Reads an operand in this format: `DenormalsAreZero` reads subnormals as zero.
+    fn read(&self, val: f64) -> f64 {
+        if self.specials == MaskedSpecials::DenormalsAreZero
+            && val.abs() < ldexp(1.0, self.min_exponent())
+        {
+            0.0f64.copysign(val)
+        } else {
+            val
+        }
+    }
+
+    /// The exponent of the smallest normal value, with IEEE special values.
+    fn min_exponent(&self) -> i64 {
+        2 - (1 << self.exponent)
+    }
+
+    /// The largest finite value, with IEEE special values.
+    fn max_finite(&self) -> f64 {
+        let digits = (self.fraction as i64 + 1).min(FRACTION as i64);
+        ldexp(2.0 - ldexp(1.0, -digits), 1 - self.min_exponent())
+    }
+}
+
+impl std::str::FromStr for MaskedFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let args = s
+            .trim()
+            .strip_prefix("MaskedFloat<")
+            .and_then(|rest| rest.strip_suffix('>'))
+            .ok_or_else(|| format!("'{}' is not of the form MaskedFloat<E,F>", s))?;
+        let args: Vec<&str> = args.split(',').collect();
+        let (e, f, rounding, specials) = match args[..] {
+            [e, f] => (e, f, Rounding::default(), MaskedSpecials::default()),
+            // A single mode is the special-value mode if it parses as one.
+            [e, f, mode] => match mode.parse() {
+                Ok(specials) => (e, f, Rounding::default(), specials),
+                Err(_) => (e, f, mode.parse()?, MaskedSpecials::default()),
+            },
+            [e, f, rounding, specials] => (e, f, rounding.parse()?, specials.parse()?),
+            _ => return Err(format!("'{}' is not of the form MaskedFloat<E,F>", s)),
+        };
+        let parse = |v: &str| {
+            v.trim()
+                .parse::<usize>()
+                .map_err(|err| format!("invalid width '{}' in '{}': {}", v, s, err))
+        };
+        Ok(MaskedFormat::new(parse(e)?, parse(f)?)?
+            .with_rounding(rounding)
+            .with_specials(specials))
+    }
+}
+
+impl std::fmt::Display for MaskedFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "MaskedFloat<{},{}", self.exponent, self.fraction)?;
+        if let Some(name) = self.rounding.name() {
+            write!(f, ",{}", name)?;
+        }
+        if let Some(name) = self.specials.name() {
+            write!(f, ",{}", name)?;
+        }
+        write!(f, ">")
+    }
+}
+
+/// Variant of `MaskedFloat` whose exponent and fraction widths are chosen at runtime.
+///
+/// Each value carries its format. Values without a format- the constants produced by
+/// `FractalNumber::from_i32`, and unformatted conversions- are treated as exact, and take on
+/// the format of whatever they're combined or compared with.
+/// Use `with_format` to put a value (e.g. an input coordinate) into a specific format.
+#[derive(Copy, Clone, Debug)]
+pub struct DynMaskedFloat {
+    val: f64,
+    format: Option<MaskedFormat>,
+}
+
+impl DynMaskedFloat {
+    /// Creates an unformatted (exact) value.
+    pub fn new(val: f64) -> Self {
+        DynMaskedFloat { val, format: None }
+    }
+
+    /// Rounds a rational into the given format, once.
+    ///
+    /// This uses the format's rounding mode, except that `Truncate` (which describes how
+    /// arithmetic discards the bits of an f64 result) rounds to nearest-even, as
+    /// `MaskedFloat::from_rational` does.
+    pub fn from_rational(r: &BigRational, format: MaskedFormat) -> Self {
+        let rounding = match format.rounding {
+            Rounding::Truncate => Rounding::NearestEven,
+            rounding => rounding,
+        };
+        let (val, error) = truncate(r);
+        DynMaskedFloat {
+            val: format.with_rounding(rounding).apply(val, error),
+            format: Some(format),
+        }
+    }
+
+    /// Masks this value to the given format.
+    pub fn with_format(self, format: MaskedFormat) -> Self {
+        DynMaskedFloat {
+            val: format.apply(self.val, 0.0),
+            format: Some(format),
+        }
+    }
+
+    pub fn format(&self) -> Option<MaskedFormat> {
+        self.format
+    }
+
+    pub fn to_f64(self) -> f64 {
+        self.val
+    }
+
+    /// Produces the result of a binary operation, in whichever format the operands are in.
+    /// The operation returns its f64 result and that result's rounding error.
+    fn combine(self, other: Self, op: impl FnOnce(f64, f64) -> (f64, f64)) -> Self {
+        let format = self.format.or(other.format);
+        let read = |val: f64| format.map_or(val, |format| format.read(val));
+        let (val, error) = op(read(self.val), read(other.val));
+        match format {
+            Some(format) => DynMaskedFloat {
+                val: format.apply(val, error),
+                format: Some(format),
+            },
+            None => DynMaskedFloat::new(val),
+        }
+    }
+
+    /// Both values, masked to the same format.
+    fn common(&self, other: &Self) -> (f64, f64) {
+        match self.format.or(other.format) {
+            Some(format) => (
+                format.read(format.apply(self.val, 0.0)),
+                format.read(format.apply(other.val, 0.0)),
+            ),
+            None => (self.val, other.val),
+        }
+    }
+}
+
+impl From<DynMaskedFloat> for f64 {
+    fn from(masked: DynMaskedFloat) -> Self {
+        masked.val
+    }
+}
+
+impl PartialEq for DynMaskedFloat {
+    fn eq(&self, other: &Self) -> bool {
+        let (a, b) = self.common(other);
+        a == b
+    }
+}
+
+impl PartialOrd for DynMaskedFloat {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        let (a, b) = self.common(other);
+        a.partial_cmp(&b)
+    }
+}
+
+impl std::ops::Add for DynMaskedFloat {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        self.combine(other, two_sum)
+    }
+}
+
+impl std::ops::Sub for DynMaskedFloat {
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self {
+        self.combine(other, |a, b| two_sum(a, -b))
+    }
+}
+
+impl std::ops::Mul for DynMaskedFloat {
+    type Output = Self;
+
+    fn mul(self, other: Self) -> Self {
+        self.combine(other, two_prod)
+    }
+}
+
+impl std::ops::Div for DynMaskedFloat {
+    type Output = Self;
+
+    fn div(self, other: Self) -> Self {
+        self.combine(other, |a, b| {
+            let q = a / b;
+            // The residual a - q*b is exact, and has the sign of the quotient's error (times b's).
+            let (p, e) = two_prod(q, b);
+            let residual = (a - p) - e;
+            (q, residual * b.signum())
+        })
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -246,4 +803,270 @@ mod tests {
         assert_eq!(T::new(0.0).to_f64().to_bits(), 0.0f64.to_bits());
         assert_eq!(T::new(-0.0).to_f64().to_bits(), (-0.0f64).to_bits());
     }
+
+    #[test]
+    fn test_dynamic_matches_static() {
+        let format: MaskedFormat = "MaskedFloat<3,50>".parse().unwrap();
+        let values = [
+            0.0, -0.0, 1.0, -1.5, 0.0001, -0.0001, 65_504.0, 1e-30, 1e30, 0.1,
+        ];
+        for a in values {
+            for b in values {
+                let (sa, sb) = (MaskedFloat::<3, 50>::new(a), MaskedFloat::<3, 50>::new(b));
+                let (da, db) = (
+                    DynMaskedFloat::new(a).with_format(format),
+                    DynMaskedFloat::new(b).with_format(format),
+                );
+                assert_eq!((sa + sb).to_f64().to_bits(), (da + db).to_f64().to_bits());
+                assert_eq!((sa - sb).to_f64().to_bits(), (da - db).to_f64().to_bits());
+                assert_eq!((sa * sb).to_f64().to_bits(), (da * db).to_f64().to_bits());
+                assert_eq!(sa.partial_cmp(&sb), da.partial_cmp(&db));
+            }
+        }
+    }
+
+    #[test]
+    fn test_dynamic_constants_take_format() {
+        let format = MaskedFormat::new(6, 3).unwrap();
+        let x = DynMaskedFloat::new(0.3).with_format(format);
+        let two = DynMaskedFloat::new(2.0);
+        let product = two * x;
+        assert_eq!(product.format(), Some(format));
+        assert_eq!(
+            product.to_f64(),
+            MaskedFloat::<6, 3>::new(2.0 * x.to_f64()).to_f64()
+        );
+    }
+
+    #[test]
+    fn test_parse_format() {
+        assert_eq!(
+            "MaskedFloat<4, 50>".parse::<MaskedFormat>(),
+            Ok(MaskedFormat {
+                exponent: 4,
+                fraction: 50,
+                rounding: Rounding::Truncate,
+                specials: MaskedSpecials::Clamp,
+            })
+        );
+        assert_eq!(
+            "MaskedFloat<4,50, RNE>"
+                .parse::<MaskedFormat>()
+                .unwrap()
+                .to_string(),
+            "MaskedFloat<4,50,RNE>"
+        );
+        assert!("MaskedFloat<4,50,RNA>".parse::<MaskedFormat>().is_err());
+        for name in [
+            "MaskedFloat<4,9,IEEE>",
+            "MaskedFloat<4,9,RNE,FTZ>",
+            "MaskedFloat<4,9,RZ,DAZ>",
+        ] {
+            assert_eq!(name.parse::<MaskedFormat>().unwrap().to_string(), name);
+        }
+        // The rounding mode comes first.
+        assert!("MaskedFloat<4,9,IEEE,RNE>".parse::<MaskedFormat>().is_err());
+        assert_eq!(
+            "MaskedFloat<10,52>"
+                .parse::<MaskedFormat>()
+                .unwrap()
+                .to_string(),
+            "MaskedFloat<10,52>"
+        );
+        assert!("MaskedFloat<11,3>".parse::<MaskedFormat>().is_err());
+        assert!("MaskedFloat<4,53>".parse::<MaskedFormat>().is_err());
+        assert!("MaskedFloat<4>".parse::<MaskedFormat>().is_err());
+        assert!("P16".parse::<MaskedFormat>().is_err());
+    }
+
+    #[test]
+    fn test_rounding_modes() {
+        let round = |val: f64, rounding: &str| {
+            let format: MaskedFormat = format!("MaskedFloat<8,1,{}>", rounding).parse().unwrap();
+            DynMaskedFloat::new(val).with_format(format).to_f64()
+        };
+        // MaskedFloat<8,1> keeps 2 fraction bits (as `mask` does), so
+        // representable values near 1 are 1, 1.25, 1.5, 1.75, 2.
+        assert_eq!(round(1.1, "RZ"), 1.0);
+        assert_eq!(round(1.1, "RNE"), 1.0);
+        assert_eq!(round(1.1, "RU"), 1.25);
+        assert_eq!(round(1.1, "RD"), 1.0);
+        assert_eq!(round(-1.1, "RZ"), -1.0);
+        assert_eq!(round(-1.1, "RU"), -1.0);
+        assert_eq!(round(-1.1, "RD"), -1.25);
+        // Ties go to even; carries go into the exponent.
+        assert_eq!(round(1.125, "RNE"), 1.0);
+        assert_eq!(round(1.375, "RNE"), 1.5);
+        assert_eq!(round(1.9, "RNE"), 2.0);
+        assert_eq!(round(1.9, "RU"), 2.0);
+        assert_eq!(round(-1.9, "RD"), -2.0);
+        // Representable values are unchanged:
+        for mode in ["RZ", "RNE", "RU", "RD", "SR"] {
+            assert_eq!(round(1.75, mode), 1.75);
+            assert_eq!(round(0.0, mode), 0.0);
+        }
+    }
+
+    #[test]
+    fn test_rounding_uses_exact_result() {
+        // At 52 fraction bits, the f64 result is already rounded to nearest;
+        // directed modes must still round the exact result.
+        let format = |mode: &str| -> MaskedFormat {
+            format!("MaskedFloat<10,52,{}>", mode).parse().unwrap()
+        };
+        let value = |v: f64, mode: &str| DynMaskedFloat::new(v).with_format(format(mode));
+        let (one, tiny) = (1.0, 2f64.powi(-60));
+        assert_eq!((value(one, "RNE") + value(tiny, "RNE")).to_f64(), 1.0);
+        assert_eq!((value(one, "RZ") + value(tiny, "RZ")).to_f64(), 1.0);
+        assert_eq!(
+            (value(one, "RU") + value(tiny, "RU")).to_f64(),
+            1.0 + f64::EPSILON
+        );
+        assert_eq!(
+            (value(one, "RD") - value(tiny, "RD")).to_f64(),
+            1.0 - f64::EPSILON / 2.0
+        );
+        assert_eq!(
+            (value(one, "RZ") - value(tiny, "RZ")).to_f64(),
+            1.0 - f64::EPSILON / 2.0
+        );
+        // 1/3 is below its nearest f64; 2/3 above.
+        let third = value(1.0, "RU") / value(3.0, "RU");
+        assert!(third.to_f64() > 1.0 / 3.0);
+        let third = value(1.0, "RD") / value(3.0, "RD");
+        assert_eq!(third.to_f64(), 1.0 / 3.0);
+        // 0.1 * 0.1 rounds up to its nearest f64.
+        let product = value(0.1, "RD") * value(0.1, "RD");
+        assert!(product.to_f64() < 0.1 * 0.1);
+        let product = value(0.1, "RU") * value(0.1, "RU");
+        assert_eq!(product.to_f64(), 0.1 * 0.1);
+    }
+
+    #[test]
+    fn test_from_rational_rounds_once() {
+        // Just above the tie between 1 and 1.25 in MaskedFloat<8,1>. Its nearest f64 is the tie
+        // itself, which would then round down to even.
+        let r =
+            BigRational::new(9.into(), 8.into()) + BigRational::new(1.into(), (1i64 << 60).into());
+        assert_eq!(MaskedFloat::<8, 1>::from_rational(&r).to_f64(), 1.25);
+        let format = |name: &str| -> MaskedFormat { name.parse().unwrap() };
+        let value = DynMaskedFloat::from_rational(&r, format("MaskedFloat<8,1>"));
+        assert_eq!(value.to_f64(), 1.25);
+        assert_eq!(value.format(), Some(format("MaskedFloat<8,1>")));
+        let value = DynMaskedFloat::from_rational(&r, format("MaskedFloat<8,1,RZ>"));
+        assert_eq!(value.to_f64(), 1.0);
+        let value = DynMaskedFloat::from_rational(&-r, format("MaskedFloat<8,1,RD>"));
+        assert_eq!(value.to_f64(), -1.25);
+    }
+
+    #[test]
+    fn test_ieee_matches_binary16() {
+        use crate::small_float::Binary16;
+
+        let format: MaskedFormat = "MaskedFloat<4,9,RNE,IEEE>".parse().unwrap();
+        let tiny = 2f64.powi(-24);
+        let values = [
+            0.0,
+            -0.0,
+            1.0,
+            -1.5,
+            0.1,
+            3.0,
+            1000.5,
+            65_504.0,
+            -65_504.0,
+            2f64.powi(-14),
+            tiny,
+            -3.0 * tiny,
+            1023.0 * tiny,
+            0.5,
+            f64::INFINITY,
+            f64::NAN,
+        ];
+        let same = |a: f64, b: f64| (a.is_nan() && b.is_nan()) || a.to_bits() == b.to_bits();
+        for a in values {
+            for b in values {
+                let (ma, mb) = (
+                    DynMaskedFloat::new(a).with_format(format),
+                    DynMaskedFloat::new(b).with_format(format),
+                );
+                let (ha, hb) = (Binary16::from_f64(a), Binary16::from_f64(b));
+                let results = [
+                    ((ma + mb).to_f64(), (ha + hb).to_f64()),
+                    ((ma - mb).to_f64(), (ha - hb).to_f64()),
+                    ((ma * mb).to_f64(), (ha * hb).to_f64()),
+                    ((ma / mb).to_f64(), (ha / hb).to_f64()),
+                ];
+                for (i, (masked, half)) in results.into_iter().enumerate() {
+                    assert!(
+                        same(masked, half),
+                        "op {} on {}, {}: {} vs {}",
+                        i,
+                        a,
+                        b,
+                        masked,
+                        half
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_specials() {
+        let value = |v: f64, name: &str| {
+            let format: MaskedFormat = name.parse().unwrap();
+            DynMaskedFloat::new(v).with_format(format)
+        };
+        // The default clamps: overflow and NaN are finite, and there are no subnormals.
+        let (big, zero) = (
+            value(65_504.0, "MaskedFloat<4,9>"),
+            value(0.0, "MaskedFloat<4,9>"),
+        );
+        assert!((big * big).to_f64().is_finite());
+        assert!((zero / zero).to_f64().is_finite());
+
+        // IEEE: overflow goes to infinity (or the largest value, toward zero); NaN propagates.
+        let big = value(65_504.0, "MaskedFloat<4,9,RNE,IEEE>");
+        assert_eq!((big * big).to_f64(), f64::INFINITY);
+        let negative = value(-2.0, "MaskedFloat<4,9,RNE,IEEE>");
+        assert_eq!((big * negative).to_f64(), f64::NEG_INFINITY);
+        let big = value(65_504.0, "MaskedFloat<4,9,RZ,IEEE>");
+        assert_eq!((big * big).to_f64(), 65_504.0);
+        let zero = value(0.0, "MaskedFloat<4,9,IEEE>");
+        let one = value(1.0, "MaskedFloat<4,9,IEEE>");
+        assert!(((zero / zero) + one).to_f64().is_nan());
+
+        // Halving the smallest normal value underflows gradually, unless flushed.
+        let min = 2f64.powi(-14);
+        let half = |name: &str| value(min, name) * value(0.5, name);
+        assert_eq!(half("MaskedFloat<4,9,RNE,IEEE>").to_f64(), min / 2.0);
+        assert_eq!(half("MaskedFloat<4,9,RNE,FTZ>").to_f64(), 0.0);
+        // With DAZ, the subnormal result is kept, but reads as zero.
+        let subnormal = half("MaskedFloat<4,9,RNE,DAZ>");
+        assert_eq!(subnormal.to_f64(), min / 2.0);
+        let zero = value(0.0, "MaskedFloat<4,9,RNE,DAZ>");
+        assert_eq!(subnormal, zero);
+        assert_eq!((subnormal + subnormal).to_f64(), 0.0);
+    }
+
+    #[test]
+    fn test_stochastic_rounding() {
+        // 1.1 is 40% of the way from 1 to 1.25; on average, it should round up 40% of the time.
+        let format: MaskedFormat = "MaskedFloat<8,1,SR>".parse().unwrap();
+        let trials = 10_000;
+        let up = (0..trials)
+            .map(|_| DynMaskedFloat::new(1.1).with_format(format).to_f64())
+            .filter(|v| {
+                assert!(*v == 1.0 || *v == 1.25);
+                *v == 1.25
+            })
+            .count();
+        let fraction = up as f64 / trials as f64;
+        assert!(
+            (fraction - 0.4).abs() < 0.03,
+            "rounded up {} of the time",
+            fraction
+        );
+    }
 }
@@ -0,0 +1,382 @@
+//! Monte Carlo arithmetic: a wrapper that randomly perturbs the result of every operation.
+//!
+//! `Mca<N>` computes each operation in N, then multiplies the result by `1 + δ`, with δ drawn
+//! uniformly from `[-2^-t, 2^-t)` for a virtual precision t. A single render shows how the
+//! fractal looks with t-bit noise; the variance across several seeded renders
+//! (see `mandelbrot::sensitivity` and `newton::sensitivity`) maps where the result is sensitive
+//! to rounding, without a high-precision reference.
+//!
+//! Formats are named `MCA<inner,t>`, e.g. `MCA<f64,24>`, for any of the `INNER_FORMATS`.
+
+use std::cmp::Ordering;
+use std::fmt::Display;
+use std::ops::{Add, Div, Mul, RangeInclusive, Sub};
+use std::str::FromStr;
+
+use num::BigRational;
+
+use crate::multi_double::DoubleDouble;
+use crate::number::{FractalNumber, NumberVisitor};
+use crate::numeric::FromRational;
+use crate::random;
+use crate::small_float::{BFloat16, Binary16, Tf32};
+use crate::{CommonParams, SensitivityVector};
+
+/// Bits of randomness in each perturbation.
+const DELTA_BITS: u32 = 8;
+
+/// The virtual precisions, t. Past 2^-1074, the smallest f64, perturbations of the inner formats
+/// are lost in rounding anyway.
+pub const PRECISIONS: RangeInclusive<u32> = 1..=1100;
+
+/// A value whose arithmetic is perturbed at a virtual precision.
+///
+/// As with `DynMaskedFloat`, values without a precision- the constants produced by
+/// `FractalNumber::from_i32`, and plain conversions- are exact, and take on the precision of
+/// whatever they're combined with. Use `with_precision` to perturb a value's results.
+#[derive(Clone, Debug)]
+pub struct Mca<N> {
+    value: N,
+    /// `2^-(t + DELTA_BITS - 1)`: the perturbation is a random multiple of this.
+    unit: Option<N>,
+}
+
+impl<N: FractalNumber> Mca<N> {
+    /// Creates an exact value.
+    pub fn new(value: N) -> Self {
+        Mca { value, unit: None }
+    }
+
+    /// Perturbs the results of operations on this value at virtual precision `t`; an error if
+    /// t isn't one of the `PRECISIONS`.
+    pub fn with_precision(self, t: u32) -> Result<Self, String> {
+        // Build the unit from steps small enough for narrow formats to represent.
+        let mut unit = N::from_i32(1);
+        let mut shift = t
+            .checked_add(DELTA_BITS - 1)
+            .filter(|_| PRECISIONS.contains(&t))
+            .ok_or_else(|| precision_error(t))?;
+        while shift > 0 {
+            let step = shift.min(8);
+            unit = unit / N::from_i32(1 << step);
+            shift -= step;
+        }
+        Ok(Mca {
+            value: self.value,
+            unit: Some(unit),
+        })
+    }
+
+    pub fn value(&self) -> &N {
+        &self.value
+    }
+
+    /// Applies the operation to both values, and perturbs the result.
+    fn combine(self, other: Self, op: impl FnOnce(N, N) -> N) -> Self {
+        let unit = self.unit.or(other.unit);
+        let value = op(self.value, other.value);
+        Self::perturb(value, unit)
+    }
+
+    /// Applies a fused operation (see `FractalNumber::fused_add_products`) to the values, and
+    /// perturbs its one result; None if N has no fused accumulation.
+    pub(crate) fn fuse(
+        values: [&Self; 4],
+        op: impl FnOnce(&N, &N, &N, &N) -> Option<N>,
+    ) -> Option<Self> {
+        let [a, b, c, d] = values;
+        let value = op(&a.value, &b.value, &c.value, &d.value)?;
+        let unit = values.iter().find_map(|v| v.unit.clone());
+        Some(Self::perturb(value, unit))
+    }
+
+    fn perturb(value: N, unit: Option<N>) -> Self {
+        let value = match &unit {
+            Some(unit) => {
+                let xi = (random::next_u64() >> (64 - DELTA_BITS)) as i32 - (1 << (DELTA_BITS - 1));
+                let delta = N::from_i32(xi) * unit.clone();
+                value.clone() + value * delta
+            }
+            None => value,
+        };
+        Mca { value, unit }
+    }
+}
+
+impl<N: FractalNumber> From<Mca<N>> for f64 {
+    fn from(v: Mca<N>) -> Self {
+        v.value.to_f64()
+    }
+}
+
+impl<N: FractalNumber> FromRational for Mca<N> {
+    fn from_bigrational(r: &BigRational) -> Result<Self, String> {
+        Ok(Mca::new(N::from_bigrational(r)?))
+    }
+}
+
+impl<N: FractalNumber> PartialEq for Mca<N> {
+    fn eq(&self, other: &Self) -> bool {
+        self.value == other.value
+    }
+}
+
+impl<N: FractalNumber> PartialOrd for Mca<N> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        self.value.partial_cmp(&other.value)
+    }
+}
+
+impl<N: FractalNumber> Add for Mca<N> {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        self.combine(other, |a, b| a + b)
+    }
+}
+
+impl<N: FractalNumber> Sub for Mca<N> {
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self {
+        self.combine(other, |a, b| a - b)
+    }
+}
+
+impl<N: FractalNumber> Mul for Mca<N> {
+    type Output = Self;
+
+    fn mul(self, other: Self) -> Self {
+        self.combine(other, |a, b| a * b)
+    }
+}
+
+impl<N: FractalNumber> Div for Mca<N> {
+    type Output = Self;
+
+    fn div(self, other: Self) -> Self {
+        self.combine(other, |a, b| a / b)
+    }
+}
+
+fn precision_error(t: u32) -> String {
+    format!(
+        "virtual precision {} is out of range {}..={}",
+        t,
+        PRECISIONS.start(),
+        PRECISIONS.end()
+    )
+}
+
+/// An `MCA<inner,t>` format name.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct McaFormat {
+    pub inner: String,
+    pub precision: u32,
+}
+
+impl FromStr for McaFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || format!("invalid Monte Carlo format {}: want MCA<format,t>", s);
+        let params = s
+            .trim()
+            .strip_prefix("MCA<")
+            .and_then(|s| s.strip_suffix('>'))
+            .ok_or_else(err)?;
+        // The inner name may have its own parameters, so split at the last comma.
+        let (inner, precision) = params.rsplit_once(',').ok_or_else(err)?;
+        let precision = precision.trim().parse().map_err(|_| err())?;
+        if !PRECISIONS.contains(&precision) {
+            return Err(precision_error(precision));
+        }
+        let inner = inner.trim().to_string();
+        if !INNER_FORMATS.contains(&inner.as_str()) {
+            return Err(format!(
+                "unsupported format for Monte Carlo arithmetic: {}",
+                inner
+            ));
+        }
+        Ok(McaFormat { inner, precision })
+    }
+}
+
+impl Display for McaFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "MCA<{},{}>", self.inner, self.precision)
+    }
+}
+
+macro_rules! inner_formats {
+    ($($name:literal => $t:ty),* $(,)?) => {
+        /// Names of the formats that Monte Carlo arithmetic can wrap.
+        pub const INNER_FORMATS: &[&str] = &[$($name),*];
+
+        /// If `name` is one of the `INNER_FORMATS`, applies the visitor to its (unwrapped) type.
+        pub(crate) fn visit<V: NumberVisitor>(name: &str, visitor: V) -> Option<V::Output> {
+            match name {
+                $($name => Some(visitor.visit::<$t>()),)*
+                _ => None,
+            }
+        }
+    };
+}
+
+inner_formats!(
+    "f32" => f32,
+    "f64" => f64,
+    "DoubleDouble" => DoubleDouble,
+    "TF32" => Tf32,
+    "bfloat16" => BFloat16,
+    "binary16" => Binary16,
+    "P32" => softposit::P32,
+    "P16" => softposit::P16,
+);
+
+/// Running mean and variance (Welford's algorithm), for summarizing trials.
+#[derive(Copy, Clone, Debug, Default)]
+pub(crate) struct Moments {
+    count: usize,
+    mean: f64,
+    m2: f64,
+}
+
+impl Moments {
+    pub(crate) fn add(&mut self, x: f64) {
+        self.count += 1;
+        let d = x - self.mean;
+        self.mean += d / self.count as f64;
+        self.m2 += d * (x - self.mean);
+    }
+
+    /// The (population) variance, or None if there were no samples.
+    pub(crate) fn variance(&self) -> Option<f64> {
+        (self.count > 0).then(|| self.m2 / self.count as f64)
+    }
+}
+
+/// Runs `trials` renders of `params`, seeding each from `params.seed`, and returns the variance
+/// of each point across the trials: the sum of the variances of its K components.
+///
+/// `trial` renders with the given parameters and returns each point's components, or None for a
+/// point that doesn't contribute a sample to that trial.
+pub(crate) fn sensitivity<I, const K: usize>(
+    params: &CommonParams,
+    trials: usize,
+    mut trial: impl FnMut(&CommonParams) -> Result<I, String>,
+) -> Result<SensitivityVector, String>
+where
+    I: IntoIterator<Item = Option<[f64; K]>>,
+{
+    let mut moments = vec![[Moments::default(); K]; params.size.width * params.size.height];
+    let base = params.seed.unwrap_or(0);
+    for n in 0..trials {
+        let params = CommonParams {
+            seed: Some(base.wrapping_add(n as u64)),
+            ..params.clone()
+        };
+        for (moments, sample) in moments.iter_mut().zip(trial(&params)?) {
+            for (moments, x) in moments.iter_mut().zip(sample.into_iter().flatten()) {
+                moments.add(x);
+            }
+        }
+    }
+    Ok(moments
+        .iter()
+        .map(|moments| moments.iter().map(Moments::variance).sum())
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_perturbation() {
+        random::reseed(0, 0);
+        let t = 10;
+        let x = Mca::new(1.0f64).with_precision(t).unwrap();
+        let one = Mca::<f64>::from_i32(1);
+        let mut moments = Moments::default();
+        for _ in 0..1000 {
+            let y = (x.clone() * one.clone()).to_f64();
+            assert!((y - 1.0).abs() <= 2f64.powi(-(t as i32)), "{}", y);
+            moments.add(y);
+        }
+        // Uniform on [-2^-t, 2^-t) has variance 2^-2t / 3.
+        let expected = 2f64.powi(-2 * t as i32) / 3.0;
+        let variance = moments.variance().unwrap();
+        assert!(
+            (variance / expected - 1.0).abs() < 0.2,
+            "{} vs {}",
+            variance,
+            expected
+        );
+
+        // Exact values stay exact:
+        assert_eq!((one.clone() + one).to_f64(), 2.0);
+    }
+
+    #[test]
+    fn test_seeded() {
+        let run = |seed| {
+            random::reseed(seed, 0);
+            let x = Mca::new(1.0f32).with_precision(12).unwrap();
+            (0..10).fold(x.clone(), |acc, _| acc * x.clone()).to_f64()
+        };
+        assert_eq!(run(1), run(1));
+        assert_ne!(run(1), run(2));
+    }
+
+    #[test]
+    fn test_format() {
+        let format: McaFormat = "MCA<f64,24>".parse().unwrap();
+        assert_eq!(
+            format,
+            McaFormat {
+                inner: "f64".to_string(),
+                precision: 24
+            }
+        );
+        assert_eq!(format.to_string(), "MCA<f64,24>");
+        assert!("MCA<f64>".parse::<McaFormat>().is_err());
+        assert!("MCA<I16F16,8>".parse::<McaFormat>().is_err());
+        assert!("MCA<bfloat16,x>".parse::<McaFormat>().is_err());
+        assert!("MCA<f64,0>".parse::<McaFormat>().is_err());
+        assert!("MCA<f64,1100>".parse::<McaFormat>().is_ok());
+        assert!("MCA<f64,4294967295>".parse::<McaFormat>().is_err());
+        assert!(Mca::new(1.0f64).with_precision(u32::MAX).is_err());
+    }
+
+    #[test]
+    fn test_moments() {
+        let mut moments = Moments::default();
+        assert_eq!(moments.variance(), None);
+        for x in [2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0] {
+            moments.add(x);
+        }
+        assert_eq!(moments.variance(), Some(4.0));
+    }
+
+    #[test]
+    fn test_sensitivity() {
+        let params = CommonParams {
+            size: crate::Size {
+                width: 2,
+                height: 1,
+            },
+            seed: Some(5),
+            ..crate::test_params("f64", 1)
+        };
+        // Seeds 5, 6, 7 have variance 2/3; the second point never contributes a sample.
+        let variance = sensitivity(&params, 3, |params| {
+            let seed = params.seed.unwrap() as f64;
+            Ok([Some([seed, 0.0]), None])
+        })
+        .unwrap();
+        assert_eq!(variance.len(), 2);
+        assert!((variance[0].unwrap() - 2.0 / 3.0).abs() < 1e-12);
+        assert_eq!(variance[1], None);
+    }
+}
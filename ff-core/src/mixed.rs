@@ -0,0 +1,380 @@
+//! Mixed-precision evaluation: separate formats for the input coordinates, the iteration state,
+//! and the tests on the state (Mandelbrot's escape test, Newton's convergence test).
+//!
+//! Formats are named `Mixed<coordinate,state,test>`, e.g. `Mixed<f64,P16,f64>`: each coordinate
+//! is rounded to f64 and then to P16, the orbit is iterated in P16, and on each iteration the
+//! state is converted to f64 to compute the test. Each conversion rounds once, from the exact
+//! value. Comparing `Mixed<P16,f64,f64>` with `Mixed<f64,P16,f64>` (e.g. with
+//! `divergence::compare`) tells input quantization apart from iteration error.
+//!
+//! Each part can be any format `registry::visit_any` accepts, except that only the test can be
+//! an interval format. Conversions go through exact rationals, so mixed evaluation is much
+//! slower than evaluation in a single format.
+
+use std::fmt::Display;
+use std::marker::PhantomData;
+use std::str::FromStr;
+
+use num::BigRational;
+
+use crate::number::{ConvertVisitor, FractalNumber};
+use crate::numeric::Complex;
+use crate::registry::{self, Family};
+
+/// The formats of a mixed-precision evaluation.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MixedFormat {
+    /// The format the pixel coordinates are rounded to, before they're converted to the state's.
+    pub coordinate: String,
+    /// The format the orbit is iterated in.
+    pub state: String,
+    /// The format the state is converted to for each test.
+    pub test: String,
+}
+
+impl FromStr for MixedFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || {
+            format!(
+                "invalid mixed format {}: want Mixed<coordinate,state,test>",
+                s
+            )
+        };
+        let params = s
+            .trim()
+            .strip_prefix("Mixed<")
+            .and_then(|s| s.strip_suffix('>'))
+            .ok_or_else(err)?;
+        // The formats may have their own parameters, so split at the commas outside of them.
+        let mut parts = Vec::new();
+        let (mut depth, mut start) = (0, 0);
+        for (i, c) in params.char_indices() {
+            match c {
+                '<' => depth += 1,
+                '>' => depth -= 1,
+                ',' if depth == 0 => {
+                    parts.push(params[start..i].trim());
+                    start = i + 1;
+                }
+                _ => (),
+            }
+        }
+        parts.push(params[start..].trim());
+        let [coordinate, state, test] = parts[..] else {
+            return Err(err());
+        };
+        for name in [coordinate, state, test] {
+            visit(name, Supported)?;
+        }
+        // The coordinates and state carry on as exact values; an interval that isn't a single
+        // point has none. The test can be an interval: it decides with `decide_ge`.
+        for (part, name) in [("coordinate", coordinate), ("state", state)] {
+            if registry::find(name).is_some_and(|format| format.family == Family::Interval) {
+                return Err(format!(
+                    "{} can't be a mixed-precision {}: intervals have no single value",
+                    name, part
+                ));
+            }
+        }
+        Ok(MixedFormat {
+            coordinate: coordinate.to_string(),
+            state: state.to_string(),
+            test: test.to_string(),
+        })
+    }
+}
+
+impl Display for MixedFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Mixed<{},{},{}>", self.coordinate, self.state, self.test)
+    }
+}
+
+/// Applies the visitor to the named format, which can be any format `registry::visit_any`
+/// accepts.
+pub(crate) fn visit<V: ConvertVisitor>(name: &str, visitor: V) -> Result<V::Output, String> {
+    registry::visit_any(name, visitor)
+        .unwrap_or_else(|| Err(format!("unsupported format for mixed precision: {}", name)))
+}
+
+/// Checks that a format is supported, without doing anything with it.
+struct Supported;
+
+impl ConvertVisitor for Supported {
+    type Output = ();
+
+    fn visit<N: FractalNumber + Send + Sync + 'static>(
+        self,
+        _: impl Fn(&BigRational) -> Result<N, String> + Send + Sync + 'static,
+    ) {
+    }
+}
+
+/// Rounds an exact value to the value it has in a format.
+pub(crate) type Round = Box<dyn Fn(&BigRational) -> Result<BigRational, String> + Send + Sync>;
+
+/// The rounding to the named format.
+pub(crate) fn round(name: &str) -> Result<Round, String> {
+    visit(name, Rounder(name.to_string()))
+}
+
+struct Rounder(String);
+
+impl ConvertVisitor for Rounder {
+    type Output = Round;
+
+    fn visit<N: FractalNumber + Send + Sync + 'static>(
+        self,
+        convert: impl Fn(&BigRational) -> Result<N, String> + Send + Sync + 'static,
+    ) -> Self::Output {
+        let name = self.0;
+        Box::new(move |r| {
+            convert(r)?
+                .to_rational()
+                .ok_or_else(|| format!("{} isn't a finite number in {}", r, name))
+        })
+    }
+}
+
+/// The tests on an orbit's state, evaluated in the test format.
+///
+/// Each takes the exact values of the state; None if they aren't finite numbers.
+pub(crate) trait Test: Send + Sync {
+    /// Mandelbrot's escape test: whether |z|^2 >= 4 (None if the format can't tell), and |z|^2.
+    /// A state that isn't finite, or is out of the test format's range, has escaped.
+    fn escaped(&self, z: Option<Complex<BigRational>>, fused: bool) -> (Option<bool>, f64);
+
+    /// Whether `a` is near `b`, relative to `nb`, as in `Complex::near`.
+    /// Values that aren't finite, or are out of the test format's range, aren't near anything.
+    fn near(
+        &self,
+        a: Option<Complex<BigRational>>,
+        b: Option<Complex<BigRational>>,
+        nb: Option<Complex<BigRational>>,
+        threshold: i32,
+    ) -> bool;
+}
+
+/// The tests in the named format.
+pub(crate) fn test(name: &str) -> Result<Box<dyn Test>, String> {
+    visit(name, Tester)
+}
+
+struct Tester;
+
+impl ConvertVisitor for Tester {
+    type Output = Box<dyn Test>;
+
+    fn visit<N: FractalNumber + Send + Sync + 'static>(
+        self,
+        convert: impl Fn(&BigRational) -> Result<N, String> + Send + Sync + 'static,
+    ) -> Self::Output {
+        Box::new(TestIn {
+            convert,
+            format: PhantomData,
+        })
+    }
+}
+
+/// The tests in format N, with the function that rounds into it.
+struct TestIn<N, C> {
+    convert: C,
+    format: PhantomData<fn() -> N>,
+}
+
+impl<N, C> TestIn<N, C>
+where
+    N: FractalNumber,
+    C: Fn(&BigRational) -> Result<N, String>,
+{
+    fn complex(&self, z: Option<Complex<BigRational>>) -> Option<Complex<N>> {
+        let z = z?;
+        Some(Complex {
+            re: (self.convert)(&z.re).ok()?,
+            im: (self.convert)(&z.im).ok()?,
+        })
+    }
+}
+
+impl<N, C> Test for TestIn<N, C>
+where
+    N: FractalNumber,
+    C: Fn(&BigRational) -> Result<N, String> + Send + Sync,
+{
+    fn escaped(&self, z: Option<Complex<BigRational>>, fused: bool) -> (Option<bool>, f64) {
+        match self.complex(z) {
+            Some(z) => {
+                let z_magnitude_squared = z.magnitude_squared(fused);
+                let decided = z_magnitude_squared.decide_ge(&N::from_i32(4));
+                (decided, z_magnitude_squared.to_f64())
+            }
+            None => (Some(true), f64::INFINITY),
+        }
+    }
+
+    fn near(
+        &self,
+        a: Option<Complex<BigRational>>,
+        b: Option<Complex<BigRational>>,
+        nb: Option<Complex<BigRational>>,
+        threshold: i32,
+    ) -> bool {
+        match (self.complex(a), self.complex(b), self.complex(nb)) {
+            (Some(a), Some(b), Some(nb)) => a.near(b, nb, N::from_i32(threshold)),
+            _ => false,
+        }
+    }
+}
+
+/// The exact value of a state, if it's finite.
+pub(crate) fn exact<N: FractalNumber>(z: &Complex<N>) -> Option<Complex<BigRational>> {
+    Some(Complex {
+        re: z.re.to_rational()?,
+        im: z.im.to_rational()?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{mandelbrot, newton, test_params, NeverCancel};
+
+    #[test]
+    fn test_parse_format() {
+        let format: MixedFormat = "Mixed<f64, P16, f64>".parse().unwrap();
+        assert_eq!(
+            format,
+            MixedFormat {
+                coordinate: "f64".to_string(),
+                state: "P16".to_string(),
+                test: "f64".to_string(),
+            }
+        );
+        assert_eq!(format.to_string(), "Mixed<f64,P16,f64>");
+        let name = "Mixed<BigFloat<80>,MaskedFloat<4,9,RNE,IEEE>,Saturating<I16F16>>";
+        assert_eq!(name.parse::<MixedFormat>().unwrap().to_string(), name);
+        assert!("Mixed<f64,P16>".parse::<MixedFormat>().is_err());
+        assert!("Mixed<f64,P16,f64,f64>".parse::<MixedFormat>().is_err());
+        assert!("Mixed<f64,MXFP4,f64>".parse::<MixedFormat>().is_err());
+        assert!("Mixed<f64,Mixed<f64,f64,f64>,f64>"
+            .parse::<MixedFormat>()
+            .is_err());
+        assert!("Mixed<f64,Interval<f32>,f64>"
+            .parse::<MixedFormat>()
+            .is_err());
+        assert!("Mixed<Interval<f64>,f64,f64>"
+            .parse::<MixedFormat>()
+            .is_err());
+        assert!("Mixed<f64,f32,Interval<f64>>"
+            .parse::<MixedFormat>()
+            .is_ok());
+    }
+
+    #[test]
+    fn test_round() {
+        let third = BigRational::new(1.into(), 3.into());
+        let round = round("binary16").unwrap();
+        assert_eq!(
+            round(&third).unwrap(),
+            BigRational::new(1365.into(), 4096.into())
+        );
+        // A coordinate out of the format's range is an error.
+        let huge = BigRational::from_integer(1_000_000.into());
+        assert!(round(&huge).is_err());
+    }
+
+    #[test]
+    fn test_tests() {
+        let r = |n: i64, d: i64| BigRational::new(n.into(), d.into());
+        let z = |re: BigRational, im: BigRational| Some(Complex { re, im });
+        // |z|^2 is just under 4; it rounds up to 4 in binary16, but not in f64.
+        let inside = z(r(2, 1) - r(1, 1 << 20), r(0, 1));
+        assert_eq!(
+            test("f64").unwrap().escaped(inside.clone(), false).0,
+            Some(false)
+        );
+        assert_eq!(
+            test("binary16").unwrap().escaped(inside.clone(), false).0,
+            Some(true)
+        );
+        // An interval test can't decide what binary16 rounds away.
+        assert_eq!(
+            test("Interval<binary16>").unwrap().escaped(inside, false).0,
+            None
+        );
+        assert_eq!(
+            test("f64").unwrap().escaped(None, false),
+            (Some(true), f64::INFINITY)
+        );
+
+        let zero = z(r(0, 1), r(0, 1));
+        let one = z(r(1, 1), r(0, 1));
+        // Near is within |nb| / sqrt(threshold), here 1/8.
+        let (close, far) = (z(r(1, 64), r(0, 1)), z(r(1, 4), r(0, 1)));
+        for name in ["f64", "binary16", "I8F8"] {
+            let test = test(name).unwrap();
+            assert!(
+                test.near(close.clone(), zero.clone(), one.clone(), 64),
+                "{}",
+                name
+            );
+            assert!(
+                !test.near(far.clone(), zero.clone(), one.clone(), 64),
+                "{}",
+                name
+            );
+            assert!(!test.near(None, zero.clone(), one.clone(), 64), "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_same_format() {
+        // Mixing a format with itself is evaluating in it.
+        let ctx = NeverCancel();
+        let escapes = |numeric: &str| {
+            mandelbrot::compute(&ctx, &test_params(numeric, 12), 64)
+                .unwrap()
+                .into_iter()
+                .map(|e| e.map(|e| (e.count, e.z_magnitude_squared, e.undecided)))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(escapes("Mixed<f64,f64,f64>"), escapes("f64"));
+        assert_eq!(escapes("Mixed<P16,P16,P16>"), escapes("P16"));
+
+        let zeros = |numeric: &str| {
+            newton::compute(&ctx, &test_params(numeric, 12), 32)
+                .unwrap()
+                .into_iter()
+                .map(|z| z.map(|z| (z.count, z.zero, z.root)))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(zeros("Mixed<f64,f64,f64>"), zeros("f64"));
+        assert_eq!(
+            zeros("Mixed<binary16,binary16,binary16>"),
+            zeros("binary16")
+        );
+    }
+
+    #[test]
+    fn test_separate_formats() {
+        // Quantizing only the input, or only the iteration, gives different results.
+        let ctx = NeverCancel();
+        let zeros = |numeric: &str| {
+            newton::compute(&ctx, &test_params(numeric, 12), 32)
+                .unwrap()
+                .into_iter()
+                .map(|z| z.map(|z| (z.count, z.zero)))
+                .collect::<Vec<_>>()
+        };
+        let input = zeros("Mixed<FP8-E4M3,f64,f64>");
+        let iteration = zeros("Mixed<f64,FP8-E4M3,FP8-E4M3>");
+        assert_ne!(input, iteration);
+        assert_ne!(iteration, zeros("f64"));
+
+        let params = test_params("Mixed<f64,P16,f64>", 12);
+        let method = mandelbrot::Method::Perturbation;
+        assert!(mandelbrot::compute_with(&ctx, &params, 16, method).is_err());
+    }
+}
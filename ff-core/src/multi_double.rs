@@ -0,0 +1,323 @@
+//! Double-double and quad-double arithmetic.
+//!
+//! A `MultiDouble<N>` represents a number as the unevaluated sum of N f64 components of
+//! decreasing magnitude, giving about 53*N bits of precision with the exponent range of f64.
+//! These serve as high-precision reference formats for zooms past what f64 can resolve.
+//!
+//! The arithmetic is built from error-free transformations (`two_sum`, `two_prod`):
+//! each operation gathers the exact (or nearly-exact) partial terms of the result, and
+//! renormalizes them into N components. See Hida, Li & Bailey, "Library for Double-Double and
+//! Quad-Double Arithmetic" (2007), and Shewchuk, "Adaptive Precision Floating-Point Arithmetic"
+//! (1997).
+
+use std::cmp::Ordering;
+use std::ops::{Add, Div, Mul, Neg, Sub};
+
+use num::BigRational;
+
+use crate::numeric::FromRational;
+
+/// A number represented as the sum of N doubles.
+#[derive(Copy, Clone, Debug)]
+pub struct MultiDouble<const N: usize>([f64; N]);
+
+/// About 106 bits of precision.
+pub type DoubleDouble = MultiDouble<2>;
+/// About 212 bits of precision.
+pub type QuadDouble = MultiDouble<4>;
+
+/// Most terms an operation can produce before renormalization: the products in a QuadDouble multiply.
+const MAX_TERMS: usize = 32;
+
+/// Returns (s, e) such that s = fl(a + b) and s + e = a + b exactly.
+#[inline]
+pub(crate) fn two_sum(a: f64, b: f64) -> (f64, f64) {
+    let s = a + b;
+    let bb = s - a;
+    let e = (a - (s - bb)) + (b - bb);
+    (s, e)
+}
+
+/// Returns (p, e) such that p = fl(a * b) and p + e = a * b exactly.
+#[inline]
+pub(crate) fn two_prod(a: f64, b: f64) -> (f64, f64) {
+    let p = a * b;
+    if cfg!(target_feature = "fma") {
+        (p, a.mul_add(b, -p))
+    } else {
+        // Without hardware FMA, mul_add is done in software, and is much slower than Dekker's
+        // product.
+        let (ahi, alo) = split(a);
+        let (bhi, blo) = split(b);
+        let e = ((ahi * bhi - p) + ahi * blo + alo * bhi) + alo * blo;
+        (p, e)
+    }
+}
+
+/// Splits a double into two halves of 26 bits each, such that hi + lo = a.
+#[inline]
+fn split(a: f64) -> (f64, f64) {
+    const SPLITTER: f64 = 134217729.0; // 2^27 + 1
+    let t = SPLITTER * a;
+    let hi = t - (t - a);
+    (hi, a - hi)
+}
+
+/// Sums the terms, from least to most significant, leaving the sum in terms[0] and the
+/// rounding errors in the rest. The exact sum of the terms is unchanged.
+#[inline]
+fn distill(terms: &mut [f64]) {
+    for i in (1..terms.len()).rev() {
+        let (s, e) = two_sum(terms[i - 1], terms[i]);
+        terms[i - 1] = s;
+        terms[i] = e;
+    }
+}
+
+impl<const N: usize> MultiDouble<N> {
+    pub const ZERO: Self = MultiDouble([0.0; N]);
+
+    pub fn from_f64(f: f64) -> Self {
+        let mut components = [0.0; N];
+        components[0] = f;
+        MultiDouble(components)
+    }
+
+    /// The components, from most to least significant.
+    pub fn components(&self) -> [f64; N] {
+        self.0
+    }
+
+    pub fn to_f64(self) -> f64 {
+        self.0.iter().rev().sum()
+    }
+
+    /// Rounds a list of terms, ordered roughly from most to least significant, to N components.
+    fn renormalize(terms: &mut [f64]) -> Self {
+        let mut components = [0.0; N];
+        let mut rest = terms;
+        let mut i = 0;
+        while i < N && !rest.is_empty() {
+            distill(rest);
+            let (head, tail) = rest.split_at_mut(1);
+            rest = tail;
+            // Cancellation can leave a zero head with nonzero terms behind it; skip over it.
+            if head[0] != 0.0 {
+                components[i] = head[0];
+                i += 1;
+            }
+        }
+        // Anything left over is below the precision of the result; fold it into the last place.
+        if let Some(last) = components.last_mut() {
+            *last += rest.iter().rev().sum::<f64>();
+        }
+        distill(&mut components);
+        MultiDouble(components)
+    }
+
+    /// Multiplies by a single double.
+    fn mul_f64(self, b: f64) -> Self {
+        let mut terms = [0.0; MAX_TERMS];
+        for (i, a) in self.0.iter().enumerate() {
+            let (p, e) = two_prod(*a, b);
+            terms[2 * i] = p;
+            terms[2 * i + 1] = e;
+        }
+        Self::renormalize(&mut terms[..2 * N])
+    }
+
+    fn is_nan(&self) -> bool {
+        self.0.iter().any(|c| c.is_nan())
+    }
+}
+
+impl<const N: usize> From<MultiDouble<N>> for f64 {
+    fn from(v: MultiDouble<N>) -> Self {
+        v.to_f64()
+    }
+}
+
+impl<const N: usize> Neg for MultiDouble<N> {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        MultiDouble(self.0.map(|c| -c))
+    }
+}
+
+impl<const N: usize> Add for MultiDouble<N> {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        let mut terms = [0.0; MAX_TERMS];
+        for i in 0..N {
+            terms[2 * i] = self.0[i];
+            terms[2 * i + 1] = other.0[i];
+        }
+        Self::renormalize(&mut terms[..2 * N])
+    }
+}
+
+impl<const N: usize> Sub for MultiDouble<N> {
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self {
+        self + -other
+    }
+}
+
+impl<const N: usize> Mul for MultiDouble<N> {
+    type Output = Self;
+
+    fn mul(self, other: Self) -> Self {
+        let mut terms = [0.0; MAX_TERMS];
+        let mut len = 0;
+        // Gather products in order of significance.
+        // Products below the N'th order don't need their rounding errors;
+        // those past it don't contribute at all.
+        for order in 0..=N {
+            for i in 0..=order {
+                let j = order - i;
+                if i >= N || j >= N {
+                    continue;
+                }
+                if order < N {
+                    let (p, e) = two_prod(self.0[i], other.0[j]);
+                    terms[len] = p;
+                    terms[len + 1] = e;
+                    len += 2;
+                } else {
+                    terms[len] = self.0[i] * other.0[j];
+                    len += 1;
+                }
+            }
+        }
+        Self::renormalize(&mut terms[..len])
+    }
+}
+
+impl<const N: usize> Div for MultiDouble<N> {
+    type Output = Self;
+
+    fn div(self, other: Self) -> Self {
+        // Long division: each partial quotient is a double, and the remainder is computed
+        // (nearly) exactly before producing the next.
+        let mut quotients = [0.0; MAX_TERMS];
+        let mut remainder = self;
+        for q in quotients.iter_mut().take(N + 1) {
+            *q = remainder.0[0] / other.0[0];
+            remainder = remainder - other.mul_f64(*q);
+        }
+        Self::renormalize(&mut quotients[..N + 1])
+    }
+}
+
+impl<const N: usize> PartialEq for MultiDouble<N> {
+    fn eq(&self, other: &Self) -> bool {
+        self.partial_cmp(other) == Some(Ordering::Equal)
+    }
+}
+
+impl<const N: usize> PartialOrd for MultiDouble<N> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        if self.is_nan() || other.is_nan() {
+            return None;
+        }
+        // Components are normalized, so the leading component of the difference has its sign.
+        (*self - *other).0[0].partial_cmp(&0.0)
+    }
+}
+
+impl<const N: usize> FromRational for MultiDouble<N> {
+    fn from_bigrational(r: &BigRational) -> Result<Self, String> {
+        // Take the closest double, and repeat on the (exact) remainder.
+        let mut components = [0.0; N];
+        let mut remainder = r.clone();
+        for c in components.iter_mut() {
+            *c = f64::from_bigrational(&remainder)?;
+            if *c == 0.0 {
+                break;
+            }
+            remainder -=
+                BigRational::from_float(*c).ok_or_else(|| format!("{} is out of range", r))?;
+        }
+        Ok(MultiDouble(components))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use num::{BigInt, Signed, Zero};
+
+    fn to_rational<const N: usize>(v: MultiDouble<N>) -> BigRational {
+        v.components()
+            .iter()
+            .map(|c| BigRational::from_float(*c).unwrap())
+            .sum()
+    }
+
+    /// Checks that |a - b| <= |b| * 2^-bits.
+    fn assert_close(a: BigRational, b: BigRational, bits: u32) {
+        let tolerance = b.abs() / BigRational::from_integer(BigInt::from(1) << bits);
+        assert!(
+            (&a - &b).abs() <= tolerance,
+            "{} is not within 2^-{} of {}",
+            a,
+            bits,
+            b
+        );
+    }
+
+    #[test]
+    fn test_small_differences() {
+        let one = DoubleDouble::from_f64(1.0);
+        let tiny = DoubleDouble::from_f64(2f64.powi(-80));
+        assert_eq!(((one + tiny) - one).to_f64(), 2f64.powi(-80));
+        assert!(one + tiny > one);
+
+        let one = QuadDouble::from_f64(1.0);
+        let tiny = QuadDouble::from_f64(2f64.powi(-180));
+        assert_eq!(((one + tiny) - one).to_f64(), 2f64.powi(-180));
+        assert!(one + tiny > one);
+        assert!(one - tiny < one);
+    }
+
+    #[test]
+    fn test_division() {
+        let third = BigRational::new(1.into(), 3.into());
+        let dd = DoubleDouble::from_f64(1.0) / DoubleDouble::from_f64(3.0);
+        assert_close(to_rational(dd), third.clone(), 104);
+        let qd = QuadDouble::from_f64(1.0) / QuadDouble::from_f64(3.0);
+        assert_close(to_rational(qd), third.clone(), 208);
+        // And back:
+        let one = BigRational::from_integer(1.into());
+        assert_close(to_rational(qd * QuadDouble::from_f64(3.0)), one, 208);
+    }
+
+    #[test]
+    fn test_multiplication() {
+        let r = BigRational::new(12345.into(), 67891.into());
+        let s = BigRational::new((-314159).into(), 271828.into());
+        let dd = DoubleDouble::from_bigrational(&r).unwrap()
+            * DoubleDouble::from_bigrational(&s).unwrap();
+        assert_close(to_rational(dd), &r * &s, 104);
+        let qd =
+            QuadDouble::from_bigrational(&r).unwrap() * QuadDouble::from_bigrational(&s).unwrap();
+        assert_close(to_rational(qd), &r * &s, 208);
+    }
+
+    #[test]
+    fn test_from_rational() {
+        let tiny = BigRational::new(1.into(), BigInt::from(3) << 150u32);
+        let r = BigRational::from_integer(1.into()) + &tiny;
+        let qd = QuadDouble::from_bigrational(&r).unwrap();
+        assert_close(to_rational(qd), r.clone(), 210);
+        let dd = DoubleDouble::from_bigrational(&r).unwrap();
+        assert_eq!(dd.to_f64(), 1.0);
+        assert_eq!(
+            QuadDouble::from_bigrational(&BigRational::zero()).unwrap(),
+            QuadDouble::ZERO
+        );
+    }
+}
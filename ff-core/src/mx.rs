@@ -0,0 +1,359 @@
+//! Microscaling (MX) block formats, per the OCP Microscaling Formats specification.
+//!
+//! An MX block is a run of (by default) 32 elements that share a single power-of-two scale.
+//! Each element is a narrow float or integer:
+//!
+//! | Format     | Element                                   |
+//! |------------|-------------------------------------------|
+//! | MXFP8-E4M3 | FP8 E4M3, max 448                         |
+//! | MXFP8-E5M2 | FP8 E5M2, max 57344                       |
+//! | MXFP6-E2M3 | FP6 E2M3, max 7.5                         |
+//! | MXFP6-E3M2 | FP6 E3M2, max 28                          |
+//! | MXFP4      | FP4 E2M1, max 6                           |
+//! | MXINT8     | 8-bit two's complement, scaled by 2^-6    |
+//!
+//! The shared scale is chosen from the largest magnitude in the block, as the specification
+//! suggests: `2^(floor(log2(max)) - emax)`, where `emax` is the exponent of the element format's
+//! largest power of two. Elements round to nearest-even, and saturate at the element maximum.
+//! A non-finite value makes the scale, and so the whole block, NaN.
+//!
+//! Since the scale depends on all of the values in the block, these aren't scalar formats:
+//! `MxBlock` holds a whole block of lanes. Arithmetic is carried out lane-by-lane in f64, and
+//! each result is re-quantized as a block. Lanes can be masked off (e.g. when a pixel has
+//! escaped), after which they are zero and no longer affect the scale.
+
+use std::fmt::Display;
+use std::ops::{Add, Div, Mul, Sub};
+use std::str::FromStr;
+
+/// The element type of an MX format.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Element {
+    Fp8E4M3,
+    Fp8E5M2,
+    Fp6E2M3,
+    Fp6E3M2,
+    Fp4E2M1,
+    Int8,
+}
+
+impl Element {
+    pub const ALL: &'static [Element] = &[
+        Element::Fp8E4M3,
+        Element::Fp8E5M2,
+        Element::Fp6E2M3,
+        Element::Fp6E3M2,
+        Element::Fp4E2M1,
+        Element::Int8,
+    ];
+
+    /// The name of the MX format with this element type.
+    pub fn name(self) -> &'static str {
+        match self {
+            Element::Fp8E4M3 => "MXFP8-E4M3",
+            Element::Fp8E5M2 => "MXFP8-E5M2",
+            Element::Fp6E2M3 => "MXFP6-E2M3",
+            Element::Fp6E3M2 => "MXFP6-E3M2",
+            Element::Fp4E2M1 => "MXFP4",
+            Element::Int8 => "MXINT8",
+        }
+    }
+
+    /// The exponent of the largest power of two in the element format.
+    fn emax(self) -> i32 {
+        match self {
+            Element::Fp8E4M3 => 8,
+            Element::Fp8E5M2 => 15,
+            Element::Fp6E2M3 => 2,
+            Element::Fp6E3M2 => 4,
+            Element::Fp4E2M1 => 2,
+            Element::Int8 => 0,
+        }
+    }
+
+    /// The mantissa bits, smallest normal exponent, and largest value.
+    /// MXINT8 is a fixed-point format, equivalent to a float with no exponent range.
+    fn parameters(self) -> (i32, i32, f64) {
+        match self {
+            Element::Fp8E4M3 => (3, -6, 448.0),
+            Element::Fp8E5M2 => (2, -14, 57344.0),
+            Element::Fp6E2M3 => (3, 0, 7.5),
+            Element::Fp6E3M2 => (2, -2, 28.0),
+            Element::Fp4E2M1 => (1, 0, 6.0),
+            Element::Int8 => (6, 0, 127.0 / 64.0),
+        }
+    }
+
+    /// Rounds a (scaled) value to the nearest element, saturating.
+    fn quantize(self, v: f64) -> f64 {
+        let (mantissa, emin, max) = self.parameters();
+        let exponent = if self == Element::Int8 {
+            0
+        } else {
+            exponent(v).max(emin)
+        };
+        let ulp = 2f64.powi(exponent - mantissa);
+        let min = if self == Element::Int8 { -2.0 } else { -max };
+        ((v / ulp).round_ties_even() * ulp).clamp(min, max)
+    }
+}
+
+/// floor(log2(|v|)), for normal nonzero v; less than any element exponent otherwise.
+fn exponent(v: f64) -> i32 {
+    ((v.to_bits() >> 52) & 0x7ff) as i32 - 1023
+}
+
+/// The range of the shared (E8M0) scale's exponent.
+const MIN_SCALE: i32 = -127;
+const MAX_SCALE: i32 = 127;
+
+/// Names of the MX formats, with the default block size. Each can also be used with another
+/// block size, e.g. `MXFP4<16>`.
+pub const FORMATS: &[&str] = &[
+    "MXFP8-E4M3",
+    "MXFP8-E5M2",
+    "MXFP6-E2M3",
+    "MXFP6-E3M2",
+    "MXFP4",
+    "MXINT8",
+];
+
+/// An MX format: element type and block size.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct MxFormat {
+    pub element: Element,
+    pub block: usize,
+}
+
+impl MxFormat {
+    /// The block size from the OCP specification.
+    pub const DEFAULT_BLOCK: usize = 32;
+    /// Lane masks are 64 bits.
+    pub const MAX_BLOCK: usize = 64;
+}
+
+impl FromStr for MxFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (name, block) = match s.split_once('<') {
+            Some((name, rest)) => {
+                let block = rest
+                    .strip_suffix('>')
+                    .ok_or_else(|| format!("unterminated block size in {}", s))?;
+                let block: usize = block
+                    .trim()
+                    .parse()
+                    .map_err(|e| format!("invalid block size in {}: {}", s, e))?;
+                (name, block)
+            }
+            None => (s, Self::DEFAULT_BLOCK),
+        };
+        let element = Element::ALL
+            .iter()
+            .copied()
+            .find(|e| e.name() == name)
+            .ok_or_else(|| format!("unknown MX format {}", s))?;
+        if block == 0 || block > Self::MAX_BLOCK {
+            return Err(format!(
+                "block size must be between 1 and {}, not {}",
+                Self::MAX_BLOCK,
+                block
+            ));
+        }
+        Ok(MxFormat { element, block })
+    }
+}
+
+impl Display for MxFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.block == Self::DEFAULT_BLOCK {
+            write!(f, "{}", self.element.name())
+        } else {
+            write!(f, "{}<{}>", self.element.name(), self.block)
+        }
+    }
+}
+
+/// A block of lanes, stored in an MX format.
+#[derive(Clone, Debug)]
+pub struct MxBlock {
+    element: Element,
+    /// The values, as represented; zero in inactive lanes.
+    values: Vec<f64>,
+    /// The active lanes, as a bitmask.
+    active: u64,
+}
+
+impl MxBlock {
+    /// Quantizes the values into a block, with all lanes active.
+    pub fn new(element: Element, values: &[f64]) -> Self {
+        assert!(values.len() <= MxFormat::MAX_BLOCK, "MX block too large");
+        let active = u64::MAX.checked_shr(64 - values.len() as u32).unwrap_or(0);
+        Self::quantized(element, values.to_vec(), active)
+    }
+
+    /// A block with the same value in every lane.
+    pub fn splat(element: Element, value: f64, lanes: usize) -> Self {
+        Self::new(element, &vec![value; lanes])
+    }
+
+    pub fn lanes(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn get(&self, lane: usize) -> f64 {
+        self.values[lane]
+    }
+
+    /// The active lanes, as a bitmask.
+    pub fn active(&self) -> u64 {
+        self.active
+    }
+
+    /// Restricts the block to the given lanes, re-quantizing the rest.
+    pub fn with_active(self, active: u64) -> Self {
+        Self::quantized(self.element, self.values, self.active & active)
+    }
+
+    fn quantized(element: Element, mut values: Vec<f64>, active: u64) -> Self {
+        let mut max: f64 = 0.0;
+        for (lane, v) in values.iter_mut().enumerate() {
+            if active & (1 << lane) == 0 {
+                *v = 0.0;
+            } else if v.is_finite() {
+                max = max.max(v.abs());
+            } else {
+                max = f64::NAN;
+            }
+        }
+        if max.is_nan() {
+            values.iter_mut().for_each(|v| *v = f64::NAN);
+        } else if max != 0.0 {
+            let shared = (exponent(max) - element.emax()).clamp(MIN_SCALE, MAX_SCALE);
+            let scale = 2f64.powi(shared);
+            values
+                .iter_mut()
+                .for_each(|v| *v = element.quantize(*v / scale) * scale);
+        }
+        MxBlock {
+            element,
+            values,
+            active,
+        }
+    }
+
+    /// Applies `op` to each pair of active lanes, and quantizes the results.
+    fn zip(self, other: Self, op: impl Fn(f64, f64) -> f64) -> Self {
+        assert_eq!(self.element, other.element, "mismatched MX formats");
+        let values = self
+            .values
+            .iter()
+            .zip(other.values.iter())
+            .map(|(a, b)| op(*a, *b))
+            .collect();
+        Self::quantized(self.element, values, self.active & other.active)
+    }
+}
+
+impl Add for MxBlock {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        self.zip(other, |a, b| a + b)
+    }
+}
+
+impl Sub for MxBlock {
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self {
+        self.zip(other, |a, b| a - b)
+    }
+}
+
+impl Mul for MxBlock {
+    type Output = Self;
+
+    fn mul(self, other: Self) -> Self {
+        self.zip(other, |a, b| a * b)
+    }
+}
+
+impl Div for MxBlock {
+    type Output = Self;
+
+    fn div(self, other: Self) -> Self {
+        self.zip(other, |a, b| a / b)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_elements() {
+        assert_eq!(Element::Fp8E4M3.quantize(1000.0), 448.0);
+        assert_eq!(Element::Fp8E4M3.quantize(1.0 + 1.0 / 16.0), 1.0);
+        assert_eq!(Element::Fp8E4M3.quantize(2f64.powi(-9)), 2f64.powi(-9));
+        assert_eq!(Element::Fp8E4M3.quantize(2f64.powi(-11)), 0.0);
+        assert_eq!(Element::Fp4E2M1.quantize(2.5), 2.0);
+        assert_eq!(Element::Fp4E2M1.quantize(-5.0), -4.0);
+        assert_eq!(Element::Fp4E2M1.quantize(0.25), 0.0);
+        assert_eq!(Element::Fp6E3M2.quantize(-100.0), -28.0);
+        assert_eq!(Element::Int8.quantize(1.0 / 3.0), 21.0 / 64.0);
+        assert_eq!(Element::Int8.quantize(-3.0), -2.0);
+    }
+
+    #[test]
+    fn test_shared_scale() {
+        // The largest value, 5, sets the scale to 2^(2 - 2): FP4 elements are then
+        // 0, 0.5, 1, 1.5, 2, 3, 4, 6.
+        let block = MxBlock::new(Element::Fp4E2M1, &[5.0, 0.3, 1.2, -2.6]);
+        assert_eq!(block.values, vec![4.0, 0.5, 1.0, -3.0]);
+        // Shrinking the largest value lets the rest use a smaller scale.
+        let block = MxBlock::new(Element::Fp4E2M1, &[0.75, 0.3, 0.2, -0.6]);
+        assert_eq!(block.values, vec![0.75, 0.25, 0.1875, -0.5]);
+        // Inactive lanes don't count: the remaining lane gets a scale of its own.
+        let block = MxBlock::new(Element::Fp4E2M1, &[5.0, 0.3, 1.2, -2.6]).with_active(0b0010);
+        assert_eq!(block.values, vec![0.0, 0.5, 0.0, 0.0]);
+        assert_eq!(block.active(), 0b0010);
+        let block = MxBlock::new(Element::Fp4E2M1, &[0.3]);
+        assert_eq!(block.values, vec![0.25]);
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        let a = MxBlock::new(Element::Fp8E4M3, &[1.0, 2.0, 96.0]);
+        let b = MxBlock::new(Element::Fp8E4M3, &[1.0, 0.5, 0.01]);
+        // 1 sets b's scale to 2^(0 - 8), and 0.01 rounds to 2.5 * 2^-8.
+        assert_eq!(b.get(2), 0.009765625);
+        // 96 sets the scale to 2^(6 - 8); 2 and 2.5 fit, 96.0098 rounds to 96.
+        let sum = a.clone() + b.clone();
+        assert_eq!(sum.values, vec![2.0, 2.5, 96.0]);
+        let product = a.clone() * b;
+        assert_eq!(product.values, vec![1.0, 1.0, 0.9375]);
+        // Overflow saturates the element, not the scale:
+        let big = MxBlock::splat(Element::Fp8E4M3, 2f64.powi(120), 2);
+        assert_eq!((big.clone() * big).get(0), 448.0 * 2f64.powi(127));
+        // Division by zero poisons the block:
+        let zero = MxBlock::splat(Element::Fp8E4M3, 0.0, 3);
+        assert!((a / zero).get(0).is_nan());
+    }
+
+    #[test]
+    fn test_parse() {
+        let format: MxFormat = "MXFP4".parse().unwrap();
+        assert_eq!(format.element, Element::Fp4E2M1);
+        assert_eq!(format.block, 32);
+        let format: MxFormat = "MXINT8<16>".parse().unwrap();
+        assert_eq!(format.to_string(), "MXINT8<16>");
+        assert!("MXINT8<65>".parse::<MxFormat>().is_err());
+        assert!("MXFP5".parse::<MxFormat>().is_err());
+        for name in FORMATS {
+            assert_eq!(name.parse::<MxFormat>().unwrap().to_string(), *name);
+        }
+    }
+}
@@ -1,148 +1,449 @@
-use fixed::types::{I11F5, I20F12, I22F10};
 use rayon::prelude::*;
-use std::{ops::Range, panic::AssertUnwindSafe};
+use std::panic::AssertUnwindSafe;
 
 // Implementation of Newton's fractal for z^3-1
 // TODO:
 //   Parameterize to other functions
-use crate::{masked_float::MaskedFloat, numeric::Complex, CancelContext, CommonParams};
+use crate::{
+    fixed_point::{Overflow, OverflowSlot},
+    instrument::{self, Instrumented},
+    mca::{self, Mca, McaFormat},
+    mixed::{self, MixedFormat},
+    mx::{Element, MxBlock, MxFormat},
+    numeric::{make_range, Complex, FromRational},
+    random,
+    registry::{self, Fractal},
+    CancelContext, CommonParams,
+};
 
 pub use crate::number::FractalNumber;
-use crate::{Zero, ZeroVector};
+use crate::number::{ConvertVisitor, NumberVisitor};
+use crate::{EventVector, SensitivityVector, Zero, ZeroVector};
 use num::BigRational;
 
-/// Function pointer for evaluating zeros
-type EscapeFn = fn(&dyn CancelContext, &CommonParams, usize) -> Result<ZeroVector, String>;
-
-const FUNCTIONS: &[(&'static str, EscapeFn)] = &[
-    ("f32", evaluate_parallel_numeric::<f32>),
-    ("f64", evaluate_parallel_numeric::<f64>),
-    ("P32", evaluate_parallel_numeric::<softposit::P32>),
-    ("P16", evaluate_parallel_numeric::<softposit::P16>),
-    // P8 and MaskedFloat<3,50> don't produce interesting images, mostly fail to converge.
-    //("P8", evaluate_parallel_numeric::<softposit::P8>),
-    //("MaskedFloat<3,50>", evaluate_parallel_numeric::<MaskedFloat<3, 50>>),
-    (
-        "MaskedFloat<4,50>",
-        evaluate_parallel_numeric::<MaskedFloat<4, 50>>,
-    ),
-    (
-        "MaskedFloat<6,3>",
-        evaluate_parallel_numeric::<MaskedFloat<6, 3>>,
-    ),
-    ("I22F10", evaluate_parallel_numeric::<I22F10>),
-    ("I20F12", evaluate_parallel_numeric::<I20F12>),
-    ("I11F5", evaluate_parallel_numeric::<I11F5>),
-];
-
 /// List the numeric formats that are valid for rendering.
+///
+/// `compute` also accepts the formats the registry excludes (see `registry::Format::exclusion`),
+/// and the parameterized families (see `registry`).
 pub fn formats() -> impl Iterator<Item = &'static str> {
-    FUNCTIONS.iter().map(|(name, _)| *name)
+    registry::listed(Fractal::Newton).map(|format| format.name)
 }
 
 pub fn compute(ctx: &dyn CancelContext, params: &CommonParams, iterations: usize) -> Result<ZeroVector, String> {
     let fmt = params.numeric.as_str();
-    // Linear scan, we don't have that many options:
-    for (candidate, computer) in FUNCTIONS.iter() {
-        if *candidate == fmt {
-            return computer(ctx, params, iterations);
+    let evaluate = Evaluate {
+        ctx,
+        params,
+        iterations,
+    };
+    if let Some(result) = registry::visit_any(fmt, evaluate) {
+        return result?;
+    }
+
+    // MX formats share a scale across a block of pixels, so they're evaluated a block at a time:
+    if fmt.starts_with("MX") {
+        let format: MxFormat = fmt.parse()?;
+        if params.fused {
+            return Err(format!("fused arithmetic is not supported for MX format {}", format));
         }
+        return evaluate_mx(ctx, params, iterations, format);
+    }
+
+    if fmt.starts_with("MCA<") {
+        let format: McaFormat = fmt.parse()?;
+        let evaluate = EvaluateMca {
+            ctx,
+            params,
+            iterations,
+            precision: format.precision,
+        };
+        if let Some(result) = mca::visit(&format.inner, evaluate) {
+            return result;
+        }
+    }
+
+    if fmt.starts_with("Mixed<") {
+        let format: MixedFormat = fmt.parse()?;
+        let evaluate = EvaluateMixed {
+            ctx,
+            params,
+            iterations,
+            coordinate: mixed::round(&format.coordinate)?,
+            test: mixed::test(&format.test)?,
+        };
+        return mixed::visit(&format.state, evaluate)?;
     }
 
     Err(format!("unknown numeric format {}", fmt))
 }
 
-fn evaluate_parallel_numeric<N>(
+/// Computes the zeros in the given window, with each point's operation and event counts;
+/// see `instrument::Instrumented`.
+///
+/// Supports the formats `compute` does, other than MX, MCA, and mixed formats.
+pub fn events(
+    ctx: &dyn CancelContext,
+    params: &CommonParams,
+    iterations: usize,
+) -> Result<(ZeroVector, EventVector), String> {
+    let fmt = params.numeric.as_str();
+    let evaluate = EvaluateInstrumented {
+        ctx,
+        params,
+        iterations,
+    };
+    if let Some(result) = registry::visit_any(fmt, evaluate) {
+        return result?;
+    }
+
+    Err(format!("can't count events in numeric format {}", fmt))
+}
+
+/// Computes the variance of each point's root across `trials` seeded renders: the sum of the
+/// variances of its real and imaginary parts, over the trials that reached a zero.
+///
+/// Only formats with random behavior (e.g. `MCA<f64,24>`) vary between trials.
+/// A point that reaches different zeros in different trials has a large variance.
+pub fn sensitivity(
+    ctx: &dyn CancelContext,
+    params: &CommonParams,
+    iterations: usize,
+    trials: usize,
+) -> Result<SensitivityVector, String> {
+    mca::sensitivity(params, trials, |params| {
+        let zeros = compute(ctx, params, iterations)?;
+        Ok(zeros
+            .into_iter()
+            .map(|zero| zero.map(|Zero { root, .. }| [root.0, root.1])))
+    })
+}
+
+/// Evaluates a format whose type is chosen at runtime.
+struct Evaluate<'a> {
+    ctx: &'a dyn CancelContext,
+    params: &'a CommonParams,
+    iterations: usize,
+}
+
+impl ConvertVisitor for Evaluate<'_> {
+    type Output = Result<ZeroVector, String>;
+
+    fn visit<N: FractalNumber + Send + Sync + 'static>(
+        self,
+        convert: impl Fn(&BigRational) -> Result<N, String> + Send + Sync + 'static,
+    ) -> Self::Output {
+        evaluate_parallel(self.ctx, self.params, self.iterations, convert)
+    }
+}
+
+/// Evaluates a format whose type is chosen at runtime, counting events.
+struct EvaluateInstrumented<'a> {
+    ctx: &'a dyn CancelContext,
+    params: &'a CommonParams,
+    iterations: usize,
+}
+
+impl ConvertVisitor for EvaluateInstrumented<'_> {
+    type Output = Result<(ZeroVector, EventVector), String>;
+
+    fn visit<N: FractalNumber + Send + Sync + 'static>(
+        self,
+        convert: impl Fn(&BigRational) -> Result<N, String> + Send + Sync + 'static,
+    ) -> Self::Output {
+        evaluate_instrumented(self.ctx, self.params, self.iterations, convert)
+    }
+}
+
+/// Evaluates a format wrapped in Monte Carlo arithmetic.
+struct EvaluateMca<'a> {
+    ctx: &'a dyn CancelContext,
+    params: &'a CommonParams,
+    iterations: usize,
+    precision: u32,
+}
+
+impl NumberVisitor for EvaluateMca<'_> {
+    type Output = Result<ZeroVector, String>;
+
+    fn visit<N: FractalNumber + Send + Sync>(self) -> Self::Output {
+        evaluate_parallel(self.ctx, self.params, self.iterations, |r| {
+            Mca::<N>::from_bigrational(r)?.with_precision(self.precision)
+        })
+    }
+}
+
+/// Evaluates a mixed format, given its state's type.
+struct EvaluateMixed<'a> {
+    ctx: &'a dyn CancelContext,
+    params: &'a CommonParams,
+    iterations: usize,
+    coordinate: mixed::Round,
+    test: Box<dyn mixed::Test>,
+}
+
+impl ConvertVisitor for EvaluateMixed<'_> {
+    type Output = Result<ZeroVector, String>;
+
+    fn visit<N: FractalNumber + Send + Sync + 'static>(
+        self,
+        convert: impl Fn(&BigRational) -> Result<N, String> + Send + Sync + 'static,
+    ) -> Self::Output {
+        let (fused, test) = (self.params.fused, self.test.as_ref());
+        // The coordinates are rounded to their own format, then converted to the state's:
+        let convert = |r: &BigRational| convert(&(self.coordinate)(r)?);
+        let zero = mixed::exact(&Complex {
+            re: N::from_i32(0),
+            im: N::from_i32(0),
+        });
+        let converged = |fz: &Complex<N>, z: &Complex<N>| {
+            test.near(mixed::exact(fz), zero.clone(), mixed::exact(z), 1024)
+        };
+        let find =
+            |x: &N, y: &N| find_zero_tested(x, y, self.iterations, fused, converged, |_, _| ());
+        let zeros = evaluate_points(self.ctx, self.params, convert, find, |point| point())?;
+        // Telling the zeros apart is a test, too:
+        identify_zeros(
+            zeros,
+            |x, z| test.near(mixed::exact(x), mixed::exact(z), mixed::exact(z), 512),
+            |z| (z.re.clone().to_f64(), z.im.clone().to_f64()),
+        )
+    }
+}
+
+/// Evaluates the window, using `convert` to produce the input coordinates.
+fn evaluate_parallel<N>(
     ctx: &dyn CancelContext,
     params: &CommonParams,
     iterations: usize,
+    convert: impl Fn(&BigRational) -> Result<N, String>,
 ) -> Result<ZeroVector, String>
 where
     N: FractalNumber + Send + Sync,
+{
+    let find = |x: &N, y: &N| find_zero(x, y, iterations, params.fused);
+    let zeros = evaluate_points(ctx, params, convert, find, |point| point())?;
+    identify(zeros)
+}
+
+/// Like `evaluate_parallel`, with each point's operation and event counts.
+fn evaluate_instrumented<N>(
+    ctx: &dyn CancelContext,
+    params: &CommonParams,
+    iterations: usize,
+    convert: impl Fn(&BigRational) -> Result<N, String>,
+) -> Result<(ZeroVector, EventVector), String>
+where
+    N: FractalNumber + Send + Sync,
+{
+    let convert = |r: &BigRational| convert(r).map(Instrumented::new);
+    let find = |x: &Instrumented<N>, y: &Instrumented<N>| find_zero(x, y, iterations, params.fused);
+    let points = evaluate_points(ctx, params, convert, find, |point| instrument::count(point))?;
+    let (zeros, events) = points.into_iter().unzip();
+    Ok((identify(zeros)?, events))
+}
+
+/// Numbers the zeros reached in a format.
+fn identify<N: FractalNumber>(zeros: Vec<Option<(Complex<N>, usize)>>) -> Result<ZeroVector, String> {
+    identify_zeros(
+        zeros,
+        |x, z| x.near(z.clone(), z.clone(), N::from_i32(512)),
+        |z| (z.re.clone().to_f64(), z.im.clone().to_f64()),
+    )
+}
+
+/// Evaluates the window, using `convert` to produce the input coordinates, `find` to find the
+/// zero from each point, and `observe` to evaluate each point.
+fn evaluate_points<N, T>(
+    ctx: &dyn CancelContext,
+    params: &CommonParams,
+    convert: impl Fn(&BigRational) -> Result<N, String>,
+    find: impl Fn(&N, &N) -> Option<(Complex<N>, usize)> + Sync,
+    observe: impl Fn(&dyn Fn() -> Option<(Complex<N>, usize)>) -> T + Sync,
+) -> Result<Vec<T>, String>
+where
+    N: FractalNumber + Send + Sync,
+    T: Clone + Default + Send,
 {
     let size = params.size;
     // Create the X and Y ranges up-front:
-    let make_range = |r: &Range<BigRational>, steps: usize| -> Result<Vec<N>, String> {
-        let step = (&r.end - &r.start) / BigRational::new(steps.into(), 1.into());
-        let mut results = Vec::with_capacity(steps);
-        let mut next = r.start.clone();
-        for _ in 0..steps {
-            let converted = N::from_bigrational(&next)?;
-            results.push(converted);
-            next += &step;
-        }
-        Ok(results)
-    };
-    let xs = make_range(&params.x, size.width)?;
-    let ys = make_range(&params.y, size.height)?;
-    let mut zeros: Vec<Option<(Complex<N>, usize)>> = Vec::new();
-    zeros.resize(size.width * size.height, None);
+    let xs = make_range(&params.x, size.width, &convert)?;
+    let ys = make_range(&params.y, size.height, &convert)?;
+    evaluate_rows(ctx, params, ys, |row, y, row_out| {
+        xs.iter().zip(row_out).enumerate().for_each(|(col, (x, out))| {
+            if let Some(seed) = params.seed {
+                random::reseed(seed, (row * size.width + col) as u64);
+            }
+            *out = observe(&|| find(x, &y));
+        })
+    })
+}
 
+/// Evaluates each row of the output with `evaluate_row`, given its index and y coordinate.
+///
+/// A panic drops its row, except for a fixed-point `Overflow`, which fails the render.
+fn evaluate_rows<Y, T>(
+    ctx: &dyn CancelContext,
+    params: &CommonParams,
+    ys: Vec<Y>,
+    evaluate_row: impl Fn(usize, Y, &mut [T]) + Sync,
+) -> Result<Vec<T>, String>
+where
+    Y: Send,
+    T: Clone + Default + Send,
+{
+    let size = params.size;
+    let mut zeros: Vec<T> = Vec::new();
+    zeros.resize(size.width * size.height, T::default());
+
+    let overflow = OverflowSlot::default();
     let out_rows = zeros.chunks_mut(size.width);
     ys.into_iter()
         .zip(out_rows)
+        .enumerate()
         .par_bridge()
         .into_par_iter()
-        .for_each(|(y, row_out)| {
+        .for_each(|(row, (y, row_out))| {
             if ctx.is_canceled() {
                 return
             }
 
-            let result = std::panic::catch_unwind(AssertUnwindSafe(|| {
-                xs.iter().zip(row_out).for_each(|(x, out)| {
-                    *out = find_zero(x, &y, iterations);
-                })
-            }));
-            if result.is_err() {
-                tracing::error!("caught panic during mandelbrot evaluation");
+            let result =
+                std::panic::catch_unwind(AssertUnwindSafe(|| evaluate_row(row, y, row_out)));
+            if let Err(panic) = result {
+                if !overflow.catch(panic) {
+                    tracing::error!("caught panic during mandelbrot evaluation");
+                }
             }
         });
 
-    let mut zero_index: Vec<Complex<N>> = Vec::new();
-
     if ctx.is_canceled() {
         return Err("canceled".to_string())
     }
+    overflow.into_result()?;
+    Ok(zeros)
+}
+
+/// Numbers the distinct zeros that were reached, using `near` to tell whether two are the same,
+/// and `position` to locate each.
+fn identify_zeros<N>(
+    zeros: Vec<Option<(Complex<N>, usize)>>,
+    near: impl Fn(&Complex<N>, &Complex<N>) -> bool,
+    position: impl Fn(&Complex<N>) -> (f64, f64),
+) -> Result<ZeroVector, String> {
+    let mut zero_index: Vec<Complex<N>> = Vec::new();
 
-    Ok(zeros
-        .into_iter()
-        .map(|x| match x {
-            None => None,
-            Some((z, iters)) => match zero_index
-                .iter()
-                .position(|x| (*x).near(z.clone(), z.clone(), N::from_i32(512)))
-            {
-                None => {
-                    let nz = zero_index.len();
-                    zero_index.push(z);
-                    Some(Zero {
-                        count: iters,
-                        zero: nz,
-                    })
+    // Identifying the zeros is more arithmetic, which can also overflow.
+    let identified = std::panic::catch_unwind(AssertUnwindSafe(|| {
+        zeros
+            .into_iter()
+            .map(|x| match x {
+                None => None,
+                Some((z, iters)) => {
+                    let root = position(&z);
+                    match zero_index.iter().position(|x| near(x, &z)) {
+                        None => {
+                            let nz = zero_index.len();
+                            zero_index.push(z);
+                            Some(Zero {
+                                count: iters,
+                                zero: nz,
+                                root,
+                            })
+                        }
+                        Some(n) => Some(Zero {
+                            count: iters,
+                            zero: n,
+                            root,
+                        }),
+                    }
                 }
-                Some(n) => Some(Zero {
-                    count: iters,
-                    zero: n,
-                }),
-            },
-        })
-        .collect())
+            })
+            .collect()
+    }));
+    identified.map_err(|panic| match panic.downcast::<Overflow>() {
+        Ok(overflow) => overflow.to_string(),
+        Err(_) => "caught panic while identifying zeros".to_string(),
+    })
+}
+
+/// Evaluates the window in an MX format. Each run of `format.block` pixels in a row is one block.
+fn evaluate_mx(
+    ctx: &dyn CancelContext,
+    params: &CommonParams,
+    iterations: usize,
+    format: MxFormat,
+) -> Result<ZeroVector, String> {
+    let size = params.size;
+    let xs = make_range(&params.x, size.width, &f64::from_bigrational)?;
+    let ys = make_range(&params.y, size.height, &f64::from_bigrational)?;
+    let zeros = evaluate_rows(ctx, params, ys, |_, y, row_out| {
+        for (xs, out) in xs.chunks(format.block).zip(row_out.chunks_mut(format.block)) {
+            out.copy_from_slice(&find_zero_block(format.element, xs, y, iterations));
+        }
+    })?;
+    // The zeros are individual values now, not blocks: compare them as one-lane blocks.
+    let single = |v: f64| MxBlock::new(format.element, &[v]);
+    let near = |x: &Complex<f64>, z: &Complex<f64>| {
+        let (x, z) = (
+            Complex { re: single(x.re), im: single(x.im) },
+            Complex { re: single(z.re), im: single(z.im) },
+        );
+        // As in Complex::near:
+        let nearby = (z.re.clone() * z.re.clone() + z.im.clone() * z.im.clone()) / single(512.0);
+        let d = x - z;
+        let distance = d.re.clone() * d.re + d.im.clone() * d.im;
+        distance.get(0) < nearby.get(0)
+    };
+    identify_zeros(zeros, near, |z| (z.re, z.im))
 }
 
 #[inline]
-fn find_zero<N>(x: &N, y: &N, limit: usize) -> Option<(Complex<N>, usize)>
+fn find_zero<N>(x: &N, y: &N, limit: usize, fused: bool) -> Option<(Complex<N>, usize)>
 where
     N: FractalNumber,
 {
-    let mut z: Complex<N> = Complex {
-        re: x.clone(),
-        im: y.clone(),
-    };
+    find_zero_observed(x, y, limit, fused, |_, _| ())
+}
 
+/// Like `find_zero`, calling `observe` with z and the result of the convergence test on each
+/// iteration.
+#[inline]
+pub(crate) fn find_zero_observed<N>(
+    x: &N,
+    y: &N,
+    limit: usize,
+    fused: bool,
+    observe: impl FnMut(&Complex<N>, Option<bool>),
+) -> Option<(Complex<N>, usize)>
+where
+    N: FractalNumber,
+{
     let zero: Complex<N> = Complex {
         re: N::from_i32(0),
         im: N::from_i32(0),
     };
+    let converged =
+        |fz: &Complex<N>, z: &Complex<N>| fz.near(zero.clone(), z.clone(), N::from_i32(1024));
+    find_zero_tested(x, y, limit, fused, converged, observe)
+}
+
+/// Like `find_zero_observed`, with the convergence test given: whether f(z) is near zero.
+#[inline]
+fn find_zero_tested<N>(
+    x: &N,
+    y: &N,
+    limit: usize,
+    fused: bool,
+    converged: impl Fn(&Complex<N>, &Complex<N>) -> bool,
+    mut observe: impl FnMut(&Complex<N>, Option<bool>),
+) -> Option<(Complex<N>, usize)>
+where
+    N: FractalNumber,
+{
+    let mut z: Complex<N> = Complex {
+        re: x.clone(),
+        im: y.clone(),
+    };
 
     let one: Complex<N> = Complex {
         re: N::from_i32(1),
@@ -164,17 +465,99 @@ where
         // For f(x)=x^3-1, f'(x)=3x^2
         //
         // TODO: The function and its derivative could come in as lambdas.
-        let fz = z.clone() * z.clone() * z.clone() - one.clone();
-        let fpz = three.clone() * z.clone() * z.clone();
-        if fpz.re.clone() * fpz.re.clone() + fpz.im.clone() * fpz.im.clone() == N::from_i32(0) {
+        let (fz, fpz) = if fused {
+            let square = z.clone().mul_fused(z.clone());
+            (
+                square.clone().mul_fused(z.clone()) - one.clone(),
+                three.clone().mul_fused(square),
+            )
+        } else {
+            (
+                z.clone() * z.clone() * z.clone() - one.clone(),
+                three.clone() * z.clone() * z.clone(),
+            )
+        };
+        if fpz.magnitude_squared(fused) == N::from_i32(0) {
             return None;
         }
-        let del = fz.clone() / fpz;
-        if fz.near(zero.clone(), z.clone(), N::from_i32(1024)) {
+        let del = if fused {
+            fz.clone().div_fused(fpz)
+        } else {
+            fz.clone() / fpz
+        };
+        let converged = converged(&fz, &z);
+        observe(&z, Some(converged));
+        if converged {
             return Some((z, i));
         }
         z = z - del;
     }
     //println!("Fail: Z[{}]: re: {:?} im: {:?}", limit, z.re, z.im);
-    return None;
+    None
+}
+
+/// Like `find_zero`, for a block of pixels that share an MX scale.
+///
+/// Pixels that have converged (or failed) are masked off, so they stop affecting the others'
+/// scale.
+fn find_zero_block(
+    element: Element,
+    xs: &[f64],
+    y: f64,
+    limit: usize,
+) -> Vec<Option<(Complex<f64>, usize)>> {
+    let lanes = xs.len();
+    let constant = |v: f64| MxBlock::splat(element, v, lanes);
+    let mut z = Complex {
+        re: MxBlock::new(element, xs),
+        im: constant(y),
+    };
+    let one = Complex {
+        re: constant(1.0),
+        im: constant(0.0),
+    };
+    let three = Complex {
+        re: constant(3.0),
+        im: constant(0.0),
+    };
+    let threshold = constant(1024.0);
+    let mut zeros = vec![None; lanes];
+
+    for i in 0..limit {
+        let fz = z.clone() * z.clone() * z.clone() - one.clone();
+        let fpz = three.clone() * z.clone() * z.clone();
+        let fpz_magnitude = fpz.re.clone() * fpz.re.clone() + fpz.im.clone() * fpz.im.clone();
+        // As in fz.near(zero, z, 1024):
+        let nearby = (z.re.clone() * z.re.clone() + z.im.clone() * z.im.clone()) / threshold.clone();
+        let distance = fz.re.clone() * fz.re.clone() + fz.im.clone() * fz.im.clone();
+
+        let mut active = z.re.active();
+        for (lane, zero) in zeros.iter_mut().enumerate() {
+            if active & (1 << lane) == 0 {
+                continue;
+            }
+            if fpz_magnitude.get(lane) == 0.0 {
+                active &= !(1 << lane);
+            } else if distance.get(lane) < nearby.get(lane) {
+                *zero = Some((
+                    Complex {
+                        re: z.re.get(lane),
+                        im: z.im.get(lane),
+                    },
+                    i,
+                ));
+                active &= !(1 << lane);
+            }
+        }
+        if active == 0 {
+            break;
+        }
+        let mask = |c: Complex<MxBlock>| Complex {
+            re: c.re.with_active(active),
+            im: c.im.with_active(active),
+        };
+        let del = mask(fz) / mask(fpz);
+        z = mask(z) - del;
+    }
+    zeros
 }
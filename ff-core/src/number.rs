@@ -1,12 +1,22 @@
-use std::{
-    mem::size_of,
-    ops::{Add, Div, Mul, Sub},
+use std::ops::{Add, Div, Mul, Sub};
+
+use num::{BigRational, ToPrimitive};
+
+use crate::{
+    big_float::BigFloat,
+    decimal::{Decimal, DecimalFormat},
+    instrument::{Instrumented, Operation},
+    lns::Lns,
+    masked_float::{DynMaskedFloat, MaskedFloat},
+    mca::Mca,
+    multi_double::MultiDouble,
+    numeric::FromRational,
+    posit::Posit,
+    slash::{Layout, Slash},
+    small_float::{SmallFloat, SmallFloatFormat},
+    takum::{Takum, Variant},
 };
 
-use num::{BigInt, BigRational, Signed, ToPrimitive};
-
-use crate::{masked_float::MaskedFloat, numeric::FromRational};
-
 /// A numeric type that can can be used for the Mandelbrot fractal.
 ///
 /// This trait identifies the necessary operations to compute a Mandelbrot image:
@@ -30,6 +40,51 @@ pub trait FractalNumber:
 
     // Provides a way to get a f64 from this type.
     fn to_f64(self) -> f64;
+
+    /// Decides whether `self >= other`: Some(true) or Some(false) if that's certain,
+    /// None if the format can't tell (e.g. overlapping intervals).
+    fn decide_ge(&self, other: &Self) -> Option<bool> {
+        Some(self >= other)
+    }
+
+    /// The exact value, if `self` is a finite number.
+    ///
+    /// By default this goes through `to_f64`, which is exact for formats whose values are all
+    /// f64 values.
+    fn to_rational(&self) -> Option<BigRational> {
+        BigRational::from_float(self.clone().to_f64())
+    }
+
+    /// Computes `a*b + c*d` with fused accumulation: exactly, rounding once (a posit quire),
+    /// or rounding the second product before a fused multiply-add (IEEE floats).
+    /// None if the format has no fused accumulation.
+    fn fused_add_products(_a: &Self, _b: &Self, _c: &Self, _d: &Self) -> Option<Self> {
+        None
+    }
+
+    /// Like `fused_add_products`, for `a*b - c*d`.
+    fn fused_sub_products(_a: &Self, _b: &Self, _c: &Self, _d: &Self) -> Option<Self> {
+        None
+    }
+}
+
+/// An operation that can be applied to any FractalNumber type, for dispatching on a format
+/// chosen at runtime.
+pub(crate) trait NumberVisitor {
+    type Output;
+
+    fn visit<N: FractalNumber + Send + Sync + 'static>(self) -> Self::Output;
+}
+
+/// Like `NumberVisitor`, with the function that rounds an exact value into the format; for
+/// formats whose parameters are only known at runtime (see `registry::visit_any`).
+pub(crate) trait ConvertVisitor {
+    type Output;
+
+    fn visit<N: FractalNumber + Send + Sync + 'static>(
+        self,
+        convert: impl Fn(&BigRational) -> Result<N, String> + Send + Sync + 'static,
+    ) -> Self::Output;
 }
 
 impl FractalNumber for f32 {
@@ -40,6 +95,14 @@ impl FractalNumber for f32 {
     fn from_i32(i: i32) -> Self {
         i as f32
     }
+
+    fn fused_add_products(a: &Self, b: &Self, c: &Self, d: &Self) -> Option<Self> {
+        Some(a.mul_add(*b, c * d))
+    }
+
+    fn fused_sub_products(a: &Self, b: &Self, c: &Self, d: &Self) -> Option<Self> {
+        Some(a.mul_add(*b, -(c * d)))
+    }
 }
 
 impl FractalNumber for f64 {
@@ -50,6 +113,31 @@ impl FractalNumber for f64 {
     fn from_i32(i: i32) -> Self {
         i.into()
     }
+
+    fn fused_add_products(a: &Self, b: &Self, c: &Self, d: &Self) -> Option<Self> {
+        Some(a.mul_add(*b, c * d))
+    }
+
+    fn fused_sub_products(a: &Self, b: &Self, c: &Self, d: &Self) -> Option<Self> {
+        Some(a.mul_add(*b, -(c * d)))
+    }
+}
+
+impl<const N: usize> FractalNumber for MultiDouble<N> {
+    fn to_f64(self) -> f64 {
+        self.into()
+    }
+
+    fn from_i32(i: i32) -> Self {
+        MultiDouble::from_f64(i.into())
+    }
+
+    fn to_rational(&self) -> Option<BigRational> {
+        self.components()
+            .iter()
+            .map(|c| BigRational::from_float(*c))
+            .sum()
+    }
 }
 
 impl FractalNumber for BigRational {
@@ -60,6 +148,20 @@ impl FractalNumber for BigRational {
     fn from_i32(i: i32) -> Self {
         BigRational::new(i.into(), 1.into())
     }
+
+    fn to_rational(&self) -> Option<BigRational> {
+        Some(self.clone())
+    }
+}
+
+impl<F: SmallFloatFormat> FractalNumber for SmallFloat<F> {
+    fn to_f64(self) -> f64 {
+        self.into()
+    }
+
+    fn from_i32(i: i32) -> Self {
+        SmallFloat::from_f64(i.into())
+    }
 }
 
 impl<const E: usize, const F: usize> FractalNumber for MaskedFloat<E, F> {
@@ -72,148 +174,89 @@ impl<const E: usize, const F: usize> FractalNumber for MaskedFloat<E, F> {
     }
 }
 
+impl FractalNumber for DynMaskedFloat {
+    fn to_f64(self) -> f64 {
+        self.into()
+    }
+
+    fn from_i32(i: i32) -> Self {
+        DynMaskedFloat::new(i.into())
+    }
+}
+
+impl FractalNumber for BigFloat {
+    fn to_f64(self) -> f64 {
+        self.into()
+    }
+
+    fn from_i32(i: i32) -> Self {
+        BigFloat::from_i64(i.into())
+    }
+
+    fn to_rational(&self) -> Option<BigRational> {
+        Some(BigFloat::to_rational(self))
+    }
+}
+
 /// Implementation of MandelbrotNumber for fixed-precision formats.
 /// Needs at least 4 bits of integer part to allow "4" + sign.
 macro_rules! impl_fixed {
     ($t:ty) => {
-        impl FractalNumber for $t {
+        impl $crate::number::FractalNumber for $t {
             fn to_f64(self) -> f64 {
-                self.into()
+                self.to_num()
             }
 
             fn from_i32(i: i32) -> Self {
                 Self::saturating_from_num(i)
             }
+
+            fn to_rational(&self) -> Option<num::BigRational> {
+                let bits: i128 = self.to_bits().into();
+                Some(num::BigRational::new(bits.into(), num::BigInt::from(1) << Self::FRAC_NBITS))
+            }
         }
 
-        impl FromRational for $t {
-            fn from_bigrational(value: &BigRational) -> Result<Self, String> {
-                let zero: BigInt = 0.into();
-                // To make it easier to reason about fractions & numbers-
-                // Do everything as "positive", then negate.
-                let negative = value.is_negative();
-                let value = if negative { -value } else { value.clone() };
-
-                let whole = value
-                    .trunc()
-                    .to_integer()
-                    .to_i128()
-                    .ok_or_else(|| format!("big-rational {} out of range", value.trunc()))?;
-                let part = value.fract();
-                const FRAC: u32 = <$t>::FRAC_NBITS;
-
-                // Perform binary long-division on the fractional part;
-                // get one more bit of precision than we have.
-                let mut remainder = part.numer().clone();
-                let mut fraction: i128 = 0;
-                for i in 0..=FRAC {
-                    remainder = remainder << 1;
-                    let new_rem = &remainder - part.denom();
-                    if new_rem >= zero {
-                        remainder = new_rem;
-                        fraction |= 1 << (FRAC - i);
-                    }
-                }
-                // We kept an extra bit of precision so we can round.
-                let fraction = if (fraction & 1) == 1 {
-                    // Round up:
-                    (fraction >> 1) | 1
-                } else {
-                    // Round down:
-                    (fraction >> 1)
-                };
-
-                let v = <$t>::from_bits(
-                    ((whole << FRAC) | fraction)
-                        .try_into()
-                        .map_err(|_| "truncated rational".to_string())?,
-                );
-                Ok(if negative { -v } else { v })
+        impl $crate::numeric::FromRational for $t {
+            fn from_bigrational(value: &num::BigRational) -> Result<Self, String> {
+                // Round the value in units of the last place; that's the bit pattern.
+                let scale = num::BigInt::from(1) << Self::FRAC_NBITS;
+                let scaled = value * num::BigRational::from_integer(scale);
+                num::ToPrimitive::to_i128(&$crate::numeric::round_even(&scaled))
+                    .and_then(|bits| bits.try_into().ok())
+                    .map(<$t>::from_bits)
+                    .ok_or_else(|| format!("big-rational {} out of range", value))
             }
         }
     };
 }
 
-impl_fixed!(fixed::types::I11F5);
-impl_fixed!(fixed::types::I13F3);
-impl_fixed!(fixed::types::I15F1);
-impl_fixed!(fixed::types::I22F10);
-impl_fixed!(fixed::types::I20F12);
-
-impl FromRational for softposit::P32 {
-    fn from_bigrational(r: &BigRational) -> Result<Self, String>
-    where
-        Self: Sized,
-    {
-        // A quire value is either NaR or an integer multiple of the square of minPos...
-        // The smallest positive posit value, minPos, is ... Every posit value is an integer multiple of minPos.
-
-        // So: we can convert from a BigRational to an N-bit posit by:
-        // - Rounding to a multiple of the square of minPos
-        //      (Note: _square of_ minPos - tricky!)
-        // - Converting that to a quire
-        // - Converting the quire to a posit
-
-        // The smallest positive posit value, minPos, is 2^(−4n+8):
-        // const MINEXP: i32 = -4 * 32 + 8;
-        // TODO: Consider memoizing these constants, they're global but can't be static initialized.
-        // They're cheap to initialize if we do it right- by bit-shifting-
-        // but they can't do constexpr.
-        let minpos_squared: BigRational = BigRational::new(2.into(), 1.into()).pow(16 - 8 * 32);
-        let quire = {
-            const QUIRE_BYTE_COUNT: usize = 16 * 32 / 8; // 16n bits / 8 bits per byte
-            const QUIRE_WORD_COUNT: usize = QUIRE_BYTE_COUNT / size_of::<u64>();
-            let bytes = {
-                // We get the quire from the quotient:
-                let quotient = (r / minpos_squared).to_integer();
-                let mut bytes: Vec<u8> = quotient.to_signed_bytes_le().into();
-                // We need to sign-extend until we have exactly 16n bits.
-                // LE format means we append to sign-extend
-                let byte = if r.is_negative() { 0xff } else { 0x0 };
-                bytes.resize(QUIRE_BYTE_COUNT, byte);
-                bytes
-            };
-            // For whatever reason... the softposit appears to track words in reverse order?
-            let words: Vec<u64> = bytes
-                .as_slice()
-                .chunks_exact(size_of::<u64>())
-                .map(|chunk| {
-                    let mut word = [0u8; 8];
-                    word.copy_from_slice(chunk);
-                    u64::from_le_bytes(word)
-                })
-                .rev()
-                .collect();
-
-            let mut quire_words = [0u64; QUIRE_WORD_COUNT];
-            quire_words.copy_from_slice(&words);
-            softposit::Q32::from_bits(quire_words)
-        };
-
-        Ok(quire.to_posit())
-    }
-}
+// Invoked for each fixed-point row of the registry.
+pub(crate) use impl_fixed;
 
-impl FromRational for softposit::P16 {
-    fn from_bigrational(r: &BigRational) -> Result<Self, String>
-    where
-        Self: Sized,
-    {
-        Ok(softposit::P32::from_bigrational(r)?.into())
-    }
+/// Implementation of FromRational for softposit formats, which are the original standard's
+/// `posit<n, es>`: round with `Posit`, and reinterpret the bits.
+macro_rules! impl_posit_from_rational {
+    ($t:ty, $n:literal, $es:literal, $bits:ty) => {
+        impl FromRational for $t {
+            fn from_bigrational(r: &BigRational) -> Result<Self, String>
+            where
+                Self: Sized,
+            {
+                let posit = Posit::<$n, $es>::from_bigrational(r)?;
+                Ok(<$t>::from_bits(posit.to_bits() as $bits))
+            }
+        }
+    };
 }
 
-impl FromRational for softposit::P8 {
-    fn from_bigrational(r: &BigRational) -> Result<Self, String>
-    where
-        Self: Sized,
-    {
-        Ok(softposit::P32::from_bigrational(r)?.into())
-    }
-}
+impl_posit_from_rational!(softposit::P32, 32, 2, u32);
+impl_posit_from_rational!(softposit::P16, 16, 1, u16);
+impl_posit_from_rational!(softposit::P8, 8, 0, u8);
 
+/// Implementation of FractalNumber for softposit formats, which accumulate in their quire `$q`.
 macro_rules! impl_posit {
-    ($t:ty) => {
+    ($t:ty, $q:ty) => {
         impl FractalNumber for $t {
             fn from_i32(i: i32) -> Self {
                 <$t>::from_i32(i)
@@ -222,13 +265,142 @@ macro_rules! impl_posit {
             fn to_f64(self) -> f64 {
                 self.into()
             }
+
+            fn fused_add_products(a: &Self, b: &Self, c: &Self, d: &Self) -> Option<Self> {
+                let mut quire = <$q>::init();
+                quire.add_product(*a, *b);
+                quire.add_product(*c, *d);
+                Some(quire.to_posit())
+            }
+
+            fn fused_sub_products(a: &Self, b: &Self, c: &Self, d: &Self) -> Option<Self> {
+                let mut quire = <$q>::init();
+                quire.add_product(*a, *b);
+                quire.sub_product(*c, *d);
+                Some(quire.to_posit())
+            }
         }
     };
 }
 
-impl_posit!(softposit::P32);
-impl_posit!(softposit::P16);
-impl_posit!(softposit::P8);
+impl_posit!(softposit::P32, softposit::Q32);
+impl_posit!(softposit::P16, softposit::Q16);
+impl_posit!(softposit::P8, softposit::Q8);
+
+impl<const I: u32, const F: u32> FractalNumber for Lns<I, F> {
+    fn from_i32(i: i32) -> Self {
+        Lns::from_i32(i)
+    }
+
+    fn to_f64(self) -> f64 {
+        self.into()
+    }
+}
+
+impl<const N: u32, const ES: u32> FractalNumber for Posit<N, ES> {
+    fn from_i32(i: i32) -> Self {
+        Posit::from_i32(i)
+    }
+
+    fn to_f64(self) -> f64 {
+        self.into()
+    }
+}
+
+impl<const N: u32, V: Variant> FractalNumber for Takum<N, V> {
+    fn from_i32(i: i32) -> Self {
+        Takum::from_i32(i)
+    }
+
+    fn to_f64(self) -> f64 {
+        self.into()
+    }
+}
+
+impl<N: FractalNumber> FractalNumber for Mca<N> {
+    fn from_i32(i: i32) -> Self {
+        Mca::new(N::from_i32(i))
+    }
+
+    fn to_f64(self) -> f64 {
+        self.into()
+    }
+
+    fn decide_ge(&self, other: &Self) -> Option<bool> {
+        self.value().decide_ge(other.value())
+    }
+
+    fn to_rational(&self) -> Option<BigRational> {
+        self.value().to_rational()
+    }
+
+    fn fused_add_products(a: &Self, b: &Self, c: &Self, d: &Self) -> Option<Self> {
+        Mca::fuse([a, b, c, d], N::fused_add_products)
+    }
+
+    fn fused_sub_products(a: &Self, b: &Self, c: &Self, d: &Self) -> Option<Self> {
+        Mca::fuse([a, b, c, d], N::fused_sub_products)
+    }
+}
+
+impl<N: FractalNumber> FractalNumber for Instrumented<N> {
+    fn from_i32(i: i32) -> Self {
+        Instrumented::new(N::from_i32(i))
+    }
+
+    fn to_f64(self) -> f64 {
+        self.into()
+    }
+
+    fn decide_ge(&self, other: &Self) -> Option<bool> {
+        self.value().decide_ge(other.value())
+    }
+
+    fn to_rational(&self) -> Option<BigRational> {
+        self.value().to_rational()
+    }
+
+    fn fused_add_products(a: &Self, b: &Self, c: &Self, d: &Self) -> Option<Self> {
+        Instrumented::fuse([a, b, c, d], Operation::Add, N::fused_add_products)
+    }
+
+    fn fused_sub_products(a: &Self, b: &Self, c: &Self, d: &Self) -> Option<Self> {
+        Instrumented::fuse([a, b, c, d], Operation::Sub, N::fused_sub_products)
+    }
+}
+
+impl<L: Layout> FractalNumber for Slash<L> {
+    fn from_i32(i: i32) -> Self {
+        Slash::from_i32(i)
+    }
+
+    fn to_f64(self) -> f64 {
+        self.into()
+    }
+
+    fn to_rational(&self) -> Option<BigRational> {
+        let (negative, p, q) = self.parts();
+        if q == 0 {
+            return None;
+        }
+        let r = BigRational::new(p.into(), q.into());
+        Some(if negative { -r } else { r })
+    }
+}
+
+impl<F: DecimalFormat> FractalNumber for Decimal<F> {
+    fn from_i32(i: i32) -> Self {
+        Decimal::from_i32(i)
+    }
+
+    fn to_f64(self) -> f64 {
+        self.into()
+    }
+
+    fn to_rational(&self) -> Option<BigRational> {
+        Decimal::to_rational(self)
+    }
+}
 
 #[cfg(test)]
 mod tests {
@@ -311,6 +483,32 @@ mod tests {
         assert_eq!(P32::from_bigrational(&neg).unwrap(), NEG);
     }
 
+    #[test]
+    fn test_fused_accumulation() {
+        // (1 + 2^-k)^2 - 1 = 2^(1-k) + 2^-2k, but the square rounds off the 2^-2k.
+        fn check<N: FractalNumber>(k: i32) {
+            let a = N::from_bigrational(&(power(0) + power(-k))).unwrap();
+            let one = N::from_i32(1);
+            let exact = power(1 - k) + power(-2 * k);
+            let rounded = a.clone() * a.clone() - one.clone() * one.clone();
+            assert_ne!(rounded.to_rational(), Some(exact.clone()));
+            let fused = N::fused_sub_products(&a, &a, &one, &one).unwrap();
+            assert_eq!(fused.to_rational(), Some(exact.clone()));
+            let fused = N::fused_add_products(&a, &a, &N::from_i32(-1), &one).unwrap();
+            assert_eq!(fused.to_rational(), Some(exact));
+        }
+        fn power(k: i32) -> BigRational {
+            BigRational::from_integer(2.into()).pow(k)
+        }
+        check::<f32>(12);
+        check::<f64>(27);
+        check::<softposit::P16>(7);
+        check::<P32>(15);
+        // Formats without fused accumulation say so.
+        let one = MaskedFloat::<8, 23>::from_i32(1);
+        assert!(MaskedFloat::fused_add_products(&one, &one, &one, &one).is_none());
+    }
+
     #[test]
     fn test_p32_small() {
         const SMALL: P32 = P32::from_f32(1.0 / 16.0);
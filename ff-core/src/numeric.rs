@@ -1,8 +1,12 @@
-use std::ops::{Add, Div, Mul, Sub};
+use std::cmp::Ordering;
+use std::ops::{Add, Div, Mul, Range, Sub};
 
-use num::{BigRational, ToPrimitive};
+use num::{BigInt, BigRational, BigUint, Integer, Signed, ToPrimitive, Zero};
 
-use crate::{mandelbrot::FractalNumber, masked_float::MaskedFloat};
+use crate::{
+    mandelbrot::FractalNumber,
+    masked_float::{DynMaskedFloat, MaskedFloat},
+};
 
 /// A numeric type that can be converted from a BigRational.
 ///
@@ -19,7 +23,8 @@ impl FromRational for f32 {
     where
         Self: Sized,
     {
-        r.to_f32().ok_or(format!("failed conversion from {}", r))
+        // Every f32 is an f64, so the conversion is exact; overflow goes to infinity.
+        Ok(round_binary(r, f32::MANTISSA_DIGITS, f32::MIN_EXP) as f32)
     }
 }
 
@@ -28,14 +33,21 @@ impl FromRational for f64 {
     where
         Self: Sized,
     {
-        r.to_f64().ok_or(format!("failed conversion from {}", r))
+        Ok(round_binary(r, f64::MANTISSA_DIGITS, f64::MIN_EXP))
     }
 }
 
 impl<const E: usize, const F: usize> FromRational for MaskedFloat<E, F> {
+    fn from_bigrational(value: &BigRational) -> Result<Self, String> {
+        Ok(MaskedFloat::<E, F>::from_rational(value))
+    }
+}
+
+/// Produces an unformatted value; see `DynMaskedFloat::from_rational` to round into a format.
+impl FromRational for DynMaskedFloat {
     fn from_bigrational(value: &BigRational) -> Result<Self, String> {
         let f: f64 = f64::from_bigrational(value)?;
-        Ok(MaskedFloat::<E, F>::new(f))
+        Ok(DynMaskedFloat::new(f))
     }
 }
 
@@ -45,6 +57,115 @@ impl FromRational for BigRational {
     }
 }
 
+/// The binary expansion of a nonzero rational, cut off after a fixed number of significant bits.
+///
+/// The magnitude of the rational is `significand * 2^exponent`, plus something less than
+/// `2^exponent` if `sticky` is set. Formats can round from this exactly once.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct BinaryParts {
+    pub negative: bool,
+    pub exponent: i64,
+    pub significand: BigUint,
+    pub sticky: bool,
+}
+
+/// Splits the rational into `bits` significant bits, and whatever remains.
+///
+/// Returns None for zero.
+pub(crate) fn binary_parts(r: &BigRational, bits: u64) -> Option<BinaryParts> {
+    if r.is_zero() {
+        return None;
+    }
+    let n: BigUint = r.numer().abs().to_biguint()?;
+    let d: BigUint = r.denom().abs().to_biguint()?;
+    // floor(log2(n/d)) is either this or one less:
+    let estimate = n.bits() as i64 - d.bits() as i64;
+    let mut shift = bits as i64 - estimate;
+    let (n, d) = if shift >= 0 {
+        (n << shift as u64, d)
+    } else {
+        (n, d << (-shift) as u64)
+    };
+    let mut significand = &n / &d;
+    let mut sticky = !(n % d).is_zero();
+    if significand.bits() > bits {
+        sticky |= significand.bit(0);
+        significand >>= 1u32;
+        shift -= 1;
+    }
+    Some(BinaryParts {
+        negative: r.is_negative(),
+        exponent: -shift,
+        significand,
+        sticky,
+    })
+}
+
+/// Rounds to the nearest integer, ties to even.
+pub(crate) fn round_even(r: &BigRational) -> BigInt {
+    // The denominator is positive, so the remainder is too.
+    let (floor, remainder) = r.numer().div_mod_floor(r.denom());
+    match (remainder << 1u32).cmp(r.denom()) {
+        Ordering::Less => floor,
+        Ordering::Greater => floor + 1,
+        Ordering::Equal if floor.is_even() => floor,
+        Ordering::Equal => floor + 1,
+    }
+}
+
+/// Rounds the rational to nearest-even in an IEEE binary format with `digits` significant bits
+/// and smallest normal value `2^(min_exp - 1)` (as in `f64::MANTISSA_DIGITS` and `f64::MIN_EXP`),
+/// with unbounded exponent range above; the result is exact if it fits in an f64.
+///
+/// Past the f64 range, the result is infinite.
+pub(crate) fn round_binary(r: &BigRational, digits: u32, min_exp: i32) -> f64 {
+    // floor(log2(|r|)):
+    let Some(BinaryParts { exponent, .. }) = binary_parts(r, 1) else {
+        return 0.0;
+    };
+    // The last place, which is fixed in the subnormal range:
+    let digits = digits as i64;
+    let last = (exponent - digits + 1).max(min_exp as i64 - digits);
+    let (numer, denom) = if last >= 0 {
+        (r.numer().clone(), r.denom() << last as u64)
+    } else {
+        (r.numer() << (-last) as u64, r.denom().clone())
+    };
+    // At most `digits + 1` bits, when rounding carries into the next binade:
+    let significand = round_even(&BigRational::new(numer, denom));
+    ldexp(significand.to_f64().unwrap_or(f64::NAN), last)
+}
+
+/// Computes x * 2^exp, without overflowing in the intermediate power of two.
+pub(crate) fn ldexp(mut x: f64, mut exp: i64) -> f64 {
+    while exp > 1000 && x.is_finite() && x != 0.0 {
+        x *= 2f64.powi(1000);
+        exp -= 1000;
+    }
+    while exp < -1000 && x != 0.0 {
+        x *= 2f64.powi(-1000);
+        exp += 1000;
+    }
+    x * 2f64.powi(exp as i32)
+}
+
+/// Converts `steps` evenly-spaced points, starting at the start of the range.
+pub(crate) fn make_range<N>(
+    r: &Range<BigRational>,
+    steps: usize,
+    convert: &impl Fn(&BigRational) -> Result<N, String>,
+) -> Result<Vec<N>, String> {
+    let step = (&r.end - &r.start) / BigRational::new(steps.into(), 1.into());
+    let mut results = Vec::with_capacity(steps);
+    let mut next = r.start.clone();
+    for _ in 0..steps {
+        let converted = convert(&next)?;
+        results.push(converted);
+        next += &step;
+    }
+    Ok(results)
+}
+
 /// Complex number implementation.
 /// A little more granular than num_traits, because we're only interested in certain ops.
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
@@ -67,6 +188,48 @@ where
         Self { re, im }
     }
 
+    /// Like `square`, accumulating the real part with `FractalNumber::fused_sub_products` if the
+    /// format supports it.
+    pub fn square_fused(self) -> Self {
+        let (a, b) = (&self.re, &self.im);
+        let re = sub_products(a, a, b, b);
+        let im = <N as FractalNumber>::from_i32(2) * (self.re * self.im);
+        Self { re, im }
+    }
+
+    /// Like `*`, accumulating each part's products with fused accumulation if the format
+    /// supports it.
+    pub fn mul_fused(self, rhs: Complex<N>) -> Self {
+        let (a, b) = (&self.re, &self.im);
+        let (c, d) = (&rhs.re, &rhs.im);
+        Self {
+            re: sub_products(a, c, b, d),
+            im: add_products(a, d, b, c),
+        }
+    }
+
+    /// Like `/`, accumulating the products with fused accumulation if the format supports it.
+    pub fn div_fused(self, rhs: Complex<N>) -> Self {
+        let (a, b) = (&self.re, &self.im);
+        let (c, d) = (&rhs.re, &rhs.im);
+        let denominator = add_products(c, c, d, d);
+        Self {
+            re: add_products(a, c, b, d) / denominator.clone(),
+            im: sub_products(b, c, a, d) / denominator,
+        }
+    }
+
+    /// The squared magnitude, re^2 + im^2; with fused accumulation, if `fused` and the format
+    /// supports it.
+    pub fn magnitude_squared(&self, fused: bool) -> N {
+        let (re, im) = (&self.re, &self.im);
+        if fused {
+            add_products(re, re, im, im)
+        } else {
+            re.clone() * re.clone() + im.clone() * im.clone()
+        }
+    }
+
     /// Reports if two complex numbers are near each other.
     ///
     /// Near is defined as:
@@ -83,10 +246,22 @@ where
         let dre = self.re.clone() - rhs.re.clone();
         let dim = self.im.clone() - rhs.im.clone();
         let distance = dre.clone() * dre + dim.clone() * dim;
-        return distance < nearby;
+        distance < nearby
     }
 }
 
+/// a*b + c*d, fused if the format supports it.
+fn add_products<N: FractalNumber>(a: &N, b: &N, c: &N, d: &N) -> N {
+    N::fused_add_products(a, b, c, d)
+        .unwrap_or_else(|| a.clone() * b.clone() + c.clone() * d.clone())
+}
+
+/// a*b - c*d, fused if the format supports it.
+fn sub_products<N: FractalNumber>(a: &N, b: &N, c: &N, d: &N) -> N {
+    N::fused_sub_products(a, b, c, d)
+        .unwrap_or_else(|| a.clone() * b.clone() - c.clone() * d.clone())
+}
+
 impl<N> Mul<Complex<N>> for Complex<N>
 where
     N: Clone + Add<N, Output = N> + Sub<N, Output = N> + Mul<N, Output = N>,
@@ -163,3 +338,32 @@ where
         Complex { re, im }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use num::BigInt;
+
+    #[test]
+    fn test_binary_parts() {
+        let three_quarters = BigRational::new(3.into(), 4.into());
+        let parts = binary_parts(&three_quarters, 4).unwrap();
+        assert_eq!(parts.significand, BigUint::from(0b1100u32));
+        assert_eq!(parts.exponent, -4);
+        assert!(!parts.sticky);
+
+        let third = BigRational::new((-1).into(), 3.into());
+        let parts = binary_parts(&third, 5).unwrap();
+        assert!(parts.negative);
+        assert_eq!(parts.significand, BigUint::from(0b10101u32));
+        assert_eq!(parts.exponent, -6);
+        assert!(parts.sticky);
+
+        let big = BigRational::from_integer(BigInt::from(1) << 100u32);
+        let parts = binary_parts(&big, 3).unwrap();
+        assert_eq!(parts.significand, BigUint::from(0b100u32));
+        assert_eq!(parts.exponent, 98);
+
+        assert_eq!(binary_parts(&BigRational::zero(), 8), None);
+    }
+}
@@ -0,0 +1,488 @@
+//! Posit arithmetic, with any width and exponent size.
+//!
+//! `softposit` only provides the 8/16/32-bit posits of the original standard. This is a software
+//! implementation of `posit<n, es>` for 3 <= n <= 64 and es <= 4, including the 2022 standard's
+//! es=2 posits.
+//!
+//! Every operation decodes its operands, computes a result with enough extra bits to round
+//! correctly, and re-encodes with round-to-nearest-even on the bit string.
+//! As in the standard, results never round to zero or NaR: they saturate at minpos/maxpos.
+
+use std::cmp::Ordering;
+use std::ops::{Add, Div, Mul, Neg, Sub};
+
+use num::{BigRational, ToPrimitive};
+
+use crate::numeric::{binary_parts, FromRational};
+
+/// An N-bit posit with ES exponent bits.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Posit<const N: u32, const ES: u32> {
+    bits: u64,
+}
+
+/// A posit, decoded.
+#[derive(Copy, Clone, Debug)]
+enum Decoded {
+    Zero,
+    NaR,
+    /// The value is `sig * 2^(scale - 62)`; the hidden bit of `sig` is bit 62.
+    Finite {
+        negative: bool,
+        scale: i64,
+        sig: u64,
+    },
+}
+
+/// Position of the hidden bit in a decoded significand.
+const HIDDEN: i64 = 62;
+
+impl<const N: u32, const ES: u32> Posit<N, ES> {
+    const VALID: () = assert!(N >= 3 && N <= 64 && ES <= 4, "unsupported posit size");
+    const MASK: u64 = if N == 64 { u64::MAX } else { (1 << N) - 1 };
+    const MAXPOS: u64 = (1 << (N - 1)) - 1;
+    const MINPOS: u64 = 1;
+    /// The scale (power of two) of maxpos; minpos has the negation.
+    const MAX_SCALE: i64 = ((N - 2) as i64) << ES;
+
+    pub const ZERO: Self = Posit { bits: 0 };
+    pub const NAR: Self = Posit { bits: 1 << (N - 1) };
+
+    pub fn from_bits(bits: u64) -> Self {
+        #[allow(clippy::let_unit_value)]
+        let _ = Self::VALID;
+        Posit {
+            bits: bits & Self::MASK,
+        }
+    }
+
+    pub fn to_bits(self) -> u64 {
+        self.bits
+    }
+
+    pub fn is_nar(self) -> bool {
+        self == Self::NAR
+    }
+
+    /// The bits, sign-extended; posits are ordered as two's-complement integers.
+    fn signed(self) -> i64 {
+        ((self.bits << (64 - N)) as i64) >> (64 - N)
+    }
+
+    fn decode(self) -> Decoded {
+        if self.bits == 0 {
+            return Decoded::Zero;
+        }
+        if self.bits == Self::NAR.bits {
+            return Decoded::NaR;
+        }
+        let negative = self.bits >> (N - 1) != 0;
+        let x = if negative {
+            self.bits.wrapping_neg() & Self::MASK
+        } else {
+            self.bits
+        };
+        // Align the bits after the sign bit to the top of the word.
+        let y = x << (65 - N);
+        let (run, k) = if y >> 63 == 1 {
+            let run = y.leading_ones();
+            (run, run as i64 - 1)
+        } else {
+            let run = y.leading_zeros();
+            (run, -(run as i64))
+        };
+        // Skip the regime and its terminating bit; anything past the end of the posit is zero.
+        let rest = y.checked_shl(run + 1).unwrap_or(0);
+        let exponent = if ES == 0 { 0 } else { rest >> (64 - ES) };
+        let fraction = rest << ES;
+        Decoded::Finite {
+            negative,
+            scale: (k << ES) + exponent as i64,
+            sig: (1 << HIDDEN) | (fraction >> 2),
+        }
+    }
+
+    /// Rounds `sig * 2^exponent` (plus a bit less than `2^exponent`, if sticky) to a posit.
+    fn encode(negative: bool, exponent: i64, sig: u128, sticky: bool) -> Self {
+        if sig == 0 {
+            return Self::ZERO;
+        }
+        let top = 127 - sig.leading_zeros() as i64;
+        let scale = exponent + top;
+        // The 64 bits after the leading one, and whether anything is left over:
+        let aligned = sig << (127 - top);
+        let fraction = (aligned >> 63) as u64;
+        let sticky = sticky || (aligned & ((1 << 63) - 1)) != 0;
+
+        let magnitude = if scale >= Self::MAX_SCALE {
+            Self::MAXPOS
+        } else if scale < -Self::MAX_SCALE {
+            Self::MINPOS
+        } else {
+            let k = scale >> ES;
+            let exponent = (scale & ((1 << ES) - 1)) as u128;
+            let (regime, regime_len) = if k >= 0 {
+                // k+1 ones, then a zero:
+                (((1u64 << (k + 1)) - 1) << 1, k + 2)
+            } else {
+                // -k zeros, then a one:
+                (1u64, 1 - k)
+            };
+            // Bits available for exponent and fraction:
+            let remaining = (N as i64 - 1 - regime_len) as u32;
+            let tail = (exponent << 64) | fraction as u128;
+            let shift = ES + 64 - remaining;
+            let kept = (tail >> shift) as u64;
+            let guard = (tail >> (shift - 1)) & 1 == 1;
+            let lower = tail & ((1 << (shift - 1)) - 1) != 0 || sticky;
+            let result = (regime << remaining) | kept;
+            if guard && (lower || result & 1 == 1) {
+                result + 1
+            } else {
+                result
+            }
+        };
+        if negative {
+            Self::from_bits(magnitude.wrapping_neg())
+        } else {
+            Self::from_bits(magnitude)
+        }
+    }
+
+    pub fn from_f64(f: f64) -> Self {
+        if f.is_nan() || f.is_infinite() {
+            return Self::NAR;
+        }
+        if f == 0.0 {
+            return Self::ZERO;
+        }
+        let bits = f.to_bits();
+        let biased = ((bits >> 52) & 0x7ff) as i64;
+        let fraction = bits & ((1 << 52) - 1);
+        let (sig, exponent) = if biased == 0 {
+            (fraction, -1074)
+        } else {
+            (fraction | (1 << 52), biased - 1075)
+        };
+        Self::encode(f < 0.0, exponent, sig as u128, false)
+    }
+
+    pub fn from_i32(i: i32) -> Self {
+        Self::encode(i < 0, 0, i.unsigned_abs() as u128, false)
+    }
+
+    pub fn to_f64(self) -> f64 {
+        match self.decode() {
+            Decoded::Zero => 0.0,
+            Decoded::NaR => f64::NAN,
+            Decoded::Finite {
+                negative,
+                scale,
+                sig,
+            } => {
+                let magnitude = sig as f64 * 2f64.powi((scale - HIDDEN) as i32);
+                if negative {
+                    -magnitude
+                } else {
+                    magnitude
+                }
+            }
+        }
+    }
+}
+
+impl<const N: u32, const ES: u32> From<Posit<N, ES>> for f64 {
+    fn from(p: Posit<N, ES>) -> Self {
+        p.to_f64()
+    }
+}
+
+impl<const N: u32, const ES: u32> PartialOrd for Posit<N, ES> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.signed().cmp(&other.signed()))
+    }
+}
+
+impl<const N: u32, const ES: u32> Neg for Posit<N, ES> {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        // Two's complement; zero and NaR are their own negations.
+        Self::from_bits(self.bits.wrapping_neg())
+    }
+}
+
+impl<const N: u32, const ES: u32> Add for Posit<N, ES> {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        match (self.decode(), other.decode()) {
+            (Decoded::NaR, _) | (_, Decoded::NaR) => Self::NAR,
+            (Decoded::Zero, _) => other,
+            (_, Decoded::Zero) => self,
+            (
+                Decoded::Finite {
+                    negative: an,
+                    scale: ascale,
+                    sig: asig,
+                },
+                Decoded::Finite {
+                    negative: bn,
+                    scale: bscale,
+                    sig: bsig,
+                },
+            ) => Self::sum((an, ascale, asig), (bn, bscale, bsig)),
+        }
+    }
+}
+
+impl<const N: u32, const ES: u32> Posit<N, ES> {
+    /// Adds two finite, nonzero (negative, scale, significand) values.
+    fn sum(a: (bool, i64, u64), b: (bool, i64, u64)) -> Self {
+        // Put the larger-magnitude operand first.
+        let ((an, ascale, asig), (bn, bscale, bsig)) = if (a.1, a.2) >= (b.1, b.2) {
+            (a, b)
+        } else {
+            (b, a)
+        };
+        // Hidden bit at 126, leaving a bit of headroom for carry.
+        let a = (asig as u128) << 64;
+        let b = (bsig as u128) << 64;
+        let distance = (ascale - bscale) as u32;
+        let (b, sticky) = if distance >= 128 {
+            (0, true)
+        } else {
+            (b >> distance, b & ((1 << distance) - 1) != 0)
+        };
+        let sig = if an == bn {
+            a + b
+        } else if sticky {
+            // The true value of b is a little larger than the shifted value.
+            a - b - 1
+        } else {
+            a - b
+        };
+        Self::encode(an, ascale - HIDDEN - 64, sig, sticky)
+    }
+}
+
+impl<const N: u32, const ES: u32> Sub for Posit<N, ES> {
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self {
+        self + -other
+    }
+}
+
+impl<const N: u32, const ES: u32> Mul for Posit<N, ES> {
+    type Output = Self;
+
+    fn mul(self, other: Self) -> Self {
+        match (self.decode(), other.decode()) {
+            (Decoded::NaR, _) | (_, Decoded::NaR) => Self::NAR,
+            (Decoded::Zero, _) | (_, Decoded::Zero) => Self::ZERO,
+            (
+                Decoded::Finite {
+                    negative: an,
+                    scale: ascale,
+                    sig: asig,
+                },
+                Decoded::Finite {
+                    negative: bn,
+                    scale: bscale,
+                    sig: bsig,
+                },
+            ) => Self::encode(
+                an != bn,
+                ascale + bscale - 2 * HIDDEN,
+                asig as u128 * bsig as u128,
+                false,
+            ),
+        }
+    }
+}
+
+impl<const N: u32, const ES: u32> Div for Posit<N, ES> {
+    type Output = Self;
+
+    fn div(self, other: Self) -> Self {
+        match (self.decode(), other.decode()) {
+            (Decoded::NaR, _) | (_, Decoded::NaR) | (_, Decoded::Zero) => Self::NAR,
+            (Decoded::Zero, _) => Self::ZERO,
+            (
+                Decoded::Finite {
+                    negative: an,
+                    scale: ascale,
+                    sig: asig,
+                },
+                Decoded::Finite {
+                    negative: bn,
+                    scale: bscale,
+                    sig: bsig,
+                },
+            ) => {
+                let dividend = (asig as u128) << 64;
+                let divisor = bsig as u128;
+                Self::encode(
+                    an != bn,
+                    ascale - bscale - 64,
+                    dividend / divisor,
+                    !dividend.is_multiple_of(divisor),
+                )
+            }
+        }
+    }
+}
+
+impl<const N: u32, const ES: u32> FromRational for Posit<N, ES> {
+    fn from_bigrational(r: &BigRational) -> Result<Self, String> {
+        // Enough bits to round any posit up to 64 bits correctly:
+        let Some(parts) = binary_parts(r, 100) else {
+            return Ok(Self::ZERO);
+        };
+        let sig = parts
+            .significand
+            .to_u128()
+            .ok_or_else(|| format!("significand of {} out of range", r))?;
+        Ok(Self::encode(
+            parts.negative,
+            parts.exponent,
+            sig,
+            parts.sticky,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use softposit::{P16, P32, P8};
+
+    type Posit8 = Posit<8, 0>;
+
+    #[test]
+    fn test_p8_exhaustive() {
+        // Posit<8, 0> is the original standard's 8-bit posit.
+        for a in 0..=255u8 {
+            let (pa, sa) = (Posit8::from_bits(a as u64), P8::from_bits(a));
+            if !pa.is_nar() {
+                assert_eq!(pa.to_f64(), f64::from(sa), "{:#x}", a);
+            }
+            for b in 0..=255u8 {
+                let (pb, sb) = (Posit8::from_bits(b as u64), P8::from_bits(b));
+                assert_eq!(
+                    (pa + pb).to_bits(),
+                    (sa + sb).to_bits() as u64,
+                    "{a:#x} + {b:#x}"
+                );
+                assert_eq!(
+                    (pa - pb).to_bits(),
+                    (sa - sb).to_bits() as u64,
+                    "{a:#x} - {b:#x}"
+                );
+                assert_eq!(
+                    (pa * pb).to_bits(),
+                    (sa * sb).to_bits() as u64,
+                    "{a:#x} * {b:#x}"
+                );
+                if b != 0 {
+                    assert_eq!(
+                        (pa / pb).to_bits(),
+                        (sa / sb).to_bits() as u64,
+                        "{a:#x} / {b:#x}"
+                    );
+                }
+                assert_eq!(pa.partial_cmp(&pb), sa.partial_cmp(&sb));
+            }
+        }
+    }
+
+    #[test]
+    fn test_matches_softposit() {
+        let values = [
+            0.0,
+            1.0,
+            -1.0,
+            0.1,
+            -0.3,
+            1.5,
+            3.75,
+            1e-6,
+            -2e5,
+            1e30,
+            4.0,
+            0.0625,
+            1.0 / 3.0,
+        ];
+        for a in values {
+            for b in values {
+                let (pa, pb) = (Posit::<32, 2>::from_f64(a), Posit::<32, 2>::from_f64(b));
+                let (sa, sb) = (P32::from_f64(a), P32::from_f64(b));
+                assert_eq!(pa.to_bits(), sa.to_bits() as u64, "{}", a);
+                assert_eq!((pa + pb).to_bits(), (sa + sb).to_bits() as u64, "{a} + {b}");
+                assert_eq!((pa - pb).to_bits(), (sa - sb).to_bits() as u64, "{a} - {b}");
+                assert_eq!((pa * pb).to_bits(), (sa * sb).to_bits() as u64, "{a} * {b}");
+                assert_eq!((pa / pb).to_bits(), (sa / sb).to_bits() as u64, "{a} / {b}");
+
+                let (pa, pb) = (Posit::<16, 1>::from_f64(a), Posit::<16, 1>::from_f64(b));
+                let (sa, sb) = (P16::from_f64(a), P16::from_f64(b));
+                assert_eq!(pa.to_bits(), sa.to_bits() as u64, "{}", a);
+                assert_eq!((pa + pb).to_bits(), (sa + sb).to_bits() as u64, "{a} + {b}");
+                assert_eq!((pa * pb).to_bits(), (sa * sb).to_bits() as u64, "{a} * {b}");
+                assert_eq!((pa / pb).to_bits(), (sa / sb).to_bits() as u64, "{a} / {b}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_nar() {
+        type P = Posit<12, 1>;
+        let one = P::from_i32(1);
+        assert!((one / P::ZERO).is_nar());
+        assert!((P::NAR + one).is_nar());
+        assert!((P::NAR * P::ZERO).is_nar());
+        assert!(P::from_f64(f64::NAN).is_nar());
+        assert!(P::NAR.to_f64().is_nan());
+        assert!(P::NAR < P::from_i32(-1000));
+    }
+
+    #[test]
+    fn test_saturation() {
+        type P = Posit<12, 1>;
+        // maxpos = useed^(n-2) = 4^10
+        let maxpos = P::from_bits(0x7ff);
+        assert_eq!(maxpos.to_f64(), 1048576.0);
+        assert_eq!(maxpos * maxpos, maxpos);
+        let minpos = P::from_bits(1);
+        assert_eq!(minpos.to_f64(), 1.0 / 1048576.0);
+        assert_eq!(minpos * minpos, minpos);
+        assert_eq!(-minpos * minpos, -minpos);
+    }
+
+    #[test]
+    fn test_round_to_nearest_even() {
+        type P = Posit<8, 2>;
+        // Near 1, posit<8,2> has three fraction bits: 1 + 1/16 is a tie between 1 and 1 + 1/8.
+        let one = P::from_i32(1);
+        let tie = P::from_bigrational(&BigRational::new(17.into(), 16.into())).unwrap();
+        assert_eq!(tie, one);
+        let tie = P::from_bigrational(&BigRational::new(19.into(), 16.into())).unwrap();
+        assert_eq!(tie.to_f64(), 1.25);
+        let above = P::from_bigrational(&BigRational::new(35.into(), 32.into())).unwrap();
+        assert_eq!(above.to_f64(), 1.125);
+    }
+
+    #[test]
+    fn test_from_rational() {
+        let third = BigRational::new(1.into(), 3.into());
+        assert_eq!(
+            Posit::<32, 2>::from_bigrational(&third).unwrap().to_bits(),
+            P32::from_f64(1.0 / 3.0).to_bits() as u64
+        );
+        let neg = BigRational::new((-7).into(), 2.into());
+        assert_eq!(
+            Posit::<24, 2>::from_bigrational(&neg).unwrap().to_f64(),
+            -3.5
+        );
+    }
+}
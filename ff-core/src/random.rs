@@ -0,0 +1,44 @@
+//! A small, fast, per-thread pseudorandom source, for stochastic numeric behaviors.
+//!
+//! This doesn't need to be cryptographically strong- just cheap, and uncorrelated between
+//! threads. Each thread's generator is seeded from std's randomized hasher state.
+
+use std::cell::Cell;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+
+thread_local! {
+    static STATE: Cell<u64> = Cell::new(seed());
+}
+
+fn seed() -> u64 {
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u64(0x9e37_79b9_7f4a_7c15);
+    // xorshift must not start at zero.
+    hasher.finish() | 1
+}
+
+/// Returns the next pseudorandom 64 bits for this thread (xorshift64*).
+pub(crate) fn next_u64() -> u64 {
+    STATE.with(|state| {
+        let mut x = state.get();
+        x ^= x >> 12;
+        x ^= x << 25;
+        x ^= x >> 27;
+        state.set(x);
+        x.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    })
+}
+
+/// Reseeds this thread's generator with the given stream of the given seed.
+///
+/// Seeding per pixel (stream) makes a render reproducible, whichever thread evaluates the pixel.
+pub(crate) fn reseed(seed: u64, stream: u64) {
+    // splitmix64's finalizer, so nearby seeds and streams are uncorrelated.
+    let mut z = seed ^ stream.wrapping_mul(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^= z >> 31;
+    // xorshift must not start at zero.
+    STATE.with(|state| state.set(z | 1));
+}
@@ -0,0 +1,378 @@
+//! The named numeric formats, and which fractals list them.
+//!
+//! Each fractal dispatches formats through `visit_any`, and lists them with `listed`.
+//! Adding a format is one line in the table at the bottom of this file.
+//!
+//! Parameterized families aren't listed here, since they have too many members. `visit_any`
+//! also accepts:
+//!
+//! - `MaskedFloat<E,F>` for any E <= 10 and F <= 52, optionally with a rounding mode
+//!   (`MaskedFloat<E,F,RNE>`; see `masked_float::Rounding`) and IEEE special values
+//!   (`MaskedFloat<E,F,RNE,IEEE>`; see `masked_float::MaskedSpecials`);
+//! - `BigFloat<P>` for any precision P (in bits);
+//! - the fixed-point formats with an overflow policy (e.g. `Saturating<I16F16>`; see
+//!   `fixed_point`). Bare, they wrap.
+//!
+//! The families that wrap other formats, or evaluate them a block at a time, are parsed by each
+//! fractal's `compute`:
+//!
+//! - any of `mx::FORMATS` with a block size (e.g. `MXFP4<16>`);
+//! - any of `mca::INNER_FORMATS` with Monte Carlo arithmetic at a virtual precision
+//!   (e.g. `MCA<f64,24>`);
+//! - mixed formats for the coordinates, state, and tests (e.g. `Mixed<f64,P16,f64>`; see
+//!   `mixed`).
+
+use num::BigRational;
+
+use crate::big_float::{BigFloat, BigFloatFormat};
+use crate::decimal::{Decimal128, Decimal32, Decimal64};
+use crate::fixed_point::{Fixed, Wrapping};
+use crate::interval::Interval;
+use crate::lns::Lns;
+use crate::masked_float::{DynMaskedFloat, MaskedFloat, MaskedFormat};
+use crate::multi_double::{DoubleDouble, QuadDouble};
+use crate::number::{ConvertVisitor, FractalNumber, NumberVisitor};
+use crate::posit::Posit;
+use crate::slash::{FixedSlash, FloatingSlash};
+use crate::small_float::{BFloat16, Binary16, Fp8E4M3, Fp8E5M2, Tf32};
+use crate::takum::{Linear, Logarithmic, Takum};
+use fixed::types::{I11F5, I13F3, I15F1, I16F16, I20F12, I22F10, I32F32, I64F64, I8F8};
+
+/// A fractal that formats can be listed for.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Fractal {
+    Mandelbrot,
+    Newton,
+}
+
+/// The kind of number a format is.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Family {
+    /// IEEE 754-style binary floating-point, at any width.
+    BinaryFloat,
+    /// IEEE 754 decimal floating-point.
+    DecimalFloat,
+    /// Unevaluated sums of f64s.
+    MultiDouble,
+    /// f64 with a narrower exponent and fraction.
+    MaskedFloat,
+    Posit,
+    Takum,
+    /// Logarithmic number system.
+    Lns,
+    /// Bounded rationals.
+    Slash,
+    FixedPoint,
+    /// Outward-rounded intervals of another format.
+    Interval,
+    /// OCP microscaling: narrow elements sharing a scale per block.
+    Microscaling,
+}
+
+/// How a format's points are evaluated.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Evaluation {
+    /// Each point is a FractalNumber, evaluated on its own.
+    Number,
+    /// Points are evaluated a block at a time, sharing state (e.g. an MX scale).
+    Block,
+}
+
+/// A named numeric format.
+#[derive(Copy, Clone, Debug)]
+pub struct Format {
+    pub name: &'static str,
+    pub family: Family,
+    /// Bits of storage per value. For block formats, this doesn't count the shared state.
+    pub bits: u32,
+    pub evaluation: Evaluation,
+    /// Fractals this format isn't listed for, and why.
+    /// Excluded formats can still be requested by name.
+    pub exclusions: &'static [(Fractal, &'static str)],
+}
+
+impl Format {
+    /// If this format is excluded from the fractal's list, why.
+    pub fn exclusion(&self, fractal: Fractal) -> Option<&'static str> {
+        self.exclusions
+            .iter()
+            .find(|(excluded, _)| *excluded == fractal)
+            .map(|(_, reason)| *reason)
+    }
+}
+
+/// Looks up a format by name.
+pub fn find(name: &str) -> Option<&'static Format> {
+    FORMATS.iter().find(|format| format.name == name)
+}
+
+/// The formats listed for the fractal.
+pub fn listed(fractal: Fractal) -> impl Iterator<Item = &'static Format> {
+    FORMATS
+        .iter()
+        .filter(move |format| format.exclusion(fractal).is_none())
+}
+
+/// Applies the visitor to the named format: a named `Evaluation::Number` format, a fixed-point
+/// format with an overflow policy, or a `MaskedFloat<E,F>` or `BigFloat<P>` with any
+/// parameters.
+///
+/// None if `name` isn't one of those; an error if it's in a family, with invalid parameters.
+pub(crate) fn visit_any<V: ConvertVisitor>(
+    name: &str,
+    visitor: V,
+) -> Option<Result<V::Output, String>> {
+    if find(name).is_some() {
+        return visit(name, Converting(visitor)).map(Ok);
+    }
+    // Other MaskedFloat widths are masked at runtime:
+    if name.starts_with("MaskedFloat<") {
+        return Some(name.parse().map(|format: MaskedFormat| {
+            visitor.visit(move |r| Ok(DynMaskedFloat::from_rational(r, format)))
+        }));
+    }
+    if name.starts_with("BigFloat<") {
+        return Some(name.parse().map(|format: BigFloatFormat| {
+            visitor.visit(move |r| Ok(BigFloat::from_rational(r, format)))
+        }));
+    }
+    crate::fixed_point::visit(name, Converting(visitor)).map(Ok)
+}
+
+/// Visits a format whose type has all of its parameters, which converts with its `FromRational`.
+struct Converting<V>(V);
+
+impl<V: ConvertVisitor> NumberVisitor for Converting<V> {
+    type Output = V::Output;
+
+    fn visit<N: FractalNumber + Send + Sync + 'static>(self) -> Self::Output {
+        self.0.visit(|r: &BigRational| N::from_bigrational(r))
+    }
+}
+
+macro_rules! visit_format {
+    ($name:literal, $visitor:ident, Number, $t:ty) => {
+        Some($visitor.visit::<$t>())
+    };
+    // Bare fixed-point names wrap on overflow.
+    ($name:literal, $visitor:ident, Fixed, $t:ty) => {
+        Some($visitor.visit::<Fixed<$t, Wrapping>>())
+    };
+    // Block formats aren't FractalNumbers; each fractal evaluates them by name.
+    ($name:literal, $visitor:ident, Block,) => {
+        None
+    };
+}
+
+/// Fixed-point rows are `Fixed<F>`, for the `fixed` type F: each also takes an overflow policy.
+macro_rules! fixed_format {
+    ($policy:ident, $visitor:ident, Fixed, $t:ty) => {
+        crate::fixed_point::visit_policy::<$t, V>($policy, $visitor)
+    };
+    ($policy:ident, $visitor:ident, $evaluation:ident, $($t:ty)?) => {
+        None
+    };
+}
+
+macro_rules! evaluation {
+    (Fixed) => {
+        Evaluation::Number
+    };
+    ($evaluation:ident) => {
+        Evaluation::$evaluation
+    };
+}
+
+macro_rules! impl_fixed_row {
+    (Fixed, $t:ty) => {
+        crate::number::impl_fixed!($t);
+    };
+    ($evaluation:ident, $($t:ty)?) => {};
+}
+
+macro_rules! formats {
+    ($($name:literal: $family:ident $bits:literal, $evaluation:ident$(<$t:ty>)?
+        $(, except $($fractal:ident: $reason:expr),+)?;)*) => {
+        /// All of the named formats.
+        pub const FORMATS: &[Format] = &[$(Format {
+            name: $name,
+            family: Family::$family,
+            bits: $bits,
+            evaluation: evaluation!($evaluation),
+            exclusions: &[$($((Fractal::$fractal, $reason)),+)?],
+        }),*];
+
+        /// If `name` is a named `Evaluation::Number` format, applies the visitor to its type.
+        pub(crate) fn visit<V: NumberVisitor>(name: &str, visitor: V) -> Option<V::Output> {
+            match name {
+                $($name => visit_format!($name, visitor, $evaluation, $($t)?),)*
+                _ => None,
+            }
+        }
+
+        /// If `width` names a fixed-point format, applies the visitor to it with the overflow
+        /// policy named by `policy`.
+        pub(crate) fn visit_fixed<V: NumberVisitor>(
+            width: &str,
+            policy: &str,
+            visitor: V,
+        ) -> Option<V::Output> {
+            match width {
+                $($name => fixed_format!(policy, visitor, $evaluation, $($t)?),)*
+                _ => None,
+            }
+        }
+
+        $(impl_fixed_row!($evaluation, $($t)?);)*
+    };
+}
+
+/// Reason for excluding the narrowest formats from Newton's fractal.
+const NO_CONVERGENCE: &str = "doesn't produce interesting images; mostly fails to converge";
+
+/// Reason for excluding fixed-point formats with only a few fraction bits.
+const COARSE: &str = "too few fraction bits to resolve the default window";
+
+/// Reason for excluding intervals from Newton's fractal.
+const NO_CERTIFIED_ZEROS: &str =
+    "only the escape test is tri-state; intervals widen until no zero is certain";
+
+formats! {
+    "f32": BinaryFloat 32, Number<f32>;
+    "f64": BinaryFloat 64, Number<f64>;
+    // Decimal formats, for comparison against the binary ones:
+    "decimal32": DecimalFloat 32, Number<Decimal32>;
+    "decimal64": DecimalFloat 64, Number<Decimal64>;
+    "decimal128": DecimalFloat 128, Number<Decimal128>;
+    // Reference formats, for zooms past f64:
+    "DoubleDouble": MultiDouble 128, Number<DoubleDouble>;
+    "QuadDouble": MultiDouble 256, Number<QuadDouble>;
+    "TF32": BinaryFloat 19, Number<Tf32>;
+    "bfloat16": BinaryFloat 16, Number<BFloat16>;
+    "binary16": BinaryFloat 16, Number<Binary16>;
+    "FP8-E5M2": BinaryFloat 8, Number<Fp8E5M2>;
+    "FP8-E4M3": BinaryFloat 8, Number<Fp8E4M3>;
+    "P32": Posit 32, Number<softposit::P32>;
+    "P16": Posit 16, Number<softposit::P16>;
+    "P8": Posit 8, Number<softposit::P8>, except Newton: NO_CONVERGENCE;
+    // Takums, at the same widths:
+    "Takum<32>": Takum 32, Number<Takum<32, Logarithmic>>;
+    "Takum<16>": Takum 16, Number<Takum<16, Logarithmic>>;
+    "Takum<8>": Takum 8, Number<Takum<8, Logarithmic>>;
+    "LinearTakum<32>": Takum 32, Number<Takum<32, Linear>>;
+    "LinearTakum<16>": Takum 16, Number<Takum<16, Linear>>;
+    "LinearTakum<8>": Takum 8, Number<Takum<8, Linear>>;
+    "Posit<32,2>": Posit 32, Number<Posit<32, 2>>;
+    "Posit<24,2>": Posit 24, Number<Posit<24, 2>>;
+    "Posit<16,2>": Posit 16, Number<Posit<16, 2>>;
+    "Posit<12,1>": Posit 12, Number<Posit<12, 1>>;
+    "Posit<8,2>": Posit 8, Number<Posit<8, 2>>, except Newton: NO_CONVERGENCE;
+    "LNS<8,23>": Lns 32, Number<Lns<8, 23>>;
+    "LNS<5,10>": Lns 16, Number<Lns<5, 10>>;
+    // Bounded rationals, between BigRational and fixed-point:
+    // (sign, numerator and denominator; floating-slash adds the slash position.)
+    "FixedSlash<16>": Slash 33, Number<FixedSlash<16>>;
+    "FixedSlash<32>": Slash 65, Number<FixedSlash<32>>;
+    "FloatingSlash<32>": Slash 39, Number<FloatingSlash<32>>;
+    "FloatingSlash<64>": Slash 72, Number<FloatingSlash<64>>;
+    // Intervals render points they can't certify as escaped (or not) in gray:
+    "Interval<f64>": Interval 128, Number<Interval<f64>>, except Newton: NO_CERTIFIED_ZEROS;
+    "Interval<f32>": Interval 64, Number<Interval<f32>>, except Newton: NO_CERTIFIED_ZEROS;
+    "Interval<bfloat16>": Interval 32, Number<Interval<BFloat16>>, except Newton: NO_CERTIFIED_ZEROS;
+    "Interval<binary16>": Interval 32, Number<Interval<Binary16>>, except Newton: NO_CERTIFIED_ZEROS;
+    "MaskedFloat<3,50>": MaskedFloat 54, Number<MaskedFloat<3, 50>>, except Newton: NO_CONVERGENCE;
+    "MaskedFloat<4,50>": MaskedFloat 55, Number<MaskedFloat<4, 50>>;
+    "MaskedFloat<6,3>": MaskedFloat 10, Number<MaskedFloat<6, 3>>;
+    // Fixed-point; each width is also available with an overflow policy, e.g. `Checked<I8F8>`.
+    "I64F64": FixedPoint 128, Fixed<I64F64>;
+    "I32F32": FixedPoint 64, Fixed<I32F32>;
+    "I16F16": FixedPoint 32, Fixed<I16F16>;
+    "I22F10": FixedPoint 32, Fixed<I22F10>;
+    "I20F12": FixedPoint 32, Fixed<I20F12>;
+    "I8F8": FixedPoint 16, Fixed<I8F8>;
+    "I11F5": FixedPoint 16, Fixed<I11F5>;
+    "I13F3": FixedPoint 16, Fixed<I13F3>,
+        except Mandelbrot: COARSE, Newton: COARSE;
+    "I15F1": FixedPoint 16, Fixed<I15F1>,
+        except Mandelbrot: COARSE, Newton: COARSE;
+    // Microscaling, at the default block size:
+    "MXFP8-E4M3": Microscaling 8, Block;
+    "MXFP8-E5M2": Microscaling 8, Block;
+    "MXFP6-E2M3": Microscaling 6, Block;
+    "MXFP6-E3M2": Microscaling 6, Block;
+    "MXFP4": Microscaling 4, Block;
+    "MXINT8": Microscaling 8, Block;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_names_unique() {
+        for (i, format) in FORMATS.iter().enumerate() {
+            assert!(
+                FORMATS[..i].iter().all(|other| other.name != format.name),
+                "duplicate format {}",
+                format.name
+            );
+        }
+    }
+
+    #[test]
+    fn test_covers_modules() {
+        for format in FORMATS.iter().filter(|f| f.family == Family::FixedPoint) {
+            let (int, frac) = format.name[1..].split_once('F').unwrap();
+            let width = int.parse::<u32>().unwrap() + frac.parse::<u32>().unwrap();
+            assert_eq!(width, format.bits, "{}", format.name);
+        }
+        for name in crate::mx::FORMATS {
+            let format = find(name).unwrap();
+            assert_eq!(format.evaluation, Evaluation::Block, "{}", name);
+            assert!(name.parse::<crate::mx::MxFormat>().is_ok(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_listed() {
+        let newton: Vec<_> = listed(Fractal::Newton).map(|f| f.name).collect();
+        assert!(newton.contains(&"P16"));
+        assert!(!newton.contains(&"P8"));
+        assert!(listed(Fractal::Mandelbrot).any(|f| f.name == "P8"));
+        assert_eq!(
+            find("P8").unwrap().exclusion(Fractal::Newton),
+            Some(NO_CONVERGENCE)
+        );
+        assert!(find("MaskedFloat<5,5>").is_none());
+    }
+
+    struct Name;
+
+    impl ConvertVisitor for Name {
+        type Output = &'static str;
+
+        fn visit<N: FractalNumber + Send + Sync + 'static>(
+            self,
+            _: impl Fn(&BigRational) -> Result<N, String> + Send + Sync + 'static,
+        ) -> &'static str {
+            std::any::type_name::<N>()
+        }
+    }
+
+    #[test]
+    fn test_visit_any() {
+        let name = |format: &str| visit_any(format, Name);
+        assert!(name("f64").unwrap().unwrap().ends_with("f64"));
+        // Listed members of families keep their static types:
+        assert!(name("MaskedFloat<6,3>").unwrap().unwrap().contains("::MaskedFloat<6, 3>"));
+        assert!(name("MaskedFloat<5,5>").unwrap().unwrap().contains("DynMaskedFloat"));
+        assert!(name("BigFloat<80>").unwrap().unwrap().contains("BigFloat"));
+        assert!(name("Saturating<I16F16>").unwrap().unwrap().contains("Saturating"));
+        assert!(name("I16F16").unwrap().unwrap().contains("Wrapping"));
+        assert!(name("Checked<I12F4>").is_none());
+        assert!(name("MaskedFloat<99,99>").unwrap().is_err());
+        assert!(name("MXFP4").is_none());
+        assert!(name("MCA<f64,24>").is_none());
+        assert!(name("f65").is_none());
+    }
+}
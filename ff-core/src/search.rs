@@ -0,0 +1,193 @@
+//! Automated search for farlands: windows where a format's render differs from a reference's.
+//!
+//! The search scans a region at coarse resolution, scores a grid of sub-windows by the fraction of
+//! their points where the formats differ (see `divergence::compare`), and zooms into the
+//! highest-scoring sub-windows, level by level. Windows are kept exact, so each result can be
+//! rendered again at any resolution.
+
+use std::ops::Range;
+
+use num::BigRational;
+
+use crate::divergence::{self, Divergence, Measure};
+use crate::{CancelContext, CommonParams, FractalParams, Size};
+
+/// How to search.
+#[derive(Clone, Debug)]
+pub struct SearchParams {
+    /// What counts as a difference between the formats.
+    pub measure: Measure,
+    /// Sub-windows per side of each scanned window.
+    pub split: usize,
+    /// How many times to zoom in.
+    pub depth: usize,
+    /// How many sub-windows to zoom into at each level.
+    pub beam: usize,
+    /// How many windows to report.
+    pub top: usize,
+}
+
+impl Default for SearchParams {
+    fn default() -> Self {
+        SearchParams {
+            measure: Measure::default(),
+            split: 4,
+            depth: 4,
+            beam: 2,
+            top: 8,
+        }
+    }
+}
+
+/// A window where the formats differ.
+#[derive(Clone, Debug)]
+pub struct Farland {
+    pub x: Range<BigRational>,
+    pub y: Range<BigRational>,
+    /// How many times the search zoomed in from the region to find this window.
+    pub depth: usize,
+    /// The fraction of the window's scanned points where the formats differ.
+    pub score: f64,
+}
+
+impl Farland {
+    fn contains(&self, other: &Farland) -> bool {
+        let within = |outer: &Range<BigRational>, inner: &Range<BigRational>| {
+            outer.start <= inner.start && inner.end <= outer.end
+        };
+        within(&self.x, &other.x) && within(&self.y, &other.y)
+    }
+}
+
+/// Searches the region in `params` for the windows where its format differs most from the
+/// `reference` format.
+///
+/// Each window is scanned at `params.size`. Returns up to `search.top` windows, highest score
+/// first, none of which contains another.
+pub fn search(
+    ctx: &dyn CancelContext,
+    params: &CommonParams,
+    fractal: &FractalParams,
+    reference: &str,
+    search: &SearchParams,
+) -> Result<Vec<Farland>, String> {
+    let Size { width, height } = params.size;
+    if search.split == 0 || width < search.split || height < search.split {
+        return Err(format!(
+            "can't split a {}x{} scan into {} sub-windows per side",
+            width, height, search.split
+        ));
+    }
+
+    let mut found = Vec::new();
+    let mut frontier = vec![(params.x.clone(), params.y.clone())];
+    for depth in 1..=search.depth {
+        let mut scored = Vec::new();
+        for (x, y) in frontier {
+            let window = CommonParams {
+                x,
+                y,
+                ..params.clone()
+            };
+            let divergence = divergence::compare(ctx, &window, fractal, reference)?;
+            scored.extend(score(&divergence, &window, search, depth));
+        }
+        scored.retain(|farland| farland.score > 0.0);
+        scored.sort_by(|a, b| b.score.total_cmp(&a.score));
+        frontier = scored
+            .iter()
+            .take(search.beam)
+            .map(|farland| (farland.x.clone(), farland.y.clone()))
+            .collect();
+        found.extend(scored);
+    }
+
+    // Among equal scores, prefer the deeper window: it's more specific.
+    found.sort_by(|a, b| b.score.total_cmp(&a.score).then(b.depth.cmp(&a.depth)));
+    let mut top: Vec<Farland> = Vec::new();
+    for farland in found {
+        if top.len() >= search.top {
+            break;
+        }
+        if !top.iter().any(|t| t.contains(&farland) || farland.contains(t)) {
+            top.push(farland);
+        }
+    }
+    Ok(top)
+}
+
+/// Scores the sub-windows of the window scanned in `params`.
+fn score(
+    divergence: &[Divergence],
+    params: &CommonParams,
+    search: &SearchParams,
+    depth: usize,
+) -> Vec<Farland> {
+    let Size { width, height } = params.size;
+    let split = search.split;
+    let mut differing = vec![0usize; split * split];
+    let mut total = vec![0usize; split * split];
+    for (i, d) in divergence.iter().enumerate() {
+        let (row, col) = (i / width, i % width);
+        let cell = (row * split / height) * split + col * split / width;
+        total[cell] += 1;
+        if d.differs(search.measure) {
+            differing[cell] += 1;
+        }
+    }
+    (0..split * split)
+        .map(|cell| Farland {
+            x: part(&params.x, cell % split, split),
+            y: part(&params.y, cell / split, split),
+            depth,
+            score: differing[cell] as f64 / total[cell] as f64,
+        })
+        .collect()
+}
+
+/// The i'th of n equal parts of the range.
+fn part(r: &Range<BigRational>, i: usize, n: usize) -> Range<BigRational> {
+    let step = (&r.end - &r.start) / BigRational::from_integer(n.into());
+    let start = &r.start + &step * BigRational::from_integer(i.into());
+    let end = &start + &step;
+    start..end
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{test_params, NeverCancel};
+
+    #[test]
+    fn test_none() {
+        let newton = FractalParams::Newton { iters: 32 };
+        let search = SearchParams::default();
+        let found = super::search(&NeverCancel(), &test_params("f64", 16), &newton, "f64", &search);
+        assert!(found.unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_coarse() {
+        let newton = FractalParams::Newton { iters: 32 };
+        let search = SearchParams {
+            measure: Measure::Root,
+            depth: 2,
+            beam: 1,
+            ..Default::default()
+        };
+        let region = test_params("FP8-E4M3", 16);
+        let found = super::search(&NeverCancel(), &region, &newton, "f64", &search).unwrap();
+        assert!(!found.is_empty());
+        assert!(found.len() <= search.top);
+        assert!(found.windows(2).all(|w| w[0].score >= w[1].score));
+        for farland in &found {
+            // Windows are exact parts of the region.
+            let width = BigRational::from_integer(4.into())
+                / BigRational::from_integer(4usize.pow(farland.depth as u32).into());
+            assert_eq!(&farland.x.end - &farland.x.start, width);
+            assert_eq!(&farland.y.end - &farland.y.start, width);
+            assert!(region.x.start <= farland.x.start && farland.x.end <= region.x.end);
+            assert!(region.y.start <= farland.y.start && farland.y.end <= region.y.end);
+        }
+    }
+}
@@ -0,0 +1,457 @@
+//! Bounded rationals: fixed-slash and floating-slash formats.
+//!
+//! A slash number is a sign and a fraction `p/q`, with `p` and `q` drawn from a fixed bit budget:
+//!
+//! - `FixedSlash<N>` gives the numerator and the denominator N bits each.
+//! - `FloatingSlash<N>` shares N bits between the two; the position of the "slash" is stored
+//!   alongside, so large numerators can trade against small denominators.
+//!
+//! Every operation is computed exactly and rounded to the nearest representable fraction,
+//! by walking the continued fraction of the exact result. Results past the largest finite value
+//! saturate to it; 1/0 is infinity (from division by zero) and 0/0 is NaN.
+
+use std::cmp::Ordering;
+use std::fmt::Debug;
+use std::marker::PhantomData;
+use std::ops::{Add, Div, Mul, Neg, Sub};
+
+use num::{BigRational, BigUint, Integer, Signed, ToPrimitive};
+
+use crate::numeric::FromRational;
+
+/// Which numerators and denominators a slash format can represent.
+///
+/// Representable fractions must be closed under shrinking either part, so that they form a
+/// subtree of the Stern-Brocot tree.
+pub trait Layout: Copy + Debug + Send + Sync + 'static {
+    fn fits(p: u64, q: u64) -> bool;
+}
+
+/// Numerator and denominator of N bits each.
+#[derive(Copy, Clone, Debug)]
+pub struct Fixed<const N: u32>;
+
+impl<const N: u32> Fixed<N> {
+    const VALID: () = assert!(N >= 1 && N <= 63, "unsupported fixed-slash size");
+}
+
+impl<const N: u32> Layout for Fixed<N> {
+    fn fits(p: u64, q: u64) -> bool {
+        #[allow(clippy::let_unit_value)]
+        let _ = Self::VALID;
+        p >> N == 0 && q >> N == 0
+    }
+}
+
+/// Numerator and denominator of N bits together.
+#[derive(Copy, Clone, Debug)]
+pub struct Floating<const N: u32>;
+
+impl<const N: u32> Floating<N> {
+    const VALID: () = assert!(N >= 2 && N <= 64, "unsupported floating-slash size");
+}
+
+impl<const N: u32> Layout for Floating<N> {
+    fn fits(p: u64, q: u64) -> bool {
+        #[allow(clippy::let_unit_value)]
+        let _ = Self::VALID;
+        let bits = |x: u64| u64::BITS - x.leading_zeros();
+        bits(p) + bits(q) <= N
+    }
+}
+
+/// A bounded rational number.
+#[derive(Copy, Clone, Debug)]
+pub struct Slash<L> {
+    negative: bool,
+    numerator: u64,
+    denominator: u64,
+    layout: PhantomData<L>,
+}
+
+pub type FixedSlash<const N: u32> = Slash<Fixed<N>>;
+pub type FloatingSlash<const N: u32> = Slash<Floating<N>>;
+
+/// The fraction nearest `n/d`, with numerator and denominator that fit L.
+/// `n/d` is nonnegative and finite.
+fn nearest<L: Layout, T>(n: T, d: T) -> (u64, u64)
+where
+    T: Clone + Integer + ToPrimitive + Into<BigUint>,
+{
+    // The previous two convergents, h0 and h1; and the next candidate, t*h1 + h0.
+    let candidate = |t: u128, (p0, q0): (u64, u64), (p1, q1): (u64, u64)| {
+        let p = t.checked_mul(p1 as u128)?.checked_add(p0 as u128)?;
+        let q = t.checked_mul(q1 as u128)?.checked_add(q0 as u128)?;
+        let (p, q) = (u64::try_from(p).ok()?, u64::try_from(q).ok()?);
+        L::fits(p, q).then_some((p, q))
+    };
+    let (mut h0, mut h1) = ((0, 1), (1, 0));
+    let (mut x, mut y) = (n, d);
+    loop {
+        let (a, r) = x.div_rem(&y);
+        // Either part of h1 is nonzero, so no multiple larger than 2^64 fits.
+        let a = a.to_u128().unwrap_or(u128::MAX).min(1 << 64);
+        if let Some(h2) = candidate(a, h0, h1) {
+            (h0, h1) = (h1, h2);
+            if r.is_zero() {
+                return h1;
+            }
+            (x, y) = (y, r);
+            continue;
+        }
+        // The convergent doesn't fit; find the largest semiconvergent that does.
+        // t = 0 gives h0, which fits.
+        let (mut t, mut over) = (0, a);
+        while over - t > 1 {
+            let mid = t + (over - t) / 2;
+            if candidate(mid, h0, h1).is_some() {
+                t = mid;
+            } else {
+                over = mid;
+            }
+        }
+        let semiconvergent = candidate(t, h0, h1).expect("semiconvergent fits");
+        let (q0, q1) = (h0.1 as u128, h1.1 as u128);
+        if q1 == 0 {
+            // h1 is 1/0; there's nothing finite larger.
+            return semiconvergent;
+        }
+        // The exact value is (α*h1 + h0) for α = x/y > t; h1 is nearer if (2t*q1 + q0) < α*q1.
+        let lhs = 2 * t * q1 + q0;
+        let small = (x.to_u128(), y.to_u128());
+        let order = match small {
+            (Some(x), Some(y)) => lhs
+                .checked_mul(y)
+                .zip(x.checked_mul(q1))
+                .map(|(l, r)| l.cmp(&r)),
+            _ => None,
+        }
+        .unwrap_or_else(|| (BigUint::from(lhs) * y.into()).cmp(&(x.into() * q1)));
+        return match order {
+            Ordering::Less => h1,
+            Ordering::Greater => semiconvergent,
+            // Ties go to the simpler fraction.
+            Ordering::Equal => std::cmp::min_by_key(h1, semiconvergent, |h| h.1),
+        };
+    }
+}
+
+impl<L: Layout> Slash<L> {
+    fn new(negative: bool, numerator: u64, denominator: u64) -> Self {
+        Slash {
+            negative: negative && numerator != 0,
+            numerator,
+            denominator,
+            layout: PhantomData,
+        }
+    }
+
+    pub fn zero() -> Self {
+        Self::new(false, 0, 1)
+    }
+
+    pub fn infinity(negative: bool) -> Self {
+        Self::new(negative, 1, 0)
+    }
+
+    pub fn nan() -> Self {
+        Self::new(false, 0, 0)
+    }
+
+    pub fn is_nan(&self) -> bool {
+        self.numerator == 0 && self.denominator == 0
+    }
+
+    pub fn is_infinite(&self) -> bool {
+        self.numerator != 0 && self.denominator == 0
+    }
+
+    fn is_finite(&self) -> bool {
+        self.denominator != 0
+    }
+
+    /// The sign, numerator, and denominator.
+    pub fn parts(&self) -> (bool, u64, u64) {
+        (self.negative, self.numerator, self.denominator)
+    }
+
+    /// The representable value nearest `n/d`.
+    fn round<T>(negative: bool, n: T, d: T) -> Self
+    where
+        T: Clone + Integer + ToPrimitive + Into<BigUint>,
+    {
+        if d.is_zero() {
+            return if n.is_zero() {
+                Self::nan()
+            } else {
+                Self::infinity(negative)
+            };
+        }
+        let (p, q) = nearest::<L, T>(n, d);
+        Self::new(negative, p, q)
+    }
+
+    pub fn from_i32(i: i32) -> Self {
+        Self::round(i < 0, i.unsigned_abs() as u128, 1)
+    }
+
+    pub fn from_f64(f: f64) -> Self {
+        if f.is_nan() {
+            Self::nan()
+        } else if f.is_infinite() {
+            Self::infinity(f < 0.0)
+        } else {
+            let r = BigRational::from_float(f).expect("finite");
+            Self::from_bigrational(&r).expect("finite")
+        }
+    }
+
+    pub fn to_f64(&self) -> f64 {
+        let (p, q) = (self.numerator, self.denominator);
+        let magnitude = if q == 0 {
+            if p == 0 {
+                return f64::NAN;
+            }
+            f64::INFINITY
+        } else if p < 1 << f64::MANTISSA_DIGITS && q < 1 << f64::MANTISSA_DIGITS {
+            // Both are exact, so the quotient is correctly rounded.
+            p as f64 / q as f64
+        } else {
+            BigRational::new(p.into(), q.into())
+                .to_f64()
+                .unwrap_or(f64::NAN)
+        };
+        if self.negative {
+            -magnitude
+        } else {
+            magnitude
+        }
+    }
+
+    /// Applies an operation with an infinite or NaN operand.
+    ///
+    /// The result is special or zero, so f64 gets it right.
+    fn special(self, other: Self, op: impl Fn(f64, f64) -> f64) -> Self {
+        Self::from_f64(op(self.to_f64(), other.to_f64()))
+    }
+}
+
+impl<L: Layout> From<Slash<L>> for f64 {
+    fn from(v: Slash<L>) -> Self {
+        v.to_f64()
+    }
+}
+
+impl<L: Layout> PartialEq for Slash<L> {
+    fn eq(&self, other: &Self) -> bool {
+        self.partial_cmp(other) == Some(Ordering::Equal)
+    }
+}
+
+impl<L: Layout> PartialOrd for Slash<L> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        if self.is_nan() || other.is_nan() {
+            return None;
+        }
+        // Cross-multiply; this holds for infinities too.
+        let signed = |v: &Self, scale: u64| {
+            let x = v.numerator as i128 * scale as i128;
+            if v.negative {
+                -x
+            } else {
+                x
+            }
+        };
+        signed(self, other.denominator).partial_cmp(&signed(other, self.denominator))
+    }
+}
+
+impl<L: Layout> Neg for Slash<L> {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        Self::new(!self.negative, self.numerator, self.denominator)
+    }
+}
+
+impl<L: Layout> Add for Slash<L> {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        if !self.is_finite() || !other.is_finite() {
+            return self.special(other, |a, b| a + b);
+        }
+        let (a, b) = (self.numerator as u128, self.denominator as u128);
+        let (c, d) = (other.numerator as u128, other.denominator as u128);
+        // Each part is less than 2^63, so none of this overflows.
+        let (x, y) = (a * d, c * b);
+        let (negative, n) = if self.negative == other.negative {
+            (self.negative, x + y)
+        } else if x >= y {
+            (self.negative, x - y)
+        } else {
+            (other.negative, y - x)
+        };
+        Self::round(negative, n, b * d)
+    }
+}
+
+impl<L: Layout> Sub for Slash<L> {
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self {
+        self + -other
+    }
+}
+
+impl<L: Layout> Mul for Slash<L> {
+    type Output = Self;
+
+    fn mul(self, other: Self) -> Self {
+        if !self.is_finite() || !other.is_finite() {
+            return self.special(other, |a, b| a * b);
+        }
+        Self::round(
+            self.negative != other.negative,
+            self.numerator as u128 * other.numerator as u128,
+            self.denominator as u128 * other.denominator as u128,
+        )
+    }
+}
+
+impl<L: Layout> Div for Slash<L> {
+    type Output = Self;
+
+    fn div(self, other: Self) -> Self {
+        if !self.is_finite() || !other.is_finite() {
+            return self.special(other, |a, b| a / b);
+        }
+        Self::round(
+            self.negative != other.negative,
+            self.numerator as u128 * other.denominator as u128,
+            self.denominator as u128 * other.numerator as u128,
+        )
+    }
+}
+
+impl<L: Layout> FromRational for Slash<L> {
+    fn from_bigrational(r: &BigRational) -> Result<Self, String> {
+        Ok(Self::round(
+            r.is_negative(),
+            r.numer().magnitude().clone(),
+            r.denom().magnitude().clone(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rational(n: i64, d: i64) -> BigRational {
+        BigRational::new(n.into(), d.into())
+    }
+
+    /// Checks rounding against a brute-force search of every representable fraction.
+    fn check_nearest<L: Layout>(limit: u64) {
+        let representable: Vec<(u64, u64)> = (0..limit)
+            .flat_map(|p| (1..limit).map(move |q| (p, q)))
+            .filter(|&(p, q)| L::fits(p, q))
+            .collect();
+        let distance =
+            |(p, q): (u64, u64), r: &BigRational| (rational(p as i64, q as i64) - r).abs();
+        for n in 0..100 {
+            for d in [1, 3, 16, 97, 1021] {
+                let r = rational(n, d);
+                let best = representable
+                    .iter()
+                    .map(|&h| distance(h, &r))
+                    .min()
+                    .unwrap();
+                let (_, p, q) = Slash::<L>::from_bigrational(&r).unwrap().parts();
+                assert!(L::fits(p, q));
+                assert_eq!(distance((p, q), &r), best, "{n}/{d} rounded to {p}/{q}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_nearest() {
+        check_nearest::<Fixed<4>>(16);
+        check_nearest::<Floating<7>>(64);
+    }
+
+    #[test]
+    fn test_rounding() {
+        let pi = rational(3141592653589793, 1000000000000000);
+        assert_eq!(
+            FixedSlash::<5>::from_bigrational(&pi).unwrap().parts(),
+            (false, 22, 7)
+        );
+        assert_eq!(
+            FixedSlash::<9>::from_bigrational(&pi).unwrap().parts(),
+            (false, 355, 113)
+        );
+        // Floating-slash trades denominator bits for numerator bits:
+        assert_eq!(
+            FloatingSlash::<8>::from_bigrational(&rational(100, 1))
+                .unwrap()
+                .parts(),
+            (false, 100, 1)
+        );
+        assert_eq!(
+            FixedSlash::<4>::from_bigrational(&rational(100, 1))
+                .unwrap()
+                .parts(),
+            (false, 15, 1)
+        );
+        // The smallest values:
+        assert_eq!(
+            FixedSlash::<4>::from_bigrational(&rational(-1, 20))
+                .unwrap()
+                .parts(),
+            (true, 1, 15)
+        );
+        assert_eq!(
+            FixedSlash::<4>::from_bigrational(&rational(1, 40))
+                .unwrap()
+                .parts(),
+            (false, 0, 1)
+        );
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        type S = FixedSlash<8>;
+        let third = S::from_i32(1) / S::from_i32(3);
+        let sixth = S::from_i32(1) / S::from_i32(6);
+        assert_eq!((third + sixth).parts(), (false, 1, 2));
+        assert_eq!((sixth - third).parts(), (true, 1, 6));
+        assert_eq!((third * third).parts(), (false, 1, 9));
+        // Saturates rather than overflowing:
+        let big = S::from_i32(200);
+        assert_eq!((big * big).parts(), (false, 255, 1));
+        assert_eq!(S::from_i32(1000), S::from_i32(255));
+        // Correctly rounded, like any other operation:
+        let x = S::from_i32(17) / S::from_i32(19);
+        let y = S::from_i32(23) / S::from_i32(29);
+        let exact = rational(17 * 29 + 23 * 19, 19 * 29);
+        assert_eq!(x + y, S::from_bigrational(&exact).unwrap());
+    }
+
+    #[test]
+    fn test_specials() {
+        type S = FloatingSlash<32>;
+        let (one, zero) = (S::from_i32(1), S::zero());
+        assert!((one / zero).is_infinite());
+        assert!((-one / zero) < -S::from_i32(1 << 20));
+        assert!((zero / zero).is_nan());
+        assert!((S::infinity(false) - S::infinity(false)).is_nan());
+        assert_eq!(one / S::infinity(true), zero);
+        assert_eq!(-zero, zero);
+        assert!(S::nan() != S::nan());
+        assert!(-one < zero && zero < one);
+        assert_eq!(S::from_f64(0.75).to_f64(), 0.75);
+        assert_eq!(S::from_f64(-2.5).parts(), (true, 5, 2));
+    }
+}
@@ -0,0 +1,461 @@
+//! Bit-accurate small floating-point formats, as used by ML accelerators.
+//!
+//! Unlike `MaskedFloat`, these round to nearest-even, have subnormals, and follow each format's
+//! rules for infinities and NaN:
+//!
+//! | Format   | Exponent | Mantissa | Specials                          |
+//! |----------|----------|----------|-----------------------------------|
+//! | binary16 | 5        | 10       | IEEE 754                          |
+//! | bfloat16 | 8        | 7        | IEEE 754                          |
+//! | TF32     | 8        | 10       | IEEE 754                          |
+//! | FP8 E5M2 | 5        | 2        | IEEE 754                          |
+//! | FP8 E4M3 | 4        | 3        | OCP "FN": no infinities, saturate |
+//!
+//! Arithmetic is carried out in f64 and rounded once to the format. f64 has more than 2p+2 bits
+//! of precision for all of these formats, so the double rounding is innocuous: the results are
+//! the correctly-rounded results of each operation.
+
+use std::cmp::Ordering;
+use std::marker::PhantomData;
+use std::ops::{Add, Div, Mul, Sub};
+
+use num::{BigRational, ToPrimitive};
+
+use crate::interval::Endpoint;
+use crate::numeric::{binary_parts, FromRational};
+
+/// How a format represents values outside the finite range.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Specials {
+    /// The all-ones exponent is reserved for infinities and NaNs; overflow produces infinity.
+    Ieee,
+    /// Only the all-ones bit pattern is NaN; there are no infinities, and overflow saturates
+    /// to the largest finite value.
+    Saturating,
+}
+
+/// Parameters of a small floating-point format.
+pub trait SmallFloatFormat {
+    /// Exponent bits.
+    const EXPONENT: u32;
+    /// Mantissa (explicit fraction) bits.
+    const MANTISSA: u32;
+    const SPECIALS: Specials;
+}
+
+/// A value in a small floating-point format, stored as its bit pattern.
+pub struct SmallFloat<F> {
+    bits: u32,
+    format: PhantomData<F>,
+}
+
+macro_rules! small_float_format {
+    ($(#[$doc:meta])* $format:ident, $alias:ident, $e:expr, $m:expr, $specials:expr) => {
+        $(#[$doc])*
+        #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+        pub struct $format;
+
+        impl SmallFloatFormat for $format {
+            const EXPONENT: u32 = $e;
+            const MANTISSA: u32 = $m;
+            const SPECIALS: Specials = $specials;
+        }
+
+        $(#[$doc])*
+        pub type $alias = SmallFloat<$format>;
+    };
+}
+
+small_float_format!(
+    /// IEEE 754 half precision.
+    Binary16Format, Binary16, 5, 10, Specials::Ieee
+);
+small_float_format!(
+    /// Brain floating-point: the exponent range of f32, with 8 bits of precision.
+    BFloat16Format, BFloat16, 8, 7, Specials::Ieee
+);
+small_float_format!(
+    /// NVIDIA TensorFloat-32: the exponent range of f32, with the precision of binary16.
+    Tf32Format, Tf32, 8, 10, Specials::Ieee
+);
+small_float_format!(
+    /// OCP 8-bit float with 5 exponent bits.
+    Fp8E5M2Format, Fp8E5M2, 5, 2, Specials::Ieee
+);
+small_float_format!(
+    /// OCP 8-bit float with 4 exponent bits.
+    Fp8E4M3Format, Fp8E4M3, 4, 3, Specials::Saturating
+);
+
+impl<F: SmallFloatFormat> SmallFloat<F> {
+    const BIAS: i64 = (1 << (F::EXPONENT - 1)) - 1;
+    const EMIN: i64 = 1 - Self::BIAS;
+    const SIGN: u32 = 1 << (F::EXPONENT + F::MANTISSA);
+    const EXPONENT_ONES: u32 = (1 << F::EXPONENT) - 1;
+    const MANTISSA_MASK: u32 = (1 << F::MANTISSA) - 1;
+
+    /// The bit pattern of the largest finite value.
+    const MAX_BITS: u32 = match F::SPECIALS {
+        Specials::Ieee => ((Self::EXPONENT_ONES - 1) << F::MANTISSA) | Self::MANTISSA_MASK,
+        Specials::Saturating => (Self::EXPONENT_ONES << F::MANTISSA) | (Self::MANTISSA_MASK - 1),
+    };
+
+    pub fn from_bits(bits: u32) -> Self {
+        SmallFloat {
+            bits: bits & ((Self::SIGN << 1) - 1),
+            format: PhantomData,
+        }
+    }
+
+    pub fn to_bits(self) -> u32 {
+        self.bits
+    }
+
+    pub fn nan() -> Self {
+        match F::SPECIALS {
+            Specials::Ieee => {
+                Self::from_bits((Self::EXPONENT_ONES << F::MANTISSA) | (1 << (F::MANTISSA - 1)))
+            }
+            Specials::Saturating => Self::from_bits(Self::SIGN - 1),
+        }
+    }
+
+    pub fn is_nan(self) -> bool {
+        let magnitude = self.bits & !Self::SIGN;
+        match F::SPECIALS {
+            Specials::Ieee => magnitude > Self::EXPONENT_ONES << F::MANTISSA,
+            Specials::Saturating => magnitude == Self::SIGN - 1,
+        }
+    }
+
+    pub fn is_infinite(self) -> bool {
+        F::SPECIALS == Specials::Ieee
+            && self.bits & !Self::SIGN == Self::EXPONENT_ONES << F::MANTISSA
+    }
+
+    /// Rounds `sig * 2^exponent` (plus a bit less than `2^exponent`, if sticky) to nearest-even.
+    ///
+    /// The significand must have at least two bits more than the format's precision,
+    /// so that `sticky` only ever breaks ties.
+    fn encode(negative: bool, exponent: i64, sig: u128, sticky: bool) -> Self {
+        let sign = if negative { Self::SIGN } else { 0 };
+        if sig == 0 {
+            return Self::from_bits(sign);
+        }
+        let top = 127 - sig.leading_zeros() as i64;
+        // Exponent of the last place: fixed for subnormals, relative to the leading bit otherwise.
+        let quantum = std::cmp::max(exponent + top, Self::EMIN) - F::MANTISSA as i64;
+        let shift = quantum - exponent;
+        let mut n = if shift <= 0 {
+            sig << -shift
+        } else if shift >= 128 {
+            // Far below the smallest subnormal; round to zero.
+            0
+        } else {
+            let kept = sig >> shift;
+            let guard = (sig >> (shift - 1)) & 1 == 1;
+            let lower = sig & ((1 << (shift - 1)) - 1) != 0 || sticky;
+            if guard && (lower || kept & 1 == 1) {
+                kept + 1
+            } else {
+                kept
+            }
+        };
+        let mut quantum = quantum;
+        if n >> (F::MANTISSA + 1) != 0 {
+            // Rounded up to the next power of two:
+            n >>= 1;
+            quantum += 1;
+        }
+
+        let magnitude = if n >> F::MANTISSA == 0 {
+            // Subnormal (or zero):
+            n as u32
+        } else {
+            let field = quantum + F::MANTISSA as i64 + Self::BIAS;
+            if field > Self::EXPONENT_ONES as i64 {
+                u32::MAX
+            } else {
+                ((field as u32) << F::MANTISSA) | (n as u32 & Self::MANTISSA_MASK)
+            }
+        };
+        if magnitude > Self::MAX_BITS {
+            Self::overflow(sign)
+        } else {
+            Self::from_bits(sign | magnitude)
+        }
+    }
+
+    fn overflow(sign: u32) -> Self {
+        match F::SPECIALS {
+            Specials::Ieee => Self::from_bits(sign | (Self::EXPONENT_ONES << F::MANTISSA)),
+            Specials::Saturating => Self::from_bits(sign | Self::MAX_BITS),
+        }
+    }
+
+    pub fn from_f64(f: f64) -> Self {
+        if f.is_nan() {
+            return Self::nan();
+        }
+        let negative = f.is_sign_negative();
+        if f.is_infinite() {
+            return Self::overflow(if negative { Self::SIGN } else { 0 });
+        }
+        let bits = f.to_bits();
+        let biased = ((bits >> 52) & 0x7ff) as i64;
+        let fraction = bits & ((1 << 52) - 1);
+        let (sig, exponent) = if biased == 0 {
+            (fraction, -1074)
+        } else {
+            (fraction | (1 << 52), biased - 1075)
+        };
+        Self::encode(negative, exponent, sig as u128, false)
+    }
+
+    pub fn to_f64(self) -> f64 {
+        if self.is_nan() {
+            return f64::NAN;
+        }
+        if self.is_infinite() {
+            return if self.bits & Self::SIGN != 0 {
+                f64::NEG_INFINITY
+            } else {
+                f64::INFINITY
+            };
+        }
+        let field = ((self.bits & !Self::SIGN) >> F::MANTISSA) as i64;
+        let mantissa = (self.bits & Self::MANTISSA_MASK) as f64;
+        let magnitude = if field == 0 {
+            mantissa * 2f64.powi((Self::EMIN - F::MANTISSA as i64) as i32)
+        } else {
+            (mantissa + (1u32 << F::MANTISSA) as f64)
+                * 2f64.powi((field - Self::BIAS - F::MANTISSA as i64) as i32)
+        };
+        if self.bits & Self::SIGN != 0 {
+            -magnitude
+        } else {
+            magnitude
+        }
+    }
+}
+
+impl<F> Clone for SmallFloat<F> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<F> Copy for SmallFloat<F> {}
+
+impl<F: SmallFloatFormat> std::fmt::Debug for SmallFloat<F> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?} ({:#x})", self.to_f64(), self.bits)
+    }
+}
+
+impl<F: SmallFloatFormat> From<SmallFloat<F>> for f64 {
+    fn from(v: SmallFloat<F>) -> Self {
+        v.to_f64()
+    }
+}
+
+impl<F: SmallFloatFormat> PartialEq for SmallFloat<F> {
+    fn eq(&self, other: &Self) -> bool {
+        self.to_f64() == other.to_f64()
+    }
+}
+
+impl<F: SmallFloatFormat> PartialOrd for SmallFloat<F> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        self.to_f64().partial_cmp(&other.to_f64())
+    }
+}
+
+impl<F: SmallFloatFormat> Add for SmallFloat<F> {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Self::from_f64(self.to_f64() + other.to_f64())
+    }
+}
+
+impl<F: SmallFloatFormat> Sub for SmallFloat<F> {
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self {
+        Self::from_f64(self.to_f64() - other.to_f64())
+    }
+}
+
+impl<F: SmallFloatFormat> Mul for SmallFloat<F> {
+    type Output = Self;
+
+    fn mul(self, other: Self) -> Self {
+        Self::from_f64(self.to_f64() * other.to_f64())
+    }
+}
+
+impl<F: SmallFloatFormat> Div for SmallFloat<F> {
+    type Output = Self;
+
+    fn div(self, other: Self) -> Self {
+        Self::from_f64(self.to_f64() / other.to_f64())
+    }
+}
+
+impl<F: SmallFloatFormat> FromRational for SmallFloat<F> {
+    fn from_bigrational(r: &BigRational) -> Result<Self, String> {
+        let Some(parts) = binary_parts(r, 64) else {
+            return Ok(Self::from_bits(0));
+        };
+        let sig = parts
+            .significand
+            .to_u128()
+            .ok_or_else(|| format!("significand of {} out of range", r))?;
+        Ok(Self::encode(
+            parts.negative,
+            parts.exponent,
+            sig,
+            parts.sticky,
+        ))
+    }
+}
+
+/// Steps through adjacent bit patterns. Past the largest finite value is infinity,
+/// or NaN (an unknown bound) for formats that saturate.
+impl<F: SmallFloatFormat> Endpoint for SmallFloat<F> {
+    fn next_up(self) -> Self {
+        let magnitude = self.bits & !Self::SIGN;
+        if self.is_nan() || (self.is_infinite() && self.bits & Self::SIGN == 0) {
+            self
+        } else if magnitude == 0 {
+            Self::from_bits(1)
+        } else if self.bits & Self::SIGN != 0 {
+            Self::from_bits(self.bits - 1)
+        } else if magnitude == Self::MAX_BITS {
+            Self::infinity()
+        } else {
+            Self::from_bits(self.bits + 1)
+        }
+    }
+
+    fn next_down(self) -> Self {
+        let negated = |v: Self| Self::from_bits(v.bits ^ Self::SIGN);
+        negated(negated(self).next_up())
+    }
+
+    fn infinity() -> Self {
+        match F::SPECIALS {
+            Specials::Ieee => Self::overflow(0),
+            Specials::Saturating => Self::nan(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_binary16() {
+        assert_eq!(Binary16::from_f64(1.0).to_bits(), 0x3c00);
+        assert_eq!(Binary16::from_f64(-2.0).to_bits(), 0xc000);
+        assert_eq!(Binary16::from_f64(65504.0).to_bits(), 0x7bff);
+        // Smallest subnormal:
+        assert_eq!(Binary16::from_f64(2f64.powi(-24)).to_bits(), 0x0001);
+        // Half of it is a tie, and rounds to (even) zero:
+        assert_eq!(Binary16::from_f64(2f64.powi(-25)).to_bits(), 0x0000);
+        assert_eq!(Binary16::from_f64(1.5 * 2f64.powi(-25)).to_bits(), 0x0001);
+
+        // Contrast with test_f16 in masked_float: real binary16 overflows.
+        let result = Binary16::from_f64(65_504.0) + Binary16::from_f64(34_496.0);
+        assert!(result.is_infinite());
+        assert_eq!(result.to_f64(), f64::INFINITY);
+
+        assert!((Binary16::from_f64(0.0) / Binary16::from_f64(0.0)).is_nan());
+        assert_eq!(Binary16::from_f64(-0.0), Binary16::from_f64(0.0));
+    }
+
+    #[test]
+    fn test_round_to_nearest_even() {
+        let one = Binary16::from_f64(1.0);
+        let ulp = 2f64.powi(-10);
+        // Ties go to even:
+        assert_eq!(Binary16::from_f64(1.0 + ulp / 2.0), one);
+        assert_eq!(
+            Binary16::from_f64(1.0 + 3.0 * ulp / 2.0).to_f64(),
+            1.0 + 2.0 * ulp
+        );
+        // And the last place rounds up into the exponent:
+        assert_eq!(
+            Binary16::from_f64(2.0 - 3.0 * ulp / 4.0).to_f64(),
+            2.0 - ulp
+        );
+        assert_eq!(
+            Binary16::from_f64(2.0 - ulp / 2.0 + ulp / 8.0).to_f64(),
+            2.0
+        );
+    }
+
+    #[test]
+    fn test_bfloat16_tf32() {
+        assert_eq!(BFloat16::from_f64(1.0 / 3.0).to_bits(), 0x3eab);
+        assert_eq!(BFloat16::from_f64(3.0e38).to_bits(), 0x7f62);
+        assert!(BFloat16::from_f64(3.4e38).is_infinite());
+        assert_eq!(Tf32::from_f64(1.0 / 3.0).to_f64(), 1365.0 / 4096.0);
+        assert_eq!(Tf32::from_f64(1.0).to_bits(), 127 << 10);
+    }
+
+    #[test]
+    fn test_fp8() {
+        // E4M3 has no infinity; overflow saturates at 448.
+        assert_eq!(Fp8E4M3::from_f64(448.0).to_bits(), 0x7e);
+        assert_eq!(Fp8E4M3::from_f64(1000.0).to_f64(), 448.0);
+        assert_eq!(Fp8E4M3::from_f64(-1e9).to_f64(), -448.0);
+        assert_eq!(Fp8E4M3::from_f64(f64::INFINITY).to_f64(), 448.0);
+        assert!(Fp8E4M3::from_bits(0x7f).is_nan());
+        assert!(Fp8E4M3::from_f64(f64::NAN).is_nan());
+        assert_eq!(Fp8E4M3::from_f64(2f64.powi(-9)).to_bits(), 0x01);
+        assert_eq!(Fp8E4M3::from_f64(1.0).to_bits(), 0x38);
+
+        assert_eq!(Fp8E5M2::from_f64(57344.0).to_bits(), 0x7b);
+        assert!(Fp8E5M2::from_f64(61440.0).is_infinite());
+        assert_eq!(Fp8E5M2::from_f64(2f64.powi(-16)).to_bits(), 0x01);
+        assert!(Fp8E5M2::from_bits(0x7d).is_nan());
+    }
+
+    #[test]
+    fn test_exhaustive_round_trip() {
+        fn round_trip<F: SmallFloatFormat>() {
+            for bits in 0..(SmallFloat::<F>::SIGN << 1) {
+                let v = SmallFloat::<F>::from_bits(bits);
+                if v.is_nan() {
+                    continue;
+                }
+                assert_eq!(SmallFloat::<F>::from_f64(v.to_f64()).to_bits(), bits);
+            }
+        }
+        round_trip::<Binary16Format>();
+        round_trip::<BFloat16Format>();
+        round_trip::<Fp8E5M2Format>();
+        round_trip::<Fp8E4M3Format>();
+    }
+
+    #[test]
+    fn test_from_rational_rounds_once() {
+        // Just above the tie between 1 and 1 + 2^-10; f64 would round it onto the tie.
+        let r = BigRational::new(1.into(), 1.into())
+            + BigRational::new(1.into(), 2048.into())
+            + BigRational::new(1.into(), num::BigInt::from(1) << 70u32);
+        assert_eq!(f64::from_bigrational(&r).unwrap(), 1.0 + 2f64.powi(-11));
+        assert_eq!(
+            Binary16::from_bigrational(&r).unwrap().to_f64(),
+            1.0 + 2f64.powi(-10)
+        );
+        let third = BigRational::new((-1).into(), 3.into());
+        assert_eq!(
+            BFloat16::from_bigrational(&third).unwrap().to_bits(),
+            0x8000 | 0x3eab
+        );
+    }
+}
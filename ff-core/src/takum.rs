@@ -0,0 +1,587 @@
+//! Takum arithmetic, with any width, in the logarithmic and linear variants.
+//!
+//! Takums are a tapered-precision format, like posits, but with a bounded dynamic range that
+//! doesn't depend on the width. An N-bit takum is the bit string
+//!
+//!   S D RRR C...C M...M
+//!
+//! with sign S, direction D, a three-bit regime R, r characteristic bits C and the rest mantissa
+//! bits M. The regime gives r = R (if D is set) or 7 - R, and the characteristic c is
+//! `2^r - 1 + C` (if D is set) or `-2^(r+1) + 1 + C`, so c ranges over [-255, 254].
+//! With m the mantissa as a fraction in [0, 1), a positive takum is:
+//!
+//! - `sqrt(e)^(c + m)` for the logarithmic takum (Hunhold 2024), selected by `Logarithmic`;
+//! - `(1 + m) * 2^c` for the linear takum, selected by `Linear`.
+//!
+//! As with posits, negation is two's complement of the bit string, zero is all zeros, and NaR
+//! ("not a real") is the sign bit alone. Takums narrower than 12 bits are the leading bits of a
+//! 12-bit takum: the characteristic itself may be cut short.
+//!
+//! Results are rounded to nearest-even on the bit string, and saturate rather than rounding to
+//! zero or NaR. Linear takums are computed exactly before rounding. Logarithmic multiplication
+//! and division are exact; logarithmic sums and conversions are evaluated in f64, which is
+//! correctly-rounded for all but the widest (more than 32-bit) takums.
+
+use std::cmp::Ordering;
+use std::f64::consts::LN_2;
+use std::fmt::Debug;
+use std::marker::PhantomData;
+use std::ops::{Add, Div, Mul, Neg, Sub};
+
+use num::{BigRational, ToPrimitive};
+
+use crate::numeric::{binary_parts, ldexp, FromRational};
+
+/// A finite, nonzero takum (or result, to be rounded to one), decoded.
+#[derive(Copy, Clone, Debug)]
+pub struct Parts {
+    negative: bool,
+    /// The characteristic. Results may be out of the takum's range.
+    c: i64,
+    /// The mantissa, as a fraction of 2^64.
+    frac: u64,
+    /// Whether the exact value is a little more (in magnitude) than `c` and `frac` give.
+    sticky: bool,
+}
+
+/// Scale of `Parts::frac`.
+const SCALE: f64 = 18446744073709551616.0;
+
+/// The range of the characteristic.
+const MAX_C: i64 = 254;
+const MIN_C: i64 = -255;
+
+/// Position of the hidden bit in a linear significand.
+const HIDDEN: i64 = 62;
+
+/// How a takum's characteristic and mantissa map to a value, and how to compute with them.
+pub trait Variant: Copy + Debug + Send + Sync + 'static {
+    /// The magnitude of a decoded takum.
+    fn magnitude(p: &Parts) -> f64;
+    /// The takum nearest `sig * 2^exponent` (plus something less than `2^exponent`, if sticky),
+    /// before rounding to width. `sig` is nonzero.
+    fn from_binary(negative: bool, exponent: i64, sig: u128, sticky: bool) -> Parts;
+    /// The sum, or None if it's zero.
+    fn sum(a: Parts, b: Parts) -> Option<Parts>;
+    fn product(a: Parts, b: Parts) -> Parts;
+    fn quotient(a: Parts, b: Parts) -> Parts;
+}
+
+/// The logarithmic takum: the characteristic and mantissa are a logarithm, base sqrt(e).
+#[derive(Copy, Clone, Debug)]
+pub struct Logarithmic;
+
+impl Logarithmic {
+    /// The logarithm, as a fixed-point number with 64 fraction bits.
+    fn log(p: &Parts) -> i128 {
+        ((p.c as i128) << 64) | p.frac as i128
+    }
+
+    fn from_log(negative: bool, log: i128, sticky: bool) -> Parts {
+        Parts {
+            negative,
+            c: (log >> 64) as i64,
+            frac: log as u64,
+            sticky,
+        }
+    }
+}
+
+impl Variant for Logarithmic {
+    fn magnitude(p: &Parts) -> f64 {
+        ((p.c as f64 + p.frac as f64 / SCALE) / 2.0).exp()
+    }
+
+    fn from_binary(negative: bool, exponent: i64, sig: u128, sticky: bool) -> Parts {
+        // Far enough out of range to saturate, and still within range of the fixed-point log:
+        let log2 = (exponent as f64 + (sig as f64).log2()).clamp(-1000.0, 1000.0);
+        let log = 2.0 * LN_2 * log2;
+        Self::from_log(negative, (log * SCALE) as i128, sticky || log != 0.0)
+    }
+
+    fn sum(a: Parts, b: Parts) -> Option<Parts> {
+        let (la, lb) = (Self::log(&a), Self::log(&b));
+        let (big, big_log, small_log) = if la >= lb { (a, la, lb) } else { (b, lb, la) };
+        // Gaussian logarithms, in base sqrt(e): log(x + y) = log(x) + 2 ln(1 +- e^(d/2))
+        let half_d = (small_log - big_log) as f64 / SCALE / 2.0;
+        let delta = if a.negative == b.negative {
+            2.0 * half_d.exp().ln_1p()
+        } else if small_log == big_log {
+            return None;
+        } else {
+            2.0 * (-half_d.exp_m1()).ln()
+        };
+        // Apart from exact cancellation, the sum is transcendental: never a tie.
+        Some(Self::from_log(
+            big.negative,
+            big_log + (delta * SCALE) as i128,
+            true,
+        ))
+    }
+
+    fn product(a: Parts, b: Parts) -> Parts {
+        Self::from_log(
+            a.negative != b.negative,
+            Self::log(&a) + Self::log(&b),
+            false,
+        )
+    }
+
+    fn quotient(a: Parts, b: Parts) -> Parts {
+        Self::from_log(
+            a.negative != b.negative,
+            Self::log(&a) - Self::log(&b),
+            false,
+        )
+    }
+}
+
+/// The linear takum: the characteristic and mantissa are an exponent and fraction, like a float.
+#[derive(Copy, Clone, Debug)]
+pub struct Linear;
+
+impl Linear {
+    /// The significand, with the hidden bit at `HIDDEN`. Takum mantissas have fewer than 60 bits,
+    /// so this is exact.
+    fn significand(p: &Parts) -> u64 {
+        (1 << HIDDEN) | (p.frac >> 2)
+    }
+}
+
+impl Variant for Linear {
+    fn magnitude(p: &Parts) -> f64 {
+        ldexp(Self::significand(p) as f64, p.c - HIDDEN)
+    }
+
+    fn from_binary(negative: bool, exponent: i64, sig: u128, sticky: bool) -> Parts {
+        let top = 127 - sig.leading_zeros() as i64;
+        // The 64 bits after the leading one, and whether anything is left over:
+        let aligned = sig << (127 - top);
+        Parts {
+            negative,
+            c: exponent + top,
+            frac: (aligned >> 63) as u64,
+            sticky: sticky || (aligned & ((1 << 63) - 1)) != 0,
+        }
+    }
+
+    fn sum(a: Parts, b: Parts) -> Option<Parts> {
+        // Put the larger-magnitude operand first.
+        let (a, b) = if (a.c, a.frac) >= (b.c, b.frac) {
+            (a, b)
+        } else {
+            (b, a)
+        };
+        // Hidden bit at 126, leaving a bit of headroom for carry.
+        let x = (Self::significand(&a) as u128) << 64;
+        let y = (Self::significand(&b) as u128) << 64;
+        let distance = (a.c - b.c) as u32;
+        let (y, sticky) = if distance >= 128 {
+            (0, true)
+        } else {
+            (y >> distance, y & ((1 << distance) - 1) != 0)
+        };
+        let sig = if a.negative == b.negative {
+            x + y
+        } else if sticky {
+            // The true value of y is a little larger than the shifted value.
+            x - y - 1
+        } else {
+            x - y
+        };
+        if sig == 0 {
+            return None;
+        }
+        Some(Self::from_binary(
+            a.negative,
+            a.c - HIDDEN - 64,
+            sig,
+            sticky,
+        ))
+    }
+
+    fn product(a: Parts, b: Parts) -> Parts {
+        Self::from_binary(
+            a.negative != b.negative,
+            a.c + b.c - 2 * HIDDEN,
+            Self::significand(&a) as u128 * Self::significand(&b) as u128,
+            false,
+        )
+    }
+
+    fn quotient(a: Parts, b: Parts) -> Parts {
+        let dividend = (Self::significand(&a) as u128) << 64;
+        let divisor = Self::significand(&b) as u128;
+        Self::from_binary(
+            a.negative != b.negative,
+            a.c - b.c - 64,
+            dividend / divisor,
+            !dividend.is_multiple_of(divisor),
+        )
+    }
+}
+
+/// An N-bit takum, of variant V.
+#[derive(Copy, Clone, Debug)]
+pub struct Takum<const N: u32, V> {
+    bits: u64,
+    variant: PhantomData<V>,
+}
+
+/// A takum, decoded.
+enum Decoded {
+    Zero,
+    NaR,
+    Finite(Parts),
+}
+
+impl<const N: u32, V: Variant> Takum<N, V> {
+    const VALID: () = assert!(N >= 2 && N <= 64, "unsupported takum size");
+    const MASK: u64 = if N == 64 { u64::MAX } else { (1 << N) - 1 };
+    const MAXPOS: u64 = (1 << (N - 1)) - 1;
+    const MINPOS: u64 = 1;
+
+    pub const ZERO: Self = Takum {
+        bits: 0,
+        variant: PhantomData,
+    };
+    pub const NAR: Self = Takum {
+        bits: 1 << (N - 1),
+        variant: PhantomData,
+    };
+
+    pub fn from_bits(bits: u64) -> Self {
+        #[allow(clippy::let_unit_value)]
+        let _ = Self::VALID;
+        Takum {
+            bits: bits & Self::MASK,
+            variant: PhantomData,
+        }
+    }
+
+    pub fn to_bits(self) -> u64 {
+        self.bits
+    }
+
+    pub fn is_nar(self) -> bool {
+        self.bits == Self::NAR.bits
+    }
+
+    /// The bits, sign-extended; takums are ordered as two's-complement integers.
+    fn signed(self) -> i64 {
+        ((self.bits << (64 - N)) as i64) >> (64 - N)
+    }
+
+    fn decode(self) -> Decoded {
+        if self.bits == 0 {
+            return Decoded::Zero;
+        }
+        if self.is_nar() {
+            return Decoded::NaR;
+        }
+        let negative = self.bits >> (N - 1) != 0;
+        let x = if negative {
+            self.bits.wrapping_neg() & Self::MASK
+        } else {
+            self.bits
+        };
+        // Align the bits after the sign bit to the top of the word; past the end is zero.
+        let y = x << (65 - N);
+        let direction = y >> 63 == 1;
+        let regime = ((y >> 60) & 7) as u32;
+        let r = if direction { regime } else { 7 - regime };
+        let characteristic = (if r == 0 { 0 } else { (y << 4) >> (64 - r) }) as i64;
+        let c = if direction {
+            (1 << r) - 1 + characteristic
+        } else {
+            1 - (1 << (r + 1)) + characteristic
+        };
+        Decoded::Finite(Parts {
+            negative,
+            c,
+            frac: y << (4 + r),
+            sticky: false,
+        })
+    }
+
+    /// Rounds to a takum; None is zero.
+    fn encode(parts: Option<Parts>) -> Self {
+        let Some(p) = parts else {
+            return Self::ZERO;
+        };
+        let magnitude = if p.c > MAX_C {
+            Self::MAXPOS
+        } else if p.c < MIN_C {
+            Self::MINPOS
+        } else {
+            let (direction, r, characteristic) = if p.c >= 0 {
+                let r = (p.c + 1).ilog2();
+                (1, r, p.c - ((1 << r) - 1))
+            } else {
+                let r = (-p.c).ilog2();
+                (0, r, p.c + (1 << (r + 1)) - 1)
+            };
+            let regime = (if direction == 1 { r } else { 7 - r }) as u128;
+            // D, R, C and then the full mantissa: 4 + r + 64 bits, of which we keep N - 1.
+            let header = (((direction << 3) | regime) << r) | characteristic as u128;
+            let word = (header << 64) | p.frac as u128;
+            let shift = 4 + r + 64 - (N - 1);
+            let kept = (word >> shift) as u64;
+            let guard = (word >> (shift - 1)) & 1 == 1;
+            let lower = word & ((1 << (shift - 1)) - 1) != 0 || p.sticky;
+            let rounded = if guard && (lower || kept & 1 == 1) {
+                kept + 1
+            } else {
+                kept
+            };
+            rounded.clamp(Self::MINPOS, Self::MAXPOS)
+        };
+        if p.negative {
+            Self::from_bits(magnitude.wrapping_neg())
+        } else {
+            Self::from_bits(magnitude)
+        }
+    }
+
+    fn from_binary(negative: bool, exponent: i64, sig: u128, sticky: bool) -> Self {
+        if sig == 0 {
+            Self::ZERO
+        } else {
+            Self::encode(Some(V::from_binary(negative, exponent, sig, sticky)))
+        }
+    }
+
+    pub fn from_f64(f: f64) -> Self {
+        if f.is_nan() || f.is_infinite() {
+            return Self::NAR;
+        }
+        let bits = f.to_bits();
+        let biased = ((bits >> 52) & 0x7ff) as i64;
+        let fraction = bits & ((1 << 52) - 1);
+        let (sig, exponent) = if biased == 0 {
+            (fraction, -1074)
+        } else {
+            (fraction | (1 << 52), biased - 1075)
+        };
+        Self::from_binary(f < 0.0, exponent, sig as u128, false)
+    }
+
+    pub fn from_i32(i: i32) -> Self {
+        Self::from_binary(i < 0, 0, i.unsigned_abs() as u128, false)
+    }
+
+    pub fn to_f64(self) -> f64 {
+        match self.decode() {
+            Decoded::Zero => 0.0,
+            Decoded::NaR => f64::NAN,
+            Decoded::Finite(p) if p.negative => -V::magnitude(&p),
+            Decoded::Finite(p) => V::magnitude(&p),
+        }
+    }
+}
+
+impl<const N: u32, V: Variant> From<Takum<N, V>> for f64 {
+    fn from(t: Takum<N, V>) -> Self {
+        t.to_f64()
+    }
+}
+
+impl<const N: u32, V> PartialEq for Takum<N, V> {
+    fn eq(&self, other: &Self) -> bool {
+        self.bits == other.bits
+    }
+}
+
+impl<const N: u32, V: Variant> PartialOrd for Takum<N, V> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.signed().cmp(&other.signed()))
+    }
+}
+
+impl<const N: u32, V: Variant> Neg for Takum<N, V> {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        // Two's complement; zero and NaR are their own negations.
+        Self::from_bits(self.bits.wrapping_neg())
+    }
+}
+
+impl<const N: u32, V: Variant> Add for Takum<N, V> {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        match (self.decode(), other.decode()) {
+            (Decoded::NaR, _) | (_, Decoded::NaR) => Self::NAR,
+            (Decoded::Zero, _) => other,
+            (_, Decoded::Zero) => self,
+            (Decoded::Finite(a), Decoded::Finite(b)) => Self::encode(V::sum(a, b)),
+        }
+    }
+}
+
+impl<const N: u32, V: Variant> Sub for Takum<N, V> {
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self {
+        self + -other
+    }
+}
+
+impl<const N: u32, V: Variant> Mul for Takum<N, V> {
+    type Output = Self;
+
+    fn mul(self, other: Self) -> Self {
+        match (self.decode(), other.decode()) {
+            (Decoded::NaR, _) | (_, Decoded::NaR) => Self::NAR,
+            (Decoded::Zero, _) | (_, Decoded::Zero) => Self::ZERO,
+            (Decoded::Finite(a), Decoded::Finite(b)) => Self::encode(Some(V::product(a, b))),
+        }
+    }
+}
+
+impl<const N: u32, V: Variant> Div for Takum<N, V> {
+    type Output = Self;
+
+    fn div(self, other: Self) -> Self {
+        match (self.decode(), other.decode()) {
+            (Decoded::NaR, _) | (_, Decoded::NaR) | (_, Decoded::Zero) => Self::NAR,
+            (Decoded::Zero, _) => Self::ZERO,
+            (Decoded::Finite(a), Decoded::Finite(b)) => Self::encode(Some(V::quotient(a, b))),
+        }
+    }
+}
+
+impl<const N: u32, V: Variant> FromRational for Takum<N, V> {
+    fn from_bigrational(r: &BigRational) -> Result<Self, String> {
+        // Enough bits to round any takum up to 64 bits correctly:
+        let Some(parts) = binary_parts(r, 100) else {
+            return Ok(Self::ZERO);
+        };
+        let sig = parts
+            .significand
+            .to_u128()
+            .ok_or_else(|| format!("significand of {} out of range", r))?;
+        Ok(Self::from_binary(
+            parts.negative,
+            parts.exponent,
+            sig,
+            parts.sticky,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type Linear16 = Takum<16, Linear>;
+    type Log16 = Takum<16, Logarithmic>;
+
+    #[test]
+    fn test_encoding() {
+        // 1 is D=1, r=0, c=0; 2 is D=1, r=1, c=1; 1/2 is D=0, r=0, c=-1.
+        assert_eq!(Linear16::from_i32(1).to_bits(), 0x4000);
+        assert_eq!(Linear16::from_i32(2).to_bits(), 0x4800);
+        assert_eq!(Linear16::from_f64(0.5).to_bits(), 0x3800);
+        assert_eq!(Linear16::from_f64(1.5).to_bits(), 0x4400);
+        assert_eq!(Linear16::from_i32(-1).to_bits(), 0xc000);
+        assert_eq!(Log16::from_i32(1).to_bits(), 0x4000);
+        assert_eq!(Log16::from_bits(0x4800).to_f64(), 0.5f64.exp());
+        // The dynamic range doesn't depend on the width:
+        let max = Takum::<12, Linear>::from_bits(0x7ff).to_f64();
+        assert_eq!(max, 2f64.powi(254));
+        let max = Takum::<32, Linear>::from_bits(0x7fff_ffff).to_f64();
+        assert_eq!(max, 2f64.powi(255) * (1.0 - 2f64.powi(-21)));
+    }
+
+    #[test]
+    fn test_round_trip() {
+        fn check<V: Variant>() {
+            type T<V> = Takum<12, V>;
+            let mut last = f64::NEG_INFINITY;
+            // In two's-complement order, from the most negative value up:
+            for i in (1 << 11) + 1..(1 << 12) + (1 << 11) {
+                let t = T::<V>::from_bits(i);
+                let f = t.to_f64();
+                assert!(f > last, "{:#x} is out of order", t.to_bits());
+                assert_eq!(T::<V>::from_f64(f), t, "{f}");
+                last = f;
+            }
+        }
+        check::<Linear>();
+        check::<Logarithmic>();
+    }
+
+    #[test]
+    fn test_linear_is_correctly_rounded() {
+        // f64 has more than twice the precision of a 16-bit takum, so rounding an f64 result
+        // gives the correctly-rounded result.
+        for a in (0..1u64 << 16).step_by(97) {
+            for b in (0..1u64 << 16).step_by(89) {
+                let (ta, tb) = (Linear16::from_bits(a), Linear16::from_bits(b));
+                if ta.is_nar() || tb.is_nar() {
+                    continue;
+                }
+                let (fa, fb) = (ta.to_f64(), tb.to_f64());
+                assert_eq!(ta + tb, Linear16::from_f64(fa + fb), "{fa} + {fb}");
+                assert_eq!(ta - tb, Linear16::from_f64(fa - fb), "{fa} - {fb}");
+                assert_eq!(ta * tb, Linear16::from_f64(fa * fb), "{fa} * {fb}");
+                if fb != 0.0 {
+                    assert_eq!(ta / tb, Linear16::from_f64(fa / fb), "{fa} / {fb}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_logarithmic() {
+        for a in (0..1u64 << 16).step_by(97) {
+            for b in (0..1u64 << 16).step_by(89) {
+                let (ta, tb) = (Log16::from_bits(a), Log16::from_bits(b));
+                if ta.is_nar() || tb.is_nar() {
+                    continue;
+                }
+                let (fa, fb) = (ta.to_f64(), tb.to_f64());
+                assert_eq!(ta + tb, Log16::from_f64(fa + fb), "{fa} + {fb}");
+                assert_eq!(ta - tb, Log16::from_f64(fa - fb), "{fa} - {fb}");
+            }
+        }
+        // Products are exact, within range:
+        let (a, b) = (Log16::from_f64(0.3), Log16::from_f64(-7.0));
+        assert_eq!(a * b / b, a);
+        assert!(a * b < Log16::ZERO);
+    }
+
+    #[test]
+    fn test_saturation_and_nar() {
+        type T = Takum<12, Linear>;
+        let maxpos = T::from_bits(0x7ff);
+        let minpos = T::from_bits(1);
+        assert_eq!(maxpos * maxpos, maxpos);
+        assert_eq!(minpos * minpos, minpos);
+        assert_eq!(-minpos * minpos, -minpos);
+        assert_eq!(T::from_f64(1e300), maxpos);
+        assert!((T::from_i32(1) / T::ZERO).is_nar());
+        assert!((T::NAR + T::ZERO).is_nar());
+        assert!(T::NAR < -maxpos);
+        // Narrow takums cut the characteristic short:
+        type T8 = Takum<8, Linear>;
+        assert_eq!(T8::from_bits(0x7f).to_f64(), 2f64.powi(239));
+        assert_eq!(T8::from_f64(2f64.powi(200)).to_f64(), 2f64.powi(207));
+    }
+
+    #[test]
+    fn test_from_rational() {
+        let third = BigRational::new(1.into(), 3.into());
+        assert_eq!(
+            Takum::<32, Linear>::from_bigrational(&third).unwrap(),
+            Takum::<32, Linear>::from_f64(1.0 / 3.0)
+        );
+        let neg = BigRational::new((-7).into(), 2.into());
+        assert_eq!(
+            Takum::<24, Logarithmic>::from_bigrational(&neg).unwrap(),
+            Takum::<24, Logarithmic>::from_f64(-3.5)
+        );
+    }
+}
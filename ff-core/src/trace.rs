@@ -0,0 +1,247 @@
+//! Traces of a single point's orbit across formats, for explaining one odd pixel.
+//!
+//! Each format's orbit is compared against the "exact" orbit: the same iteration in a `BigFloat`
+//! with enough precision that its rounding stays well below any format's. (The truly exact orbit
+//! of the Mandelbrot iteration needs twice as many bits each step.)
+
+use std::panic::AssertUnwindSafe;
+
+use num::{BigRational, Signed};
+
+use crate::big_float::{BigFloat, BigFloatFormat};
+use crate::fixed_point::Overflow;
+use crate::number::{ConvertVisitor, FractalNumber};
+use crate::numeric::Complex;
+use crate::{mandelbrot, newton, registry, FractalParams};
+
+/// Bits of precision for the exact orbit, past the coordinates' own: enough for rounding errors
+/// to double on every iteration and still not show up in an f64.
+const GUARD_BITS: u64 = 64;
+
+/// One iteration of an orbit.
+#[derive(Clone, Debug)]
+pub struct Step {
+    /// The value of z, converted to f64.
+    pub z: (f64, f64),
+    /// The exact value of z; None if it's not finite (or, for intervals, not a single value).
+    pub exact: Option<(BigRational, BigRational)>,
+    /// The result of the escape (Mandelbrot) or convergence (Newton) test on this iteration;
+    /// None if the format couldn't decide. The orbit ends at the first step that isn't false.
+    pub test: Option<bool>,
+}
+
+/// A point's orbit in one format.
+#[derive(Clone, Debug)]
+pub struct Trace {
+    pub numeric: String,
+    pub steps: Vec<Step>,
+    /// The first iteration where the orbit is farther than the tolerance from the exact orbit,
+    /// or where only one of them ended.
+    pub departure: Option<usize>,
+    /// Why the orbit ended early, if it did (e.g. a fixed-point overflow).
+    pub error: Option<String>,
+}
+
+/// A point's exact orbit, and its orbit in each requested format.
+#[derive(Clone, Debug)]
+pub struct Orbits {
+    pub exact: Trace,
+    pub traces: Vec<Trace>,
+}
+
+/// Traces the orbit of the point (x, y) in each of the `formats`, with fused accumulation if
+/// `fused` (see `CommonParams::fused`).
+///
+/// Supports the formats `registry::visit_any` does.
+/// Mandelbrot orbits are iterated directly, whatever the requested method.
+pub fn trace(
+    x: &BigRational,
+    y: &BigRational,
+    fractal: &FractalParams,
+    formats: &[&str],
+    tolerance: f64,
+    fused: bool,
+) -> Result<Orbits, String> {
+    let tolerance = BigRational::from_float(tolerance)
+        .filter(|t| !t.is_negative())
+        .ok_or_else(|| format!("invalid tolerance {}", tolerance))?;
+    let iters = match *fractal {
+        FractalParams::Mandelbrot { iters, .. } => iters,
+        FractalParams::Newton { iters } => iters,
+    };
+    let bits = |r: &BigRational| r.numer().bits().max(r.denom().bits());
+    let precision = GUARD_BITS + 2 * iters as u64 + bits(x).max(bits(y));
+    let format = BigFloatFormat::new(precision.min(BigFloatFormat::MAX_PRECISION))?;
+    let exact = orbit(x, y, fractal, format.to_string(), false, |r| {
+        Ok(BigFloat::from_rational(r, format))
+    })?;
+
+    let traces = formats
+        .iter()
+        .map(|&numeric| {
+            let mut trace = trace_format(x, y, fractal, numeric, fused)?;
+            trace.departure = departure(&trace.steps, &exact.steps, &tolerance);
+            Ok(trace)
+        })
+        .collect::<Result<_, String>>()?;
+    Ok(Orbits { exact, traces })
+}
+
+fn trace_format(
+    x: &BigRational,
+    y: &BigRational,
+    fractal: &FractalParams,
+    numeric: &str,
+    fused: bool,
+) -> Result<Trace, String> {
+    let visitor = Orbit {
+        x,
+        y,
+        fractal,
+        numeric,
+        fused,
+    };
+    if let Some(result) = registry::visit_any(numeric, visitor) {
+        return result?;
+    }
+
+    Err(format!("can't trace numeric format {}", numeric))
+}
+
+/// Traces a format whose type is chosen at runtime.
+struct Orbit<'a> {
+    x: &'a BigRational,
+    y: &'a BigRational,
+    fractal: &'a FractalParams,
+    numeric: &'a str,
+    fused: bool,
+}
+
+impl ConvertVisitor for Orbit<'_> {
+    type Output = Result<Trace, String>;
+
+    fn visit<N: FractalNumber + Send + Sync + 'static>(
+        self,
+        convert: impl Fn(&BigRational) -> Result<N, String> + Send + Sync + 'static,
+    ) -> Self::Output {
+        orbit(
+            self.x,
+            self.y,
+            self.fractal,
+            self.numeric.to_string(),
+            self.fused,
+            convert,
+        )
+    }
+}
+
+/// Traces the orbit in N, using `convert` to produce the coordinates.
+fn orbit<N: FractalNumber>(
+    x: &BigRational,
+    y: &BigRational,
+    fractal: &FractalParams,
+    numeric: String,
+    fused: bool,
+    convert: impl Fn(&BigRational) -> Result<N, String>,
+) -> Result<Trace, String> {
+    let (x, y) = (convert(x)?, convert(y)?);
+    let mut steps = Vec::new();
+    let observe = |z: &Complex<N>, test: Option<bool>| {
+        steps.push(Step {
+            z: (z.re.clone().to_f64(), z.im.clone().to_f64()),
+            exact: z.re.to_rational().zip(z.im.to_rational()),
+            test,
+        })
+    };
+    // Fixed-point formats may panic on overflow; that ends the orbit.
+    let result = std::panic::catch_unwind(AssertUnwindSafe(|| match *fractal {
+        FractalParams::Mandelbrot { iters, .. } => {
+            mandelbrot::escape_observed(&x, &y, iters, fused, observe);
+        }
+        FractalParams::Newton { iters } => {
+            newton::find_zero_observed(&x, &y, iters, fused, observe);
+        }
+    }));
+    let error = result
+        .err()
+        .map(|panic| match panic.downcast::<Overflow>() {
+            Ok(overflow) => overflow.to_string(),
+            Err(_) => "caught panic while tracing".to_string(),
+        });
+    Ok(Trace {
+        numeric,
+        steps,
+        departure: None,
+        error,
+    })
+}
+
+/// The first iteration where the orbits are farther apart than the tolerance.
+fn departure(steps: &[Step], exact: &[Step], tolerance: &BigRational) -> Option<usize> {
+    let limit = tolerance * tolerance;
+    let apart = |step: &Step, exact: &Step| match (&step.exact, &exact.exact) {
+        (Some((re, im)), Some((exact_re, exact_im))) => {
+            let (dre, dim) = (re - exact_re, im - exact_im);
+            &dre * &dre + &dim * &dim > limit
+        }
+        _ => true,
+    };
+    steps
+        .iter()
+        .zip(exact)
+        .position(|(step, exact)| apart(step, exact))
+        .or_else(|| (steps.len() != exact.len()).then(|| steps.len().min(exact.len())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mandelbrot::Method;
+
+    fn rational(n: i64, d: i64) -> BigRational {
+        BigRational::new(n.into(), d.into())
+    }
+
+    #[test]
+    fn test_exact() {
+        // -1 is a 2-cycle: 0, -1, 0, -1, ...
+        let mandelbrot = FractalParams::Mandelbrot {
+            iters: 8,
+            method: Method::Direct,
+        };
+        let orbits = trace(
+            &rational(-1, 1),
+            &rational(0, 1),
+            &mandelbrot,
+            &["f32", "I11F5"],
+            0.0,
+            false,
+        )
+        .unwrap();
+        assert_eq!(orbits.exact.steps.len(), 8);
+        for (i, step) in orbits.exact.steps.iter().enumerate() {
+            let re = if i % 2 == 0 { -1 } else { 0 };
+            assert_eq!(step.z, (re as f64, 0.0));
+            assert_eq!(step.test, Some(false));
+        }
+        for trace in orbits.traces {
+            assert_eq!(trace.steps.len(), 8);
+            assert_eq!(trace.departure, None);
+            assert_eq!(trace.error, None);
+        }
+    }
+
+    #[test]
+    fn test_departure() {
+        let newton = FractalParams::Newton { iters: 32 };
+        let (x, y) = (rational(-1, 3), rational(2, 7));
+        let orbits = trace(&x, &y, &newton, &["f64", "FP8-E4M3"], 1e-9, false).unwrap();
+        assert_eq!(orbits.exact.steps.last().unwrap().test, Some(true));
+        let (f64_trace, fp8_trace) = (&orbits.traces[0], &orbits.traces[1]);
+        assert_eq!(f64_trace.departure, None);
+        // The coordinates themselves aren't representable in FP8.
+        assert_eq!(fp8_trace.departure, Some(0));
+
+        assert!(trace(&x, &y, &newton, &["f65"], 1e-9, false).is_err());
+    }
+}
@@ -1,7 +1,7 @@
 use std::time::Duration;
 
 use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
-use ff_core::{CommonParams, RenderRequest, Size};
+use ff_core::{CommonParams, RenderMode, RenderRequest, Size};
 use ff_render::RenderServer;
 use num::BigRational;
 
@@ -22,8 +22,14 @@ pub fn bench_multithread(c: &mut Criterion) {
             x: range.clone(),
             y: range.clone(),
             numeric: "".to_string(),
+            seed: None,
+            fused: false,
         },
-        fractal: ff_core::FractalParams::Mandelbrot { iters: 16 },
+        fractal: ff_core::FractalParams::Mandelbrot {
+            iters: 16,
+            method: Default::default(),
+        },
+        mode: RenderMode::Fractal,
     };
     // Count pixels:
     group.throughput(criterion::Throughput::Elements(
@@ -39,7 +45,7 @@ pub fn bench_multithread(c: &mut Criterion) {
     // Count up powers of two:
     let thread_range = (0..).map(|x| 1 << x).take_while({
         let x = num_cpus::get().next_power_of_two();
-        move |y| (*y <= x)
+        move |y| *y <= x
     });
     for threads in thread_range {
         let exec = RenderServer::with_threads(threads).unwrap();
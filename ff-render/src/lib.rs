@@ -13,8 +13,9 @@ use std::{
     sync::{mpsc::Receiver, Arc},
 };
 
-use ff_core::{CancelContext, RenderRequest};
-mod oneshot;
+use ff_core::mandelbrot::Method;
+use ff_core::{CancelContext, FractalParams, RenderMode, RenderRequest};
+pub mod oneshot;
 
 pub struct RenderServer {
     queue: std::sync::mpsc::Sender<ImageRequest>,
@@ -87,10 +88,12 @@ fn dispatch(pool: rayon::ThreadPool, receiver: Receiver<ImageRequest>) {
 fn render(req: ImageRequest) {
     let ImageRequest { request, result } = req;
     let res = match request.fractal {
-        ff_core::FractalParams::Mandelbrot { iters } => {
-            mandelbrot_render(&result, request.common, iters)
+        ff_core::FractalParams::Mandelbrot { iters, method } => {
+            mandelbrot_render(&result, request.common, iters, method, request.mode)
+        }
+        ff_core::FractalParams::Newton { iters } => {
+            newton_render(&result, request.common, iters, request.mode)
         }
-        ff_core::FractalParams::Newton { iters } => newton_render(&result, request.common, iters),
         _ => Err(Error::InvalidArgument("unknown fractal".to_owned())),
     };
     result.send(res);
@@ -100,19 +103,43 @@ fn mandelbrot_render(
     ctx: &dyn CancelContext,
     request: ff_core::CommonParams,
     iters: usize,
+    method: Method,
+    mode: RenderMode,
 ) -> Result<image::DynamicImage, Error> {
     tracing::info!("starting mandelbrot with format {}", request.numeric);
 
     let span = tracing::info_span!("render-mandelbrot");
     let _guard = span.enter();
-    let size = request.size.clone();
-
-    let output =
-        ff_core::mandelbrot::compute(ctx, &request, iters).map_err(|msg| Error::Internal(msg))?;
-    tracing::debug!("mandelbrot-computed");
-
-    let image = ff_core::image::Renderer {}
-        .render(size, output)
+    let size = request.size;
+
+    let image = match mode {
+        RenderMode::Fractal => {
+            let output = ff_core::mandelbrot::compute_with(ctx, &request, iters, method)
+                .map_err(Error::Internal)?;
+            tracing::debug!("mandelbrot-computed");
+            ff_core::image::Renderer {}.render(size, output)
+        }
+        RenderMode::Sensitivity { trials } => {
+            let output = ff_core::mandelbrot::sensitivity(ctx, &request, iters, method, trials)
+                .map_err(Error::Internal)?;
+            tracing::debug!("mandelbrot-computed");
+            ff_core::image::SensitivityRenderer {}.render(size, output)
+        }
+        RenderMode::Events { event } => {
+            let (_, events) = ff_core::mandelbrot::events(ctx, &request, iters, method)
+                .map_err(Error::Internal)?;
+            tracing::debug!("mandelbrot-computed");
+            ff_core::image::EventRenderer { event }.render(size, events)
+        }
+        RenderMode::Divergence { reference, measure } => {
+            let fractal = FractalParams::Mandelbrot { iters, method };
+            let output = ff_core::divergence::compare(ctx, &request, &fractal, &reference)
+                .map_err(Error::Internal)?;
+            tracing::debug!("mandelbrot-computed");
+            ff_core::image::DivergenceRenderer { measure }.render(size, output)
+        }
+    };
+    let image = image
         .map_err(|err| {
             tracing::error!("rendering error: {}", err);
             Error::Internal(format!("rendering error: {}", err))
@@ -126,18 +153,42 @@ fn newton_render(
     ctx: &dyn CancelContext,
     request: ff_core::CommonParams,
     iters: usize,
+    mode: RenderMode,
 ) -> Result<image::DynamicImage, Error> {
     tracing::info!("starting newton with format {}", request.numeric);
 
     let span = tracing::info_span!("render-newton");
     let _guard = span.enter();
-    let size = request.size.clone();
-
-    let output = ff_core::newton::compute(ctx, &request, iters).map_err(|msg| Error::Internal(msg))?;
-    tracing::debug!("newton-computed");
-
-    let image = ff_core::image::NewtonRenderer {}
-        .render(size, output)
+    let size = request.size;
+
+    let image = match mode {
+        RenderMode::Fractal => {
+            let output =
+                ff_core::newton::compute(ctx, &request, iters).map_err(Error::Internal)?;
+            tracing::debug!("newton-computed");
+            ff_core::image::NewtonRenderer {}.render(size, output)
+        }
+        RenderMode::Sensitivity { trials } => {
+            let output = ff_core::newton::sensitivity(ctx, &request, iters, trials)
+                .map_err(Error::Internal)?;
+            tracing::debug!("newton-computed");
+            ff_core::image::SensitivityRenderer {}.render(size, output)
+        }
+        RenderMode::Events { event } => {
+            let (_, events) =
+                ff_core::newton::events(ctx, &request, iters).map_err(Error::Internal)?;
+            tracing::debug!("newton-computed");
+            ff_core::image::EventRenderer { event }.render(size, events)
+        }
+        RenderMode::Divergence { reference, measure } => {
+            let fractal = FractalParams::Newton { iters };
+            let output = ff_core::divergence::compare(ctx, &request, &fractal, &reference)
+                .map_err(Error::Internal)?;
+            tracing::debug!("newton-computed");
+            ff_core::image::DivergenceRenderer { measure }.render(size, output)
+        }
+    };
+    let image = image
         .map_err(|err| {
             tracing::error!("rendering error: {}", err);
             Error::Internal(format!("rendering error: {}", err))
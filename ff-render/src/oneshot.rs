@@ -21,7 +21,7 @@ pub fn new<T>() -> (Sender<T>, Receiver<T>) {
         Sender {
             shared: shared.clone(),
         },
-        Receiver { shared: shared },
+        Receiver { shared },
     )
 }
 
@@ -37,11 +37,7 @@ impl<T> ff_core::CancelContext for Sender<T> where T: Send {
             Err(_) => return true,
             Ok(v) => v,
         };
-        if let State::ReceiverDropped = *g {
-            true
-        } else {
-            false
-        }
+        matches!(*g, State::ReceiverDropped)
     }
 }
 
@@ -0,0 +1,129 @@
+//! Machine-readable properties of numeric formats.
+use axum::{
+    extract::{Path, Query},
+    http::{header::CONTENT_TYPE, StatusCode},
+    response::IntoResponse,
+    routing::get,
+    Router,
+};
+use ff_core::{introspect, registry};
+use num::BigRational;
+use num_bigint::BigInt;
+
+/// Most values listed in one response.
+const MAX_VALUES: usize = 4096;
+
+pub fn router() -> Router {
+    Router::new().route("/:numeric", get(properties))
+}
+
+/// Query parameters for listing representable values.
+/// Values are listed when both `lo` and `hi` are present.
+#[derive(serde::Deserialize, Debug)]
+struct RangeParams {
+    /// Numerator of the (inclusive) start of the range.
+    lo: Option<String>,
+    /// Numerator of the (exclusive) end of the range.
+    hi: Option<String>,
+    /// Denominator for lo and hi. Defaults to 1.
+    scale: Option<String>,
+}
+
+impl RangeParams {
+    fn range(&self) -> Result<Option<std::ops::Range<BigRational>>, String> {
+        let (Some(lo), Some(hi)) = (&self.lo, &self.hi) else {
+            return Ok(None);
+        };
+        let parse = |s: &str| s.parse::<BigInt>().map_err(|err| format!("{}: {}", s, err));
+        let scale = match &self.scale {
+            Some(scale) => parse(scale)?,
+            None => 1.into(),
+        };
+        if scale == 0.into() {
+            return Err("scale must be nonzero".to_string());
+        }
+        Ok(Some(
+            BigRational::new(parse(lo)?, scale.clone())..BigRational::new(parse(hi)?, scale),
+        ))
+    }
+}
+
+#[derive(serde::Serialize)]
+struct Exclusion {
+    fractal: String,
+    reason: &'static str,
+}
+
+/// The JSON response. Rationals are exact, as "numerator/denominator" strings.
+#[derive(serde::Serialize)]
+struct FormatProperties {
+    name: &'static str,
+    family: String,
+    bits: u32,
+    exclusions: Vec<Exclusion>,
+    epsilon: Option<String>,
+    min_positive: Option<String>,
+    max_positive: Option<String>,
+    decades: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    values: Option<Vec<String>>,
+}
+
+async fn properties(
+    Path(numeric): Path<String>,
+    Query(params): Query<RangeParams>,
+) -> axum::response::Result<impl IntoResponse> {
+    if registry::find(&numeric).is_none() {
+        return Err((
+            StatusCode::NOT_FOUND,
+            format!("unknown numeric format {}", numeric),
+        )
+            .into());
+    }
+    let range = params
+        .range()
+        .map_err(|err| (StatusCode::BAD_REQUEST, err))?;
+    // Probing takes a few thousand conversions; keep it off the async workers.
+    let result = tokio::task::spawn_blocking(move || {
+        let properties = introspect::properties(&numeric)?;
+        let values = match range {
+            Some(range) => Some(introspect::representable(&numeric, &range, MAX_VALUES)?),
+            None => None,
+        };
+        Ok::<_, String>((properties, values))
+    })
+    .await
+    .map_err(|err| {
+        tracing::error!("introspection error: {}", err);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    // The format exists, so the error is in what was asked of it: a block format, or a range
+    // that's out of bounds or has too many values.
+    let (properties, values) = result.map_err(|err| (StatusCode::BAD_REQUEST, err))?;
+
+    let format = properties.format;
+    let to_string = |r: Option<BigRational>| r.map(|r| r.to_string());
+    let response = FormatProperties {
+        name: format.name,
+        family: format!("{:?}", format.family),
+        bits: format.bits,
+        exclusions: format
+            .exclusions
+            .iter()
+            .map(|(fractal, reason)| Exclusion {
+                fractal: format!("{:?}", fractal),
+                reason,
+            })
+            .collect(),
+        epsilon: to_string(properties.epsilon),
+        min_positive: to_string(properties.min_positive),
+        max_positive: to_string(properties.max_positive),
+        decades: properties.decades,
+        values: values.map(|values| values.iter().map(ToString::to_string).collect()),
+    };
+    let body = serde_json::to_string(&response).map_err(|err| {
+        tracing::error!("serialization error: {}", err);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    Ok(([(CONTENT_TYPE, "application/json")], body))
+}
@@ -6,6 +6,22 @@
 //! All dynamic paths take query parameters:
 //! - res: Integer width & height in pixels. (Rendering is always square.)
 //! - iter: Maximum number of iterations.
+//! - trials: If set, render a sensitivity map instead of the fractal: the variance of each
+//!   point's result across this many seeded trials (e.g. with an `MCA<f64,24>` format).
+//!   At most `MAX_TRIALS`.
+//! - seed: Seed for formats with random behavior; trials use consecutive seeds from it.
+//!   Defaults to 0.
+//! - events: If set, render a heatmap of how often each point hit the format's limits instead:
+//!   the count of the named event (e.g. `overflow`; see `ff_core::instrument::Event`),
+//!   or `all` for all exceptional events.
+//! - reference: If set, render where the fractal differs from the same render in this numeric
+//!   format instead (e.g. `f64`).
+//! - divergence: With `reference`, which difference to render: `classification` (the default),
+//!   `count`, or `root`.
+//! - perturbation: If true, compute Mandelbrot points as offsets from a high-precision
+//!   reference orbit, for zooms deeper than the format can represent directly.
+//! - fused: If true, accumulate complex products with a posit quire or FMA, in the formats that
+//!   have one.
 //!
 //! - window: Numerator for window width/height. Defaults to 4.
 //! - x: Numerator of X offset of upper-left corner. Defaults to -2.
@@ -15,19 +31,35 @@
 //! Dynamic paths are:
 //! - `/`: HTML interface view. View parameters are filled by query params.
 //! - `/render/:fractal/:numeric`: Render the given fractal using the given numeric format, in the query-provided window.
+//! - `/formats/:numeric`: JSON properties of the numeric format: bit width, epsilon near 1, range.
+//!   With `lo`, `hi`, and `scale` query parameters (numerators and denominator, as for the window),
+//!   also lists the representable values in `[lo, hi)`.
+//! - `/search/:fractal/:numeric`: JSON list of the windows where the format differs most from the
+//!   `reference` format, each with a link to view it. The query-provided window is the region to
+//!   search, scanned at `res`; `split`, `depth`, `beam`, and `top` tune the search
+//!   (see `ff_core::search::SearchParams`). Each of these, and `res`, has an upper bound.
+//! - `/trace/:fractal`: JSON orbit of the point at the center of the query-provided window, in each
+//!   of the comma-separated `formats` (default: all listed non-block formats) and at high
+//!   precision, with the iteration where each format's orbit departs from the exact one by more
+//!   than `tolerance`.
+//! - `/trace/:fractal/svg`: The same orbits, plotted.
 //!
 //! Static paths are:
 //! - `/static/...`: Serve the provided static content (JS, CSS)
 use axum::{routing::get, Router};
-use ff_core::{CommonParams, FractalParams, RenderRequest, Size};
+use ff_core::mandelbrot::Method;
+use ff_core::{CommonParams, FractalParams, RenderMode, RenderRequest, Size};
 use num::BigRational;
 use num_bigint::BigInt;
 use serde::de::{Deserialize, Deserializer};
 
+mod formats;
 mod mandelbrot;
 mod newton;
 mod render;
+mod search;
 mod static_content;
+mod trace;
 
 pub fn root_routes() -> Result<axum::Router, String> {
     tracing::info!("constructing router");
@@ -38,15 +70,35 @@ pub fn root_routes() -> Result<axum::Router, String> {
             mandelbrot::router(ff_render::RenderServer::new()?),
         )
         .nest("/newton/", newton::router(ff_render::RenderServer::new()?))
+        .nest("/formats/", formats::router())
+        .nest("/search/", search::router())
+        .nest("/trace/", trace::router())
         .route("/static/:file", get(static_content::get)))
 }
 
+/// The most trials a sensitivity map may take; each is a full render.
+const MAX_TRIALS: usize = 64;
+
 #[derive(serde::Deserialize, Debug, Clone)]
 struct WindowParams {
     #[serde(default = "WindowParams::default_res")]
     res: usize,
     #[serde(default = "WindowParams::default_iters")]
     iters: usize,
+    #[serde(default)]
+    trials: Option<usize>,
+    #[serde(default)]
+    seed: Option<u64>,
+    #[serde(default)]
+    events: Option<String>,
+    #[serde(default)]
+    reference: Option<String>,
+    #[serde(default)]
+    divergence: Option<String>,
+    #[serde(default)]
+    perturbation: bool,
+    #[serde(default)]
+    fused: bool,
 
     #[serde(
         default = "WindowParams::default_window",
@@ -71,7 +123,7 @@ struct WindowParams {
 }
 
 impl WindowParams {
-    fn to_request(self, fractal: &str, numeric: String) -> Result<RenderRequest, String> {
+    fn into_request(self, fractal: &str, numeric: String) -> Result<RenderRequest, String> {
         // Web request uses center; internals use a window.
         // Compute the window.
         let half_range = self.window / 2;
@@ -90,13 +142,47 @@ impl WindowParams {
             x: range(&self.x),
             y: range(&self.y),
             numeric,
+            seed: self.seed,
+            fused: self.fused,
         };
         let fractal = match fractal {
-            "mandelbrot" => Ok(FractalParams::Mandelbrot { iters: self.iters }),
+            "mandelbrot" => Ok(FractalParams::Mandelbrot {
+                iters: self.iters,
+                method: if self.perturbation {
+                    Method::Perturbation
+                } else {
+                    Method::Direct
+                },
+            }),
             "newton" => Ok(FractalParams::Newton { iters: self.iters }),
             v => Err(format!("unknown fractal '{}'", v)),
         }?;
-        Ok(RenderRequest { common, fractal })
+        let mode = match (self.trials, self.events, self.reference) {
+            (Some(trials), None, None) if !(1..=MAX_TRIALS).contains(&trials) => Err(format!(
+                "trials must be between 1 and {}, not {}",
+                MAX_TRIALS, trials
+            )),
+            (Some(trials), None, None) => Ok(RenderMode::Sensitivity { trials }),
+            (None, Some(events), None) if events == "all" => Ok(RenderMode::Events { event: None }),
+            (None, Some(events), None) => Ok(RenderMode::Events {
+                event: Some(events.parse()?),
+            }),
+            (None, None, Some(reference)) => Ok(RenderMode::Divergence {
+                reference,
+                measure: self
+                    .divergence
+                    .map(|measure| measure.parse())
+                    .transpose()?
+                    .unwrap_or_default(),
+            }),
+            (None, None, None) => Ok(RenderMode::Fractal),
+            _ => Err("only one of trials, events, and reference can be set".to_string()),
+        }?;
+        Ok(RenderRequest {
+            common,
+            fractal,
+            mode,
+        })
     }
 }
 
@@ -18,7 +18,7 @@ pub fn router(render_server: RenderServer) -> Router {
         "/render/:numeric",
         get(
             |Path(numeric), Query(window_params): Query<WindowParams>| async move {
-                let request = window_params.to_request("newton", numeric)?;
+                let request = window_params.into_request("newton", numeric)?;
                 crate::render::render(&srv, request).await
             },
         ),
@@ -100,6 +100,10 @@ fn interface_body(query_str: &str, query: &WindowParams) -> Markup {
                 label { "Max iterations:" }
                 input name="iters" type="number" value=(query.iters);
                 " "
+
+                label { "Fused:" }
+                input name="fused" type="checkbox" value="true" checked[query.fused];
+                " "
                 br;
             }
             input text="Go" type="submit";
@@ -0,0 +1,180 @@
+//! Searching for farlands, with links to view what's found.
+use axum::{
+    extract::{Path, Query},
+    http::{header::CONTENT_TYPE, StatusCode},
+    response::IntoResponse,
+    routing::get,
+    Router,
+};
+use ff_core::divergence::Measure;
+use ff_core::mandelbrot::Method;
+use ff_core::search::{Farland, SearchParams};
+use ff_core::{FractalParams, RenderMode};
+use num::{BigRational, Integer};
+
+use crate::WindowParams;
+
+pub fn router() -> Router {
+    Router::new().route("/:fractal/:numeric", get(search))
+}
+
+/// Largest accepted values of the search parameters.
+/// A search renders up to `1 + (depth - 1) * beam` windows, each at `res` pixels square.
+const MAX_SPLIT: usize = 16;
+const MAX_DEPTH: usize = 16;
+const MAX_BEAM: usize = 8;
+const MAX_TOP: usize = 64;
+const MAX_RES: usize = 512;
+
+/// Query parameters for tuning the search; see `SearchParams`.
+#[derive(serde::Deserialize, Debug)]
+struct SearchQuery {
+    split: Option<usize>,
+    depth: Option<usize>,
+    beam: Option<usize>,
+    top: Option<usize>,
+}
+
+impl SearchQuery {
+    fn params(&self, measure: Measure) -> Result<SearchParams, String> {
+        let defaults = SearchParams::default();
+        let bounded = |name: &str, value: Option<usize>, default: usize, max: usize| {
+            let value = value.unwrap_or(default);
+            if (1..=max).contains(&value) {
+                Ok(value)
+            } else {
+                Err(format!(
+                    "{} must be between 1 and {}, not {}",
+                    name, max, value
+                ))
+            }
+        };
+        Ok(SearchParams {
+            measure,
+            split: bounded("split", self.split, defaults.split, MAX_SPLIT)?,
+            depth: bounded("depth", self.depth, defaults.depth, MAX_DEPTH)?,
+            beam: bounded("beam", self.beam, defaults.beam, MAX_BEAM)?,
+            top: bounded("top", self.top, defaults.top, MAX_TOP)?,
+        })
+    }
+}
+
+/// One window in the JSON response. Bounds are exact, as "numerator/denominator" strings.
+#[derive(serde::Serialize)]
+struct Found {
+    score: f64,
+    depth: usize,
+    x: [String; 2],
+    y: [String; 2],
+    /// The interface view of the window, showing the divergence from the reference.
+    url: String,
+}
+
+async fn search(
+    Path((fractal, numeric)): Path<(String, String)>,
+    Query(window): Query<WindowParams>,
+    Query(query): Query<SearchQuery>,
+) -> axum::response::Result<impl IntoResponse> {
+    if window.res > MAX_RES {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("res must be at most {}, not {}", MAX_RES, window.res),
+        )
+            .into());
+    }
+    let view = window.clone();
+    let request = window
+        .into_request(&fractal, numeric)
+        .map_err(|err| (StatusCode::BAD_REQUEST, err))?;
+    let RenderMode::Divergence { reference, measure } = request.mode else {
+        return Err((StatusCode::BAD_REQUEST, "reference must be set").into());
+    };
+    let params = query
+        .params(measure)
+        .map_err(|err| (StatusCode::BAD_REQUEST, err))?;
+
+    // Each level renders several windows; keep it off the async workers.
+    // If the client hangs up, the receiver is dropped, which cancels the search.
+    let (sender, receiver) = ff_render::oneshot::new();
+    tokio::task::spawn_blocking(move || {
+        let found = ff_core::search::search(
+            &sender,
+            &request.common,
+            &request.fractal,
+            &reference,
+            &params,
+        );
+        sender.send(found.map(|found| (request.fractal, found)));
+    });
+    let result = receiver.await.map_err(|err| {
+        tracing::error!("search error: {}", err);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    let (fractal, found) = result.map_err(|err| (StatusCode::BAD_REQUEST, err))?;
+
+    let response = found
+        .iter()
+        .map(|farland| {
+            Ok(Found {
+                score: farland.score,
+                depth: farland.depth,
+                x: [farland.x.start.to_string(), farland.x.end.to_string()],
+                y: [farland.y.start.to_string(), farland.y.end.to_string()],
+                url: url(&fractal, farland, &view)?,
+            })
+        })
+        .collect::<Result<Vec<_>, serde_urlencoded::ser::Error>>()
+        .map_err(|err| {
+            tracing::error!("serialization error: {}", err);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    let body = serde_json::to_string(&response).map_err(|err| {
+        tracing::error!("serialization error: {}", err);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    Ok(([(CONTENT_TYPE, "application/json")], body))
+}
+
+/// Link to the interface for the (square) window, with the view settings of the search.
+fn url(
+    fractal: &FractalParams,
+    farland: &Farland,
+    view: &WindowParams,
+) -> Result<String, serde_urlencoded::ser::Error> {
+    let two = BigRational::from_integer(2.into());
+    let x = (&farland.x.start + &farland.x.end) / &two;
+    let y = (&farland.y.start + &farland.y.end) / &two;
+    let window = &farland.x.end - &farland.x.start;
+    // The interface takes numerators over a common denominator,
+    // and halves the window's numerator with integer division.
+    let scale = [&x, &y, &(&window / &two)]
+        .into_iter()
+        .fold(1.into(), |scale, v| v.denom().lcm(&scale));
+    let numerator = |v: &BigRational| (v * &scale).to_integer().to_string();
+
+    let mut pairs = vec![
+        ("x", numerator(&x)),
+        ("y", numerator(&y)),
+        ("window", numerator(&window)),
+        ("scale", scale.to_string()),
+        ("iters", view.iters.to_string()),
+        ("res", view.res.to_string()),
+    ];
+    if let FractalParams::Mandelbrot {
+        method: Method::Perturbation,
+        ..
+    } = fractal
+    {
+        pairs.push(("perturbation", "true".to_string()));
+    }
+    if view.fused {
+        pairs.push(("fused", "true".to_string()));
+    }
+    pairs.extend(view.reference.clone().map(|r| ("reference", r)));
+    pairs.extend(view.divergence.clone().map(|d| ("divergence", d)));
+    Ok(format!(
+        "/{}/?{}",
+        fractal.name(),
+        serde_urlencoded::to_string(pairs)?
+    ))
+}
@@ -3,7 +3,7 @@ use axum::http::{header, HeaderName, StatusCode};
 use axum::response::{IntoResponse, Result};
 
 pub async fn get_index() -> StaticResponse {
-    const INDEX: &'static str = include_str!("static/index.html");
+    const INDEX: &str = include_str!("static/index.html");
     (headers("text/html"), INDEX)
 }
 
@@ -24,11 +24,11 @@ const fn headers(content_type: &'static str) -> StaticHeaders {
 type StaticResponse = (StaticHeaders, &'static str);
 
 fn get_style() -> StaticResponse {
-    const STYLE: &'static str = include_str!("static/style.css");
+    const STYLE: &str = include_str!("static/style.css");
     (headers("text/css"), STYLE)
 }
 
 fn get_app() -> StaticResponse {
-    const APP: &'static str = include_str!("static/app.js");
+    const APP: &str = include_str!("static/app.js");
     (headers("text/javascript"), APP)
 }
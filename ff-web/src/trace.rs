@@ -0,0 +1,274 @@
+//! Orbits of a single point across formats, as JSON or as an SVG plot.
+use std::fmt::Write;
+
+use axum::{
+    extract::{Path, Query},
+    http::{header::CONTENT_TYPE, StatusCode},
+    response::IntoResponse,
+    routing::get,
+    Router,
+};
+use ff_core::registry::{self, Evaluation, Fractal};
+use ff_core::trace::{Orbits, Step, Trace};
+use ff_core::FractalParams;
+use num::BigRational;
+
+use crate::WindowParams;
+
+/// Default distance from the exact orbit at which a format's orbit has departed.
+const DEFAULT_TOLERANCE: f64 = 1e-6;
+
+/// Largest coordinate plotted; escaped Mandelbrot orbits head off to infinity.
+const PLOT_LIMIT: f64 = 8.0;
+
+/// Colors for each format's orbit; the exact orbit is black.
+const PALETTE: [&str; 8] = [
+    "#e41a1c", "#377eb8", "#4daf4a", "#984ea3", "#ff7f00", "#a65628", "#f781bf", "#999999",
+];
+
+pub fn router() -> Router {
+    Router::new()
+        .route("/:fractal", get(json))
+        .route("/:fractal/svg", get(svg))
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct TraceQuery {
+    /// Formats to trace, separated by commas (outside of angle brackets, as in `Posit<8,2>`).
+    /// Defaults to all the fractal's listed formats, other than block formats.
+    formats: Option<String>,
+    tolerance: Option<f64>,
+}
+
+/// Traces the point at the center of the query window.
+async fn orbits(
+    fractal: String,
+    window: WindowParams,
+    query: TraceQuery,
+) -> axum::response::Result<Orbits> {
+    let request = window
+        .into_request(&fractal, "f64".to_string())
+        .map_err(|err| (StatusCode::BAD_REQUEST, err))?;
+    let formats: Vec<String> = match &query.formats {
+        Some(formats) => split_formats(formats),
+        None => {
+            let fractal = match request.fractal {
+                FractalParams::Newton { .. } => Fractal::Newton,
+                _ => Fractal::Mandelbrot,
+            };
+            registry::listed(fractal)
+                .filter(|format| format.evaluation == Evaluation::Number)
+                .map(|format| format.name.to_string())
+                .collect()
+        }
+    };
+    let tolerance = query.tolerance.unwrap_or(DEFAULT_TOLERANCE);
+
+    // Wide formats (and the exact orbit) can be slow; keep them off the async workers.
+    let result = tokio::task::spawn_blocking(move || {
+        let two = BigRational::from_integer(2.into());
+        let x = (&request.common.x.start + &request.common.x.end) / &two;
+        let y = (&request.common.y.start + &request.common.y.end) / &two;
+        let formats: Vec<&str> = formats.iter().map(String::as_str).collect();
+        ff_core::trace::trace(
+            &x,
+            &y,
+            &request.fractal,
+            &formats,
+            tolerance,
+            request.common.fused,
+        )
+    })
+    .await
+    .map_err(|err| {
+        tracing::error!("trace error: {}", err);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    Ok(result.map_err(|err| (StatusCode::BAD_REQUEST, err))?)
+}
+
+/// Splits a list of format names on the commas between them.
+fn split_formats(list: &str) -> Vec<String> {
+    let mut formats = vec![String::new()];
+    let mut depth = 0;
+    for c in list.chars() {
+        match c {
+            '<' => depth += 1,
+            '>' => depth -= 1,
+            ',' if depth == 0 => {
+                formats.push(String::new());
+                continue;
+            }
+            _ => (),
+        }
+        formats.last_mut().expect("nonempty").push(c);
+    }
+    formats
+        .into_iter()
+        .map(|format| format.trim().to_string())
+        .filter(|format| !format.is_empty())
+        .collect()
+}
+
+/// One step in the JSON response. Exact values are "numerator/denominator" strings;
+/// non-finite values of z are null.
+#[derive(serde::Serialize)]
+struct StepJson {
+    z: (f64, f64),
+    exact: Option<(String, String)>,
+    test: Option<bool>,
+}
+
+#[derive(serde::Serialize)]
+struct TraceJson {
+    numeric: String,
+    steps: Vec<StepJson>,
+    departure: Option<usize>,
+    error: Option<String>,
+}
+
+#[derive(serde::Serialize)]
+struct OrbitsJson {
+    exact: TraceJson,
+    traces: Vec<TraceJson>,
+}
+
+impl From<Trace> for TraceJson {
+    fn from(trace: Trace) -> Self {
+        TraceJson {
+            numeric: trace.numeric,
+            steps: trace
+                .steps
+                .into_iter()
+                .map(|step| StepJson {
+                    z: step.z,
+                    exact: step.exact.map(|(re, im)| (re.to_string(), im.to_string())),
+                    test: step.test,
+                })
+                .collect(),
+            departure: trace.departure,
+            error: trace.error,
+        }
+    }
+}
+
+async fn json(
+    Path(fractal): Path<String>,
+    Query(window): Query<WindowParams>,
+    Query(query): Query<TraceQuery>,
+) -> axum::response::Result<impl IntoResponse> {
+    let orbits = orbits(fractal, window, query).await?;
+    let response = OrbitsJson {
+        exact: orbits.exact.into(),
+        traces: orbits.traces.into_iter().map(Into::into).collect(),
+    };
+    let body = serde_json::to_string(&response).map_err(|err| {
+        tracing::error!("serialization error: {}", err);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    Ok(([(CONTENT_TYPE, "application/json")], body))
+}
+
+async fn svg(
+    Path(fractal): Path<String>,
+    Query(window): Query<WindowParams>,
+    Query(query): Query<TraceQuery>,
+) -> axum::response::Result<impl IntoResponse> {
+    let orbits = orbits(fractal, window, query).await?;
+    Ok(([(CONTENT_TYPE, "image/svg+xml")], plot(&orbits)))
+}
+
+/// Plots the orbits overlaid on the complex plane, with each format's departure circled.
+///
+/// As in the renders, y increases downward.
+fn plot(orbits: &Orbits) -> String {
+    const SIZE: f64 = 512.0;
+    const MARGIN: f64 = 16.0;
+    let plotted = |step: &Step| {
+        let (re, im) = step.z;
+        (re.abs() <= PLOT_LIMIT && im.abs() <= PLOT_LIMIT).then_some((re, im))
+    };
+    let traces = || std::iter::once(&orbits.exact).chain(&orbits.traces);
+
+    // Fit the plot to the points, keeping the axes' scales equal.
+    let points: Vec<(f64, f64)> = traces()
+        .flat_map(|trace| trace.steps.iter().filter_map(plotted))
+        .collect();
+    let bound = |coord: fn(&(f64, f64)) -> f64| {
+        let lo = points.iter().map(coord).fold(f64::INFINITY, f64::min);
+        let hi = points.iter().map(coord).fold(f64::NEG_INFINITY, f64::max);
+        if lo <= hi {
+            (lo, hi)
+        } else {
+            (-2.0, 2.0)
+        }
+    };
+    let ((x0, x1), (y0, y1)) = (bound(|p| p.0), bound(|p| p.1));
+    let extent = (x1 - x0).max(y1 - y0).max(1e-9);
+    let scale = (SIZE - 2.0 * MARGIN) / extent;
+    let position = |(re, im): (f64, f64)| (MARGIN + (re - x0) * scale, MARGIN + (im - y0) * scale);
+
+    let mut svg = String::new();
+    let height = SIZE + 16.0 * (orbits.traces.len() + 1) as f64;
+    // Writing to a String can't fail.
+    let _ = writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{SIZE}" height="{height}" font-family="monospace" font-size="12">"#
+    );
+    let _ = writeln!(svg, r#"<rect width="100%" height="100%" fill="white"/>"#);
+    for (i, trace) in traces().enumerate() {
+        let color = match i {
+            0 => "black",
+            i => PALETTE[(i - 1) % PALETTE.len()],
+        };
+        let path: Vec<String> = trace
+            .steps
+            .iter()
+            .filter_map(plotted)
+            .map(|point| {
+                let (x, y) = position(point);
+                format!("{:.2},{:.2}", x, y)
+            })
+            .collect();
+        let _ = writeln!(
+            svg,
+            r#"<polyline points="{}" fill="none" stroke="{}" stroke-opacity="0.7"/>"#,
+            path.join(" "),
+            color
+        );
+        if let Some((x, y)) = trace
+            .departure
+            .and_then(|i| trace.steps.get(i))
+            .and_then(plotted)
+            .map(position)
+        {
+            let _ = writeln!(
+                svg,
+                r#"<circle cx="{:.2}" cy="{:.2}" r="5" fill="none" stroke="{}"/>"#,
+                x, y, color
+            );
+        }
+        let label = match (i, trace.departure) {
+            (0, _) => format!("{} (exact)", trace.numeric),
+            (_, Some(departure)) => format!("{}: departs at {}", trace.numeric, departure),
+            (_, None) => format!("{}: within tolerance", trace.numeric),
+        };
+        let _ = writeln!(
+            svg,
+            r#"<text x="{}" y="{}" fill="{}">{}</text>"#,
+            MARGIN,
+            SIZE + 16.0 * i as f64 + 12.0,
+            color,
+            escape(&label)
+        );
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
+
+/// Escapes text for XML: format names have angle brackets.
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}